@@ -1,24 +1,41 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, response::IntoResponse, Json};
 use register::RegistrationKind;
 use ruma::{
 	api::client::{
 		account::{
-			change_password, deactivate, get_3pids, get_username_availability,
+			add_3pid, change_password, deactivate, delete_3pid, get_3pids, get_username_availability,
 			register::{self, LoginType},
-			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn, whoami,
+			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
+			request_password_change_token_via_email, request_registration_token_via_email, whoami,
 			ThirdPartyIdRemovalStatus,
 		},
 		error::ErrorKind,
 		uiaa::{AuthFlow, AuthType, UiaaInfo},
 	},
-	events::{room::message::RoomMessageEventContent, GlobalAccountDataEventType},
-	push, UserId,
+	events::{
+		room::{message::RoomMessageEventContent, redaction::RoomRedactionEventContent},
+		GlobalAccountDataEventType, TimelineEventType,
+	},
+	push,
+	thirdparty::Medium,
+	UserId,
 };
+use serde::Deserialize;
+use serde_json::value::to_raw_value;
 use tracing::{error, info, warn};
 
 use super::{DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::{
 	api::client_server::{self, join_room_by_id_helper},
-	service, services,
+	service::{
+		self,
+		event_policy::PolicyCheck,
+		pdu::PduBuilder,
+		threepid::{self, EmailPurpose},
+	},
+	services,
 	utils::{self, user_id::user_is_local},
 	Error, Result, Ruma,
 };
@@ -83,6 +100,10 @@ pub(crate) async fn get_register_available_route(
 /// - Creates a new account and populates it with default account data
 /// - If `inhibit_login` is false: Creates a device and returns device id and
 ///   access_token
+/// - If `refresh_token` is true: Also returns a refresh token, and the
+///   access_token expires after the configured `access_token_ttl`
+/// - If `registration_requires_email` is enabled: Requires a validated email
+///   address as the last UIAA stage and binds it to the new account
 #[allow(clippy::doc_markdown)]
 pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<register::v3::Response> {
 	if !services().globals.allow_registration() && body.appservice_info.is_none() {
@@ -197,9 +218,14 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 		skip_auth = body.appservice_info.is_some() || is_guest;
 	}
 
+	if services().globals.config.registration_requires_email {
+		uiaainfo.flows[0].stages.push(AuthType::EmailIdentity);
+	}
+
+	let mut validated_email = None;
 	if !skip_auth {
 		if let Some(auth) = &body.auth {
-			let (worked, uiaainfo) = services().uiaa.try_auth(
+			let (worked, uiaainfo, email) = services().uiaa.try_auth_email(
 				&UserId::parse_with_server_name("", services().globals.server_name()).expect("we know this is valid"),
 				"".into(),
 				auth,
@@ -208,6 +234,7 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 			if !worked {
 				return Err(Error::Uiaa(uiaainfo));
 			}
+			validated_email = email;
 		// Success!
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
@@ -223,6 +250,37 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 		}
 	}
 
+	let email = if services().globals.config.registration_requires_email && !skip_auth {
+		let Some((email, validated_at)) = validated_email else {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"Email address has not been validated.",
+			));
+		};
+
+		if services()
+			.threepid
+			.find_user_by_threepid(&Medium::Email, &email)?
+			.is_some()
+		{
+			return Err(Error::BadRequest(ErrorKind::ThreepidInUse, "Email address is already in use."));
+		}
+
+		Some((email, validated_at))
+	} else {
+		None
+	};
+
+	if body.appservice_info.is_none() {
+		services()
+			.event_policy
+			.enforce(&PolicyCheck::Registration {
+				username: user_id.localpart(),
+				guest: is_guest,
+			})
+			.await?;
+	}
+
 	let password = if is_guest {
 		None
 	} else {
@@ -232,6 +290,12 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 	// Create user
 	services().users.create(&user_id, password)?;
 
+	if let Some((email, validated_at)) = email {
+		services()
+			.threepid
+			.add_email(&user_id, &email, validated_at)?;
+	}
+
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
 
@@ -286,6 +350,19 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 		.users
 		.create_device(&user_id, &device_id, &token, body.initial_device_display_name.clone())?;
 
+	// Issue a refresh token and let the access token expire if the client supports
+	// it
+	let (refresh_token, expires_in) = if body.refresh_token {
+		let refresh_token = utils::random_string(TOKEN_LENGTH);
+		let expires_in = services()
+			.users
+			.set_refreshable_token(&user_id, &device_id, &token, &refresh_token)?;
+
+		(Some(refresh_token), Some(expires_in))
+	} else {
+		(None, None)
+	};
+
 	info!("New user \"{}\" registered on this server.", user_id);
 
 	// log in conduit admin channel if a non-guest user registered
@@ -388,8 +465,8 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 		access_token: Some(token),
 		user_id,
 		device_id: Some(device_id),
-		refresh_token: None,
-		expires_in: None,
+		refresh_token,
+		expires_in,
 	})
 }
 
@@ -410,10 +487,15 @@ pub(crate) async fn register_route(body: Ruma<register::v3::Request>) -> Result<
 ///   last seen ts)
 /// - Forgets to-device events
 /// - Triggers device list updates
+///
+/// Without an access token, the password is reset for the account the email
+/// address validated through UIAA is bound to.
 pub(crate) async fn change_password_route(
 	body: Ruma<change_password::v3::Request>,
 ) -> Result<change_password::v3::Response> {
-	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let Some(sender_user) = body.sender_user.as_ref() else {
+		return reset_password_route(body).await;
+	};
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	let mut uiaainfo = UiaaInfo {
@@ -471,6 +553,89 @@ pub(crate) async fn change_password_route(
 	Ok(change_password::v3::Response {})
 }
 
+/// Resets the password of the account bound to the email address validated
+/// through UIAA, and logs out all of its devices if logout_devices is true.
+async fn reset_password_route(body: Ruma<change_password::v3::Request>) -> Result<change_password::v3::Response> {
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow {
+			stages: vec![AuthType::EmailIdentity],
+		}],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	let validated_email;
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo, email) = services().uiaa.try_auth_email(
+			&UserId::parse_with_server_name("", services().globals.server_name()).expect("we know this is valid"),
+			"".into(),
+			auth,
+			&uiaainfo,
+		)?;
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+		validated_email = email;
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services().uiaa.create(
+			&UserId::parse_with_server_name("", services().globals.server_name()).expect("we know this is valid"),
+			"".into(),
+			&uiaainfo,
+			&json,
+		)?;
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let Some((email, _)) = validated_email else {
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidAuthFailed,
+			"Email address has not been validated.",
+		));
+	};
+
+	let user_id = services()
+		.threepid
+		.find_user_by_threepid(&Medium::Email, &email)?
+		.ok_or(Error::BadRequest(
+			ErrorKind::ThreepidNotFound,
+			"Email address is not bound to an account.",
+		))?;
+
+	if services().users.is_deactivated(&user_id)? {
+		return Err(Error::BadRequest(ErrorKind::UserDeactivated, "The user has been deactivated"));
+	}
+
+	services()
+		.users
+		.set_password(&user_id, Some(&body.new_password))?;
+
+	if body.logout_devices {
+		for id in services()
+			.users
+			.all_device_ids(&user_id)
+			.filter_map(Result::ok)
+		{
+			services().users.remove_device(&user_id, &id)?;
+		}
+	}
+
+	info!("User {} reset their password.", user_id);
+	services()
+		.admin
+		.send_message(RoomMessageEventContent::notice_plain(format!(
+			"User {user_id} reset their password."
+		)))
+		.await;
+
+	Ok(change_password::v3::Response {})
+}
+
 /// # `GET _matrix/client/r0/account/whoami`
 ///
 /// Get `user_id` of the sender user.
@@ -498,6 +663,7 @@ pub(crate) async fn whoami_route(body: Ruma<whoami::v3::Request>) -> Result<whoa
 /// - Forgets all to-device events
 /// - Triggers device list updates
 /// - Removes ability to log in again
+/// - If `erase` is true: erases the account data first (see [`erase_account`])
 pub(crate) async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Result<deactivate::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
@@ -530,6 +696,11 @@ pub(crate) async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Res
 		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
 	}
 
+	// Redact the user's events while they can still send redactions
+	if body.erase {
+		erase_account(sender_user).await?;
+	}
+
 	// Make the user leave all rooms before deactivation
 	client_server::leave_all_rooms(sender_user).await?;
 
@@ -540,7 +711,12 @@ pub(crate) async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Res
 	services()
 		.admin
 		.send_message(RoomMessageEventContent::notice_plain(format!(
-			"User {sender_user} deactivated their account."
+			"User {sender_user} deactivated their account{}.",
+			if body.erase {
+				" and requested erasure"
+			} else {
+				""
+			}
 		)))
 		.await;
 
@@ -549,15 +725,224 @@ pub(crate) async fn deactivate_route(body: Ruma<deactivate::v3::Request>) -> Res
 	})
 }
 
+/// Erases a local user's data, as requested with `erase` on deactivation.
+///
+/// - Redacts the user's events in all rooms they are still joined to by sending
+///   redaction events as the user
+/// - Deletes all media the user uploaded
+/// - Clears the profile, the global and per-room account data and the key
+///   backups
+/// - Marks the user as erased, so their events are only served redacted over
+///   federation, including in rooms they left before
+///
+/// This has to happen before the user leaves their rooms.
+pub(crate) async fn erase_account(user_id: &UserId) -> Result<()> {
+	let joined_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_joined(user_id)
+		.filter_map(Result::ok)
+		.collect::<Vec<_>>();
+
+	for room_id in &joined_rooms {
+		// State events other than the user's membership are kept, redacting them
+		// could change the room's auth rules
+		let event_ids = services()
+			.rooms
+			.timeline
+			.all_pdus(user_id, room_id)?
+			.filter_map(Result::ok)
+			.filter(|(_, pdu)| {
+				*pdu.sender == *user_id
+					&& pdu.kind != TimelineEventType::RoomRedaction
+					&& !pdu.is_redacted()
+					&& pdu.state_key.as_ref().map_or(true, |state_key| {
+						pdu.kind == TimelineEventType::RoomMember && state_key == user_id.as_str()
+					})
+			})
+			.map(|(_, pdu)| pdu.event_id)
+			.collect::<Vec<_>>();
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.clone())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		for event_id in event_ids {
+			if let Err(e) = services()
+				.rooms
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						event_type: TimelineEventType::RoomRedaction,
+						content: to_raw_value(&RoomRedactionEventContent {
+							redacts: Some((*event_id).to_owned()),
+							reason: None,
+						})
+						.expect("event is valid, we just created it"),
+						unsigned: None,
+						state_key: None,
+						redacts: Some(event_id.clone()),
+					},
+					user_id,
+					room_id,
+					&state_lock,
+				)
+				.await
+			{
+				warn!("Failed to redact {event_id} in {room_id} while erasing {user_id}: {e}");
+			}
+		}
+
+		drop(state_lock);
+	}
+
+	for mxc in services().media.get_all_user_mxcs(user_id) {
+		if let Err(e) = services().media.delete(mxc.clone()).await {
+			warn!("Failed to delete media {mxc} while erasing {user_id}: {e}");
+		}
+	}
+
+	services().users.set_displayname(user_id, None).await?;
+	services().users.set_avatar_url(user_id, None).await?;
+	services().users.set_blurhash(user_id, None).await?;
+
+	services().account_data.remove_all(None, user_id)?;
+	let left_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_left(user_id)
+		.filter_map(Result::ok)
+		.map(|(room_id, _)| room_id);
+	let invited_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_invited(user_id)
+		.filter_map(Result::ok)
+		.map(|(room_id, _)| room_id);
+	for room_id in joined_rooms
+		.into_iter()
+		.chain(left_rooms)
+		.chain(invited_rooms)
+	{
+		services()
+			.account_data
+			.remove_all(Some(&room_id), user_id)?;
+	}
+
+	while let Some(version) = services().key_backups.get_latest_backup_version(user_id)? {
+		services().key_backups.delete_backup(user_id, &version)?;
+	}
+
+	services().users.set_erased(user_id)?;
+
+	info!("Erased the data of user {user_id}");
+
+	Ok(())
+}
+
 /// # `GET _matrix/client/v3/account/3pid`
 ///
 /// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
 pub(crate) async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	Ok(get_3pids::v3::Response::new(Vec::new()))
+	let threepids = services()
+		.threepid
+		.threepids(sender_user)
+		.collect::<Result<Vec<_>>>()?;
+
+	Ok(get_3pids::v3::Response::new(threepids))
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Binds an email address validated through the `requestToken` endpoint to
+/// this account.
+///
+/// - Requires UIAA to verify user password
+pub(crate) async fn add_3pid_route(body: Ruma<add_3pid::v3::Request>) -> Result<add_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow {
+			stages: vec![AuthType::Password],
+		}],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services()
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)?;
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+	// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services()
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json)?;
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let Some((email, validated_at)) = services()
+		.threepid
+		.validated_email(body.sid.as_str(), body.client_secret.as_str())?
+	else {
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidAuthFailed,
+			"Email address has not been validated.",
+		));
+	};
+
+	services()
+		.threepid
+		.add_email(sender_user, &email, validated_at)?;
+
+	info!("User {sender_user} added email address {email} to their account.");
+
+	Ok(add_3pid::v3::Response {})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes a third party identifier from this account. We never bind to
+/// identity servers, so there is nothing to unbind there.
+pub(crate) async fn delete_3pid_route(body: Ruma<delete_3pid::v3::Request>) -> Result<delete_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let address = match body.medium {
+		Medium::Email => services().threepid.normalize_email(&body.address)?,
+		_ => body.address.clone(),
+	};
+
+	if services()
+		.threepid
+		.remove_threepid(sender_user, &body.medium, &address)?
+	{
+		info!(
+			"User {sender_user} removed {} {address} from their account.",
+			body.medium.as_str()
+		);
+	}
+
+	Ok(delete_3pid::v3::Response {
+		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
+	})
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
@@ -565,15 +950,132 @@ pub(crate) async fn third_party_route(body: Ruma<get_3pids::v3::Request>) -> Res
 /// "This API should be used to request validation tokens when adding an email
 /// address to an account"
 ///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
+/// - 400 signals that the email address is already bound to an account
+/// - Sends the validation email unless this `send_attempt` was already sent
 pub(crate) async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
 ) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
+	let email = services().threepid.normalize_email(&body.email)?;
+
+	if services()
+		.threepid
+		.find_user_by_threepid(&Medium::Email, &email)?
+		.is_some()
+	{
+		return Err(Error::BadRequest(ErrorKind::ThreepidInUse, "Email address is already in use."));
+	}
+
+	let sid = services()
+		.threepid
+		.request_email_validation(&body.client_secret, &email, body.send_attempt, EmailPurpose::AddThreepid)
+		.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid::submit_url()),
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a validation email to reset the password of the account the email
+/// address is bound to.
+///
+/// - 400 signals that the email address is not bound to any account
+pub(crate) async fn request_password_change_token_via_email_route(
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	let email = services().threepid.normalize_email(&body.email)?;
+
+	if services()
+		.threepid
+		.find_user_by_threepid(&Medium::Email, &email)?
+		.is_none()
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::ThreepidNotFound,
+			"Email address is not bound to an account.",
+		));
+	}
+
+	let sid = services()
+		.threepid
+		.request_email_validation(&body.client_secret, &email, body.send_attempt, EmailPurpose::PasswordReset)
+		.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid::submit_url()),
+	})
+}
+
+/// # `POST /_matrix/client/v3/register/email/requestToken`
+///
+/// Sends a validation email for the `m.login.email.identity` registration
+/// stage.
+///
+/// - Only works if registration is enabled
+/// - 400 signals that the email address is already bound to an account
+pub(crate) async fn request_registration_token_via_email_route(
+	body: Ruma<request_registration_token_via_email::v3::Request>,
+) -> Result<request_registration_token_via_email::v3::Response> {
+	if !services().globals.allow_registration() {
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "Registration has been disabled."));
+	}
+
+	let email = services().threepid.normalize_email(&body.email)?;
+
+	if services()
+		.threepid
+		.find_user_by_threepid(&Medium::Email, &email)?
+		.is_some()
+	{
+		return Err(Error::BadRequest(ErrorKind::ThreepidInUse, "Email address is already in use."));
+	}
+
+	let sid = services()
+		.threepid
+		.request_email_validation(&body.client_secret, &email, body.send_attempt, EmailPurpose::Registration)
+		.await?;
+
+	Ok(request_registration_token_via_email::v3::Response {
+		sid,
+		submit_url: Some(threepid::submit_url()),
+	})
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SubmitTokenParams {
+	sid: String,
+	client_secret: String,
+	token: String,
+}
+
+/// # `GET /_conduwuit/email/submit_token`
+///
+/// Validates an email address through the link sent in the validation email.
+pub(crate) async fn submit_email_token_link_route(
+	Query(params): Query<SubmitTokenParams>,
+) -> Result<impl IntoResponse> {
+	services()
+		.threepid
+		.submit_token(&params.sid, &params.client_secret, &params.token)?;
+
+	Ok("Your email address has been validated. You can now return to your Matrix client.")
+}
+
+/// # `POST /_conduwuit/email/submit_token`
+///
+/// Validates an email address with a token the client submitted to the
+/// `submit_url` returned by the `requestToken` endpoints.
+pub(crate) async fn submit_email_token_route(Json(params): Json<SubmitTokenParams>) -> Result<impl IntoResponse> {
+	services()
+		.threepid
+		.submit_token(&params.sid, &params.client_secret, &params.token)?;
+
+	Ok(Json(serde_json::json!({
+		"success": true,
+	})))
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
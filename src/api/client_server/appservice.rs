@@ -0,0 +1,165 @@
+use std::time::Instant;
+
+use http::StatusCode;
+use ruma::api::client::error::{Error as RumaError, ErrorBody, ErrorKind};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{services, Error, Result, Ruma};
+
+/// `POST /_matrix/client/v1/appservice/{appserviceId}/ping` from MSC2659
+pub(crate) mod request_ping {
+	use ruma::{
+		api::{request, response, Metadata},
+		metadata, OwnedTransactionId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_matrix/client/unstable/fi.mau.msc2659/appservice/:appservice_id/ping",
+			1.7 => "/_matrix/client/v1/appservice/:appservice_id/ping",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The appservice to ping
+		#[ruma_api(path)]
+		pub appservice_id: String,
+
+		/// Passed on to the appservice so it can tell its pings apart
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub transaction_id: Option<OwnedTransactionId>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {
+		/// How long the appservice took to answer
+		pub duration_ms: u64,
+	}
+}
+
+/// `POST /_matrix/app/v1/ping` on the appservice
+pub(crate) mod send_ping {
+	use ruma::{
+		api::{request, response, Metadata},
+		metadata, OwnedTransactionId,
+	};
+
+	// appservice requests are built for v1.0, which has to pick the stable path
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			1.0 => "/_matrix/app/v1/ping",
+		}
+	};
+
+	#[request]
+	pub struct Request {
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub transaction_id: Option<OwnedTransactionId>,
+	}
+
+	#[response]
+	pub struct Response {}
+}
+
+/// # `POST /_matrix/client/v1/appservice/{appserviceId}/ping`
+///
+/// Asks the homeserver to ping the appservice, so the appservice can check
+/// that both directions of the connection work
+pub(crate) async fn appservice_ping_route(body: Ruma<request_ping::Request>) -> Result<request_ping::Response> {
+	let Some(info) = body
+		.appservice_info
+		.as_ref()
+		.filter(|info| info.registration.id == body.appservice_id)
+	else {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"Appservices can only ping themselves.",
+		));
+	};
+
+	if info.registration.url.is_none() {
+		return Err(ping_error(
+			StatusCode::BAD_REQUEST,
+			json!({
+				"errcode": "M_URL_NOT_SET",
+				"error": "The appservice has no URL set in its registration.",
+			}),
+		));
+	}
+
+	let started = Instant::now();
+	let response = services()
+		.sending
+		.send_appservice_request(
+			info.registration.clone(),
+			send_ping::Request {
+				transaction_id: body.transaction_id.clone(),
+			},
+		)
+		.await;
+	let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+	match response {
+		Ok(_) => {
+			info!("Appservice {} answered our ping after {duration_ms}ms", body.appservice_id);
+			services()
+				.sending
+				.record_appservice_success(&body.appservice_id)?;
+
+			Ok(request_ping::Response {
+				duration_ms,
+			})
+		},
+		Err(e) => {
+			warn!("Appservice {} didn't answer our ping: {e}", body.appservice_id);
+			services()
+				.sending
+				.record_appservice_failure(&body.appservice_id, &e)?;
+
+			Err(match e {
+				Error::AppserviceResponse(status, body) => ping_error(
+					StatusCode::BAD_GATEWAY,
+					json!({
+						"errcode": "M_BAD_STATUS",
+						"error": "The appservice returned an unsuccessful HTTP response.",
+						"status": status.as_u16(),
+						"body": body,
+					}),
+				),
+				Error::Reqwest {
+					source,
+				} if source.is_timeout() => ping_error(
+					StatusCode::GATEWAY_TIMEOUT,
+					json!({
+						"errcode": "M_CONNECTION_TIMEOUT",
+						"error": "The connection to the appservice timed out.",
+					}),
+				),
+				_ => ping_error(
+					StatusCode::BAD_GATEWAY,
+					json!({
+						"errcode": "M_CONNECTION_FAILED",
+						"error": "The appservice could not be pinged.",
+					}),
+				),
+			})
+		},
+	}
+}
+
+/// The error codes of MSC2659 are unknown to ruma, so the error body is built
+/// by hand
+fn ping_error(status_code: StatusCode, body: serde_json::Value) -> Error {
+	Error::Matrix(RumaError {
+		status_code,
+		body: ErrorBody::Json(body),
+	})
+}
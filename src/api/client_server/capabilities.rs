@@ -27,9 +27,9 @@ pub(crate) async fn get_capabilities_route(
 		available,
 	};
 
-	// conduit does not implement 3PID stuff
+	// email addresses can only be added when we are able to validate them
 	capabilities.thirdparty_id_changes = ThirdPartyIdChangesCapability {
-		enabled: false,
+		enabled: services().globals.mailer.is_some(),
 	};
 
 	Ok(get_capabilities::v3::Response {
@@ -4,7 +4,7 @@ use ruma::api::client::{
 	uiaa::{AuthFlow, AuthType, UiaaInfo},
 };
 
-use super::SESSION_ID_LENGTH;
+use super::{SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::{services, utils, Error, Result, Ruma};
 
 /// # `GET /_matrix/client/r0/devices`
@@ -43,13 +43,30 @@ pub(crate) async fn get_device_route(body: Ruma<get_device::v3::Request>) -> Res
 /// # `PUT /_matrix/client/r0/devices/{deviceId}`
 ///
 /// Updates the metadata on a given device of the sender user.
+///
+/// Appservices create the device if it doesn't exist yet (MSC4190), so they
+/// don't need to log in as their users.
 pub(crate) async fn update_device_route(body: Ruma<update_device::v3::Request>) -> Result<update_device::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	let mut device = services()
+	let Some(mut device) = services()
 		.users
 		.get_device_metadata(sender_user, &body.device_id)?
-		.ok_or(Error::BadRequest(ErrorKind::NotFound, "Device not found."))?;
+	else {
+		if body.appservice_info.is_none() {
+			return Err(Error::BadRequest(ErrorKind::NotFound, "Device not found."));
+		}
+
+		// nobody learns the token, the appservice acts as the device with its own token
+		services().users.create_device(
+			sender_user,
+			&body.device_id,
+			&utils::random_string(TOKEN_LENGTH),
+			body.display_name.clone(),
+		)?;
+
+		return Ok(update_device::v3::Response {});
+	};
 
 	device.display_name.clone_from(&body.display_name);
 
@@ -64,7 +81,7 @@ pub(crate) async fn update_device_route(body: Ruma<update_device::v3::Request>)
 ///
 /// Deletes the given device.
 ///
-/// - Requires UIAA to verify user password
+/// - Requires UIAA to verify user password, unless called by an appservice
 /// - Invalidates access token
 /// - Deletes device metadata (device id, device display name, last seen ip,
 ///   last seen ts)
@@ -72,6 +89,16 @@ pub(crate) async fn update_device_route(body: Ruma<update_device::v3::Request>)
 /// - Triggers device list updates
 pub(crate) async fn delete_device_route(body: Ruma<delete_device::v3::Request>) -> Result<delete_device::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	// appservices manage the devices of their users without UIAA (MSC4190)
+	if body.appservice_info.is_some() {
+		services()
+			.users
+			.remove_device(sender_user, &body.device_id)?;
+
+		return Ok(delete_device::v3::Response {});
+	}
+
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	// UIAA
@@ -114,7 +141,7 @@ pub(crate) async fn delete_device_route(body: Ruma<delete_device::v3::Request>)
 ///
 /// Deletes the given device.
 ///
-/// - Requires UIAA to verify user password
+/// - Requires UIAA to verify user password, unless called by an appservice
 ///
 /// For each device:
 /// - Invalidates access token
@@ -126,6 +153,16 @@ pub(crate) async fn delete_devices_route(
 	body: Ruma<delete_devices::v3::Request>,
 ) -> Result<delete_devices::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	// appservices manage the devices of their users without UIAA (MSC4190)
+	if body.appservice_info.is_some() {
+		for device_id in &body.devices {
+			services().users.remove_device(sender_user, device_id)?;
+		}
+
+		return Ok(delete_devices::v3::Response {});
+	}
+
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	// UIAA
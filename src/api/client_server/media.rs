@@ -15,7 +15,10 @@ use webpage::HTML;
 
 use crate::{
 	debug_warn,
-	service::media::{FileMeta, UrlPreviewData},
+	service::{
+		event_policy::PolicyCheck,
+		media::{FileMeta, UrlPreviewData},
+	},
 	services,
 	utils::{self, server_name::server_is_ours},
 	Error, Result, Ruma, RumaResponse,
@@ -117,6 +120,16 @@ pub(crate) async fn create_content_route(
 ) -> Result<create_content::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	services()
+		.event_policy
+		.enforce(&PolicyCheck::MediaUpload {
+			sender: sender_user,
+			content_type: body.content_type.as_deref(),
+			filename: body.filename.as_deref(),
+			size: body.file.len(),
+		})
+		.await?;
+
 	let mxc = format!(
 		"mxc://{}/{}",
 		services().globals.server_name(),
@@ -33,7 +33,11 @@ use tracing::{debug, error, info, trace, warn};
 
 use super::get_alias_helper;
 use crate::{
-	service::pdu::{gen_event_id_canonical_json, PduBuilder},
+	service::{
+		event_policy::PolicyCheck,
+		pdu::{gen_event_id_canonical_json, PduBuilder},
+		rooms::metadata::PartialState,
+	},
 	services,
 	utils::{self, server_name::server_is_ours, user_id::user_is_local},
 	Error, PduEvent, Result, Ruma,
@@ -630,6 +634,13 @@ pub(crate) async fn get_member_events_route(
 		));
 	}
 
+	// the members are exactly what is missing from partial state
+	services()
+		.rooms
+		.event_handler
+		.wait_for_full_state(&body.room_id)
+		.await?;
+
 	Ok(get_member_events::v3::Response {
 		chunk: services()
 			.rooms
@@ -665,6 +676,12 @@ pub(crate) async fn joined_members_route(
 		));
 	}
 
+	services()
+		.rooms
+		.event_handler
+		.wait_for_full_state(&body.room_id)
+		.await?;
+
 	let mut joined = BTreeMap::new();
 	for user_id in services()
 		.rooms
@@ -695,6 +712,29 @@ pub(crate) async fn join_room_by_id_helper(
 ) -> Result<join_room_by_id::v3::Response> {
 	let sender_user = sender_user.expect("user is authenticated");
 
+	if services().users.is_suspended(sender_user)? {
+		return Err(Error::user_suspended());
+	}
+
+	if let Some(rule) = services().rooms.policy.room_rule(room_id).await? {
+		info!(
+			"{sender_user} tried joining {room_id} which is banned by policy room {}: {}",
+			rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"This room is banned by a policy list.",
+		));
+	}
+
+	if let Some(rule) = services().rooms.policy.user_rule(sender_user).await? {
+		info!(
+			"{sender_user} tried joining {room_id} but is banned by policy room {}: {}",
+			rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "You are banned by a policy list."));
+	}
+
 	if let Ok(true) = services().rooms.state_cache.is_joined(sender_user, room_id) {
 		info!("{sender_user} is already joined in {room_id}");
 		return Ok(join_room_by_id::v3::Response {
@@ -790,7 +830,7 @@ pub(crate) async fn join_room_by_id_helper(
 		// to be present
 		ruma::signatures::hash_and_sign_event(
 			services().globals.server_name().as_str(),
-			services().globals.keypair(),
+			&*services().globals.keypair(),
 			&mut join_event_stub,
 			&room_version_id,
 		)
@@ -819,13 +859,34 @@ pub(crate) async fn join_room_by_id_helper(
 					room_id: room_id.to_owned(),
 					event_id: event_id.to_owned(),
 					pdu: PduEvent::convert_to_outgoing_federation_event(join_event.clone()),
-					omit_members: false,
+					omit_members: services().globals.config.allow_faster_joins,
 				},
 			)
 			.await?;
 
 		info!("send_join finished");
 
+		// With faster joins (MSC3706) the resident server leaves out the member events,
+		// we fetch them in the background after joining
+		let partial_state = send_join_response.room_state.members_omitted.then(|| {
+			let mut servers = vec![remote_server.clone()];
+			servers.extend(
+				send_join_response
+					.room_state
+					.servers_in_room
+					.iter()
+					.flatten()
+					.filter_map(|server| OwnedServerName::try_from(server.as_str()).ok())
+					.filter(|server| !server_is_ours(server) && *server != remote_server),
+			);
+
+			PartialState {
+				join_event_id: event_id.to_owned(),
+				servers,
+				accepted_events: Vec::new(),
+			}
+		});
+
 		if join_authorized_via_users_server.is_some() {
 			match &room_version_id {
 				RoomVersionId::V1
@@ -1049,6 +1110,18 @@ pub(crate) async fn join_room_by_id_helper(
 			.rooms
 			.state
 			.set_room_state(room_id, statehash_after_join, &state_lock)?;
+
+		if let Some(partial_state) = partial_state {
+			info!("Joined {room_id} with partial state, fetching the full state in the background");
+			services()
+				.rooms
+				.metadata
+				.set_partial_state(room_id, Some(&partial_state))?;
+			services()
+				.rooms
+				.event_handler
+				.start_partial_state_resync(room_id);
+		}
 	} else {
 		info!("We can join locally");
 
@@ -1222,7 +1295,7 @@ pub(crate) async fn join_room_by_id_helper(
 			// to be present
 			ruma::signatures::hash_and_sign_event(
 				services().globals.server_name().as_str(),
-				services().globals.keypair(),
+				&*services().globals.keypair(),
 				&mut join_event_stub,
 				&room_version_id,
 			)
@@ -1369,7 +1442,7 @@ async fn make_join_request(
 	make_join_response_and_server
 }
 
-async fn validate_and_add_event_id(
+pub(crate) async fn validate_and_add_event_id(
 	pdu: &RawJsonValue, room_version: &RoomVersionId, pub_key_map: &RwLock<BTreeMap<String, BTreeMap<String, Base64>>>,
 ) -> Result<(OwnedEventId, CanonicalJsonObject)> {
 	let mut value: CanonicalJsonObject = serde_json::from_str(pdu.get()).map_err(|e| {
@@ -1430,6 +1503,37 @@ async fn validate_and_add_event_id(
 pub(crate) async fn invite_helper(
 	sender_user: &UserId, user_id: &UserId, room_id: &RoomId, reason: Option<String>, is_direct: bool,
 ) -> Result<()> {
+	if services().users.is_suspended(sender_user)? {
+		return Err(Error::user_suspended());
+	}
+
+	if let Some(rule) = services().rooms.policy.user_rule(user_id).await? {
+		info!(
+			"{sender_user} tried inviting {user_id} who is banned by policy room {}: {}",
+			rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"The invited user is banned by a policy list.",
+		));
+	}
+
+	if services().rooms.policy.room_rule(room_id).await?.is_some() {
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"This room is banned by a policy list.",
+		));
+	}
+
+	services()
+		.event_policy
+		.enforce(&PolicyCheck::Invite {
+			sender: sender_user,
+			invitee: user_id,
+			room_id,
+		})
+		.await?;
+
 	if !services().users.is_admin(user_id)? && services().globals.block_non_admin_invites() {
 		info!("User {sender_user} is not an admin and attempted to send an invite to room {room_id}");
 		return Err(Error::BadRequest(
@@ -1815,7 +1919,7 @@ async fn remote_leave_room(user_id: &UserId, room_id: &RoomId) -> Result<()> {
 	// to be present
 	ruma::signatures::hash_and_sign_event(
 		services().globals.server_name().as_str(),
-		services().globals.keypair(),
+		&*services().globals.keypair(),
 		&mut leave_event_stub,
 		&room_version_id,
 	)
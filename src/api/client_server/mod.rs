@@ -1,5 +1,6 @@
 mod account;
 mod alias;
+mod appservice;
 mod backup;
 mod capabilities;
 mod config;
@@ -36,6 +37,7 @@ mod voip;
 
 pub(crate) use account::*;
 pub(crate) use alias::*;
+pub(crate) use appservice::*;
 pub(crate) use backup::*;
 pub(crate) use capabilities::*;
 pub(crate) use config::*;
@@ -27,7 +27,8 @@ pub(crate) async fn set_presence_route(body: Ruma<set_presence::v3::Request>) ->
 ///
 /// Gets the presence state of the given user.
 ///
-/// - Only works if you share a room with the user
+/// - Only works if you share a room with the user and their presence visibility
+///   allows it
 pub(crate) async fn get_presence_route(body: Ruma<get_presence::v3::Request>) -> Result<get_presence::v3::Response> {
 	if !services().globals.allow_local_presence() {
 		return Err(Error::BadRequest(ErrorKind::forbidden(), "Presence is disabled on this server"));
@@ -35,6 +36,13 @@ pub(crate) async fn get_presence_route(body: Ruma<get_presence::v3::Request>) ->
 
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if !services().presence.visible_to(&body.user_id, sender_user)? {
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"Presence state for this user was not found",
+		));
+	}
+
 	let mut presence_event = None;
 
 	for _room_id in services()
@@ -1,13 +1,18 @@
+use std::collections::HashMap;
+
 use ruma::{
 	api::client::{
 		error::ErrorKind,
 		push::{
-			delete_pushrule, get_pushers, get_pushrule, get_pushrule_actions, get_pushrule_enabled, get_pushrules_all,
-			set_pusher, set_pushrule, set_pushrule_actions, set_pushrule_enabled, RuleScope,
+			delete_pushrule, get_notifications, get_pushers, get_pushrule, get_pushrule_actions, get_pushrule_enabled,
+			get_pushrules_all, set_pusher, set_pushrule, set_pushrule_actions, set_pushrule_enabled, PusherKind,
+			RuleScope,
 		},
 	},
 	events::{push_rules::PushRulesEvent, GlobalAccountDataEventType},
 	push::{InsertPushRuleError, RemovePushRuleError, Ruleset},
+	thirdparty::Medium,
+	uint, MilliSecondsSinceUnixEpoch, UInt,
 };
 
 use crate::{services, Error, Result, Ruma};
@@ -361,13 +366,114 @@ pub(crate) async fn get_pushers_route(body: Ruma<get_pushers::v3::Request>) -> R
 ///
 /// Adds a pusher for the sender user.
 ///
+/// - Email pushers need SMTP to be configured and an email address bound to the
+///   user's account
 /// - TODO: Handle `append`
 pub(crate) async fn set_pushers_route(body: Ruma<set_pusher::v3::Request>) -> Result<set_pusher::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if let set_pusher::v3::PusherAction::Post(data) = &body.action {
+		if matches!(data.pusher.kind, PusherKind::Email(_)) {
+			if services().globals.mailer.is_none() {
+				return Err(Error::BadRequest(
+					ErrorKind::InvalidParam,
+					"Email notifications are not enabled on this server.",
+				));
+			}
+
+			let email = services()
+				.threepid
+				.normalize_email(&data.pusher.ids.pushkey)?;
+			if services()
+				.threepid
+				.find_user_by_threepid(&Medium::Email, &email)?
+				.as_ref() != Some(sender_user)
+			{
+				return Err(Error::BadRequest(
+					ErrorKind::InvalidParam,
+					"The pushkey of an email pusher must be an email address bound to your account.",
+				));
+			}
+		}
+	}
+
 	services()
 		.pusher
 		.set_pusher(sender_user, body.action.clone())?;
 
 	Ok(set_pusher::v3::Response::default())
 }
+
+/// # `GET /_matrix/client/v3/notifications`
+///
+/// Paginates over the notifications of this user, newest first.
+///
+/// - A notification is read if the user's read receipt or private read marker
+///   is at or after the event
+pub(crate) async fn get_notifications_route(
+	body: Ruma<get_notifications::v3::Request>,
+) -> Result<get_notifications::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let until = body
+		.from
+		.as_ref()
+		.map(|from| {
+			from.parse()
+				.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid from token."))
+		})
+		.transpose()?;
+	let limit = body.limit.unwrap_or(uint!(20)).min(uint!(100));
+	let limit = usize::try_from(u64::from(limit)).expect("limit is at most 100");
+	let only_highlight = body.only.as_deref() == Some("highlight");
+
+	let mut last_read = HashMap::new();
+	let mut notifications = Vec::new();
+	let mut next_token = None;
+
+	for (count, notification) in services()
+		.pusher
+		.notifications(sender_user, until)
+		.filter_map(Result::ok)
+		.filter(|(_, notification)| !only_highlight || notification.highlight)
+	{
+		if notifications.len() >= limit {
+			break;
+		}
+
+		let Some(pdu) = services().rooms.timeline.get_pdu(&notification.event_id)? else {
+			continue;
+		};
+
+		let read = match last_read.get(&notification.room_id) {
+			Some(last_read) => count <= *last_read,
+			None => {
+				let room_last_read = services()
+					.rooms
+					.read_receipt
+					.last_read_count(&notification.room_id, sender_user)?;
+				last_read.insert(notification.room_id.clone(), room_last_read);
+				count <= room_last_read
+			},
+		};
+
+		notifications.push(get_notifications::v3::Notification::new(
+			notification.actions,
+			pdu.to_sync_room_event(),
+			read,
+			notification.room_id,
+			MilliSecondsSinceUnixEpoch(UInt::new(notification.ts).unwrap_or_default()),
+		));
+		next_token = Some(count.to_string());
+	}
+
+	// Only hand out a token if there might be more notifications
+	if notifications.len() < limit {
+		next_token = None;
+	}
+
+	Ok(get_notifications::v3::Response {
+		next_token,
+		notifications,
+	})
+}
@@ -41,7 +41,7 @@ pub(crate) async fn set_read_marker_route(
 		services()
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id)?;
+			.reset_notification_counts(sender_user, &body.room_id, &ReceiptThread::Unthreaded)?;
 	}
 
 	if let Some(event) = &body.private_read_receipt {
@@ -97,11 +97,23 @@ pub(crate) async fn set_read_marker_route(
 /// # `POST /_matrix/client/r0/rooms/{roomId}/receipt/{receiptType}/{eventId}`
 ///
 /// Sets private read marker and public read receipt EDU.
+///
+/// - Receipts with a thread ID only mark the thread (or the main timeline for
+///   `main`) as read
 pub(crate) async fn create_receipt_route(
 	body: Ruma<create_receipt::v3::Request>,
 ) -> Result<create_receipt::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
+	if matches!(&body.receipt_type, create_receipt::v3::ReceiptType::FullyRead)
+		&& body.thread != ReceiptThread::Unthreaded
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Fully read markers can't be threaded.",
+		));
+	}
+
 	if matches!(
 		&body.receipt_type,
 		create_receipt::v3::ReceiptType::Read | create_receipt::v3::ReceiptType::ReadPrivate
@@ -109,7 +121,7 @@ pub(crate) async fn create_receipt_route(
 		services()
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id)?;
+			.reset_notification_counts(sender_user, &body.room_id, &body.thread)?;
 	}
 
 	match body.receipt_type {
@@ -132,7 +144,7 @@ pub(crate) async fn create_receipt_route(
 				sender_user.clone(),
 				ruma::events::receipt::Receipt {
 					ts: Some(MilliSecondsSinceUnixEpoch::now()),
-					thread: ReceiptThread::Unthreaded,
+					thread: body.thread.clone(),
 				},
 			);
 			let mut receipts = BTreeMap::new();
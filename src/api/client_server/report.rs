@@ -3,13 +3,80 @@ use std::time::Duration;
 use rand::Rng;
 use ruma::{
 	api::client::{error::ErrorKind, room::report_content},
-	events::room::message,
 	int, EventId, RoomId, UserId,
 };
 use tokio::time::sleep;
 use tracing::info;
 
-use crate::{debug_info, service::pdu::PduEvent, services, utils::HtmlEscape, Error, Result, Ruma};
+use crate::{debug_info, service::pdu::PduEvent, services, Error, Result, Ruma};
+
+/// `POST /rooms/{roomId}/report` from MSC4151, which our ruma doesn't have yet
+pub(crate) mod report_room {
+	use ruma::{
+		api::{request, response, Metadata},
+		metadata, OwnedRoomId,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: POST,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			unstable => "/_matrix/client/unstable/org.matrix.msc4151/rooms/:room_id/report",
+			1.1 => "/_matrix/client/v3/rooms/:room_id/report",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The room being reported
+		#[ruma_api(path)]
+		pub room_id: OwnedRoomId,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub reason: Option<String>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {}
+}
+
+/// # `POST /_matrix/client/v3/rooms/{roomId}/report`
+///
+/// Reports an inappropriate room to homeserver admins
+pub(crate) async fn report_room_route(body: Ruma<report_room::Request>) -> Result<report_room::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	info!("Received /report request by user {sender_user} for room {}", body.room_id);
+
+	// don't reveal whether we know the room to users who aren't in it
+	if !services()
+		.rooms
+		.state_cache
+		.is_joined(sender_user, &body.room_id)?
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"Room does not exist or you are not in it.",
+		));
+	}
+
+	if let Some(true) = body.reason.as_ref().map(|s| s.len() >= 750) {
+		return Err(Error::BadRequest(
+			ErrorKind::InvalidParam,
+			"Reason too long, should be 750 characters or fewer",
+		));
+	};
+
+	services()
+		.reports
+		.create(sender_user, &body.room_id, None, body.reason.clone(), None)
+		.await?;
+
+	delay_response().await?;
+
+	Ok(report_room::Response {})
+}
 
 /// # `POST /_matrix/client/v3/rooms/{roomId}/report/{eventId}`
 ///
@@ -35,37 +102,16 @@ pub(crate) async fn report_event_route(
 
 	is_report_valid(&pdu.event_id, &body.room_id, sender_user, &body.reason, body.score, &pdu)?;
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
 	services()
-		.admin
-		.send_message(message::RoomMessageEventContent::text_html(
-			format!(
-				"@room Report received from: {}\n\nEvent ID: {}\nRoom ID: {}\nSent By: {}\n\nReport Score: {}\nReport \
-				 Reason: {}",
-				sender_user.to_owned(),
-				pdu.event_id,
-				pdu.room_id,
-				pdu.sender.clone(),
-				body.score.unwrap_or_else(|| ruma::Int::from(0)),
-				body.reason.as_deref().unwrap_or("")
-			),
-			format!(
-				"<details><summary>@room Report received from: <a href=\"https://matrix.to/#/{0}\">{0}\
-                </a></summary><ul><li>Event Info<ul><li>Event ID: <code>{1}</code>\
-                <a href=\"https://matrix.to/#/{2}/{1}\">🔗</a></li><li>Room ID: <code>{2}</code>\
-                </li><li>Sent By: <a href=\"https://matrix.to/#/{3}\">{3}</a></li></ul></li><li>\
-                Report Info<ul><li>Report Score: {4}</li><li>Report Reason: {5}</li></ul></li>\
-                </ul></details>",
-				sender_user.to_owned(),
-				pdu.event_id.clone(),
-				pdu.room_id.clone(),
-				pdu.sender.clone(),
-				body.score.unwrap_or_else(|| ruma::Int::from(0)),
-				HtmlEscape(body.reason.as_deref().unwrap_or(""))
-			),
-		))
-		.await;
+		.reports
+		.create(
+			sender_user,
+			&pdu.room_id,
+			Some((pdu.event_id.clone(), pdu.sender.clone())),
+			body.reason.clone(),
+			body.score,
+		)
+		.await?;
 
 	delay_response().await?;
 
@@ -91,12 +137,10 @@ fn is_report_valid(
 		));
 	}
 
-	if services()
+	if !services()
 		.rooms
 		.state_cache
-		.room_members(&pdu.room_id)
-		.filter_map(Result::ok)
-		.any(|user_id| user_id != *sender_user)
+		.is_joined(sender_user, &pdu.room_id)?
 	{
 		return Err(Error::BadRequest(
 			ErrorKind::NotFound,
@@ -11,10 +11,11 @@ use ruma::{
 				self,
 				v3::{DiscoveryInfo, HomeserverInfo},
 			},
-			logout, logout_all,
+			logout, logout_all, refresh_token,
 		},
 		uiaa::UserIdentifier,
 	},
+	thirdparty::Medium,
 	UserId,
 };
 use serde::Deserialize;
@@ -49,9 +50,12 @@ pub(crate) async fn get_login_types_route(
 ///
 /// - The user needs to authenticate using their password (or if enabled using a
 ///   json web token)
+/// - The user can be identified by their user id or by a bound email address
 /// - If `device_id` is known: invalidates old access token of that device
 /// - If `device_id` is unknown: creates a new device
 /// - Returns access token that is associated with the user and device
+/// - If `refresh_token` is true: also returns a refresh token, and the access
+///   token expires after the configured `access_token_ttl`
 ///
 /// Note: You can use [`GET
 /// /_matrix/client/r0/login`](fn.get_supported_versions_route.html) to see
@@ -68,7 +72,16 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 			..
 		}) => {
 			debug!("Got password login type");
-			let user_id = if let Some(UserIdentifier::UserIdOrLocalpart(user_id)) = identifier {
+			let user_id = if let Some(UserIdentifier::Email {
+				address,
+			}) = identifier
+			{
+				let email = services().threepid.normalize_email(address)?;
+				Ok(services()
+					.threepid
+					.find_user_by_threepid(&Medium::Email, &email)?
+					.ok_or(Error::BadRequest(ErrorKind::forbidden(), "Wrong username or password."))?)
+			} else if let Some(UserIdentifier::UserIdOrLocalpart(user_id)) = identifier {
 				UserId::parse_with_server_name(user_id.to_lowercase(), services().globals.server_name())
 			} else if let Some(user) = user {
 				UserId::parse(user)
@@ -204,6 +217,19 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 			.create_device(&user_id, &device_id, &token, body.initial_device_display_name.clone())?;
 	}
 
+	// Issue a refresh token and let the access token expire if the client supports
+	// it
+	let (refresh_token, expires_in) = if body.refresh_token {
+		let refresh_token = utils::random_string(TOKEN_LENGTH);
+		let expires_in = services()
+			.users
+			.set_refreshable_token(&user_id, &device_id, &token, &refresh_token)?;
+
+		(Some(refresh_token), Some(expires_in))
+	} else {
+		(None, None)
+	};
+
 	// send client well-known if specified so the client knows to reconfigure itself
 	let client_discovery_info: Option<DiscoveryInfo> = services()
 		.globals
@@ -222,9 +248,42 @@ pub(crate) async fn login_route(body: Ruma<login::v3::Request>) -> Result<login:
 		access_token: token,
 		device_id,
 		well_known: client_discovery_info,
-		expires_in: None,
+		expires_in,
 		home_server: Some(services().globals.server_name().to_owned()),
-		refresh_token: None,
+		refresh_token,
+	})
+}
+
+/// # `POST /_matrix/client/v3/refresh`
+///
+/// Exchanges a refresh token for a new access token.
+///
+/// - The refresh token can only be used once, a new one is returned
+/// - Invalidates the previous access token of the device
+/// - The new access token expires after the configured `access_token_ttl`
+pub(crate) async fn refresh_token_route(body: Ruma<refresh_token::v3::Request>) -> Result<refresh_token::v3::Response> {
+	let (user_id, device_id) = services()
+		.users
+		.find_from_refresh_token(&body.refresh_token)?
+		.ok_or(Error::BadRequest(
+			ErrorKind::UnknownToken {
+				soft_logout: false,
+			},
+			"Unknown refresh token.",
+		))?;
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	let refresh_token = utils::random_string(TOKEN_LENGTH);
+	let expires_in = services()
+		.users
+		.set_refreshable_token(&user_id, &device_id, &token, &refresh_token)?;
+
+	debug!("{user_id} refreshed the access token of device {device_id}");
+
+	Ok(refresh_token::v3::Response {
+		access_token: token,
+		refresh_token: Some(refresh_token),
+		expires_in_ms: Some(expires_in),
 	})
 }
 
@@ -2,10 +2,96 @@ use std::str::FromStr;
 
 use ruma::{
 	api::client::{error::ErrorKind, space::get_hierarchy},
-	UInt,
+	events::{
+		room::{encryption::RoomEncryptionEventContent, member::MembershipState},
+		StateEventType,
+	},
+	OwnedRoomId, UInt,
 };
 
-use crate::{service::rooms::spaces::PagnationToken, services, Error, Result, Ruma};
+use super::get_alias_helper;
+use crate::{
+	service::rooms::spaces::PagnationToken, services, utils::server_name::server_is_ours, Error, Result, Ruma,
+};
+
+/// `GET /_matrix/client/v1/room_summary/{roomIdOrAlias}` from MSC3266, which
+/// our ruma doesn't have yet
+pub(crate) mod get_room_summary {
+	use ruma::{
+		api::{request, response, Metadata},
+		events::room::member::MembershipState,
+		metadata,
+		room::RoomType,
+		space::SpaceRoomJoinRule,
+		EventEncryptionAlgorithm, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName,
+		RoomVersionId, UInt,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: GET,
+		rate_limited: false,
+		authentication: AccessTokenOptional,
+		history: {
+			unstable => "/_matrix/client/unstable/im.nheko.summary/summary/:room_id_or_alias",
+			1.1 => "/_matrix/client/v1/room_summary/:room_id_or_alias",
+		}
+	};
+
+	#[request(error = ruma::api::client::Error)]
+	pub struct Request {
+		/// The room to summarize
+		#[ruma_api(path)]
+		pub room_id_or_alias: OwnedRoomOrAliasId,
+
+		/// Servers to ask for the summary if we are not in the room
+		#[ruma_api(query)]
+		#[serde(default, skip_serializing_if = "Vec::is_empty")]
+		pub via: Vec<OwnedServerName>,
+	}
+
+	#[response(error = ruma::api::client::Error)]
+	pub struct Response {
+		pub room_id: OwnedRoomId,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub canonical_alias: Option<OwnedRoomAliasId>,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub name: Option<String>,
+
+		pub num_joined_members: UInt,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub topic: Option<String>,
+
+		pub world_readable: bool,
+
+		pub guest_can_join: bool,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub avatar_url: Option<OwnedMxcUri>,
+
+		pub join_rule: SpaceRoomJoinRule,
+
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub room_type: Option<RoomType>,
+
+		#[serde(skip_serializing_if = "Vec::is_empty")]
+		pub allowed_room_ids: Vec<OwnedRoomId>,
+
+		/// The membership of the requesting user, if they have one
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub membership: Option<MembershipState>,
+
+		/// Only known if we are in the room
+		#[serde(rename = "im.nheko.summary.room_version", skip_serializing_if = "Option::is_none")]
+		pub room_version: Option<RoomVersionId>,
+
+		/// Only known if we are in the room
+		#[serde(rename = "im.nheko.summary.encryption", skip_serializing_if = "Option::is_none")]
+		pub encryption: Option<EventEncryptionAlgorithm>,
+	}
+}
 
 /// # `GET /_matrix/client/v1/rooms/{room_id}/hierarchy`
 ///
@@ -52,3 +138,88 @@ pub(crate) async fn get_hierarchy_route(body: Ruma<get_hierarchy::v1::Request>)
 		)
 		.await
 }
+
+/// # `GET /_matrix/client/v1/room_summary/{roomIdOrAlias}`
+///
+/// Summarizes a room for previewing it, e.g. from a link.
+///
+/// - Asks the `via` servers and the servers of the room ID or alias over
+///   federation if we are not in the room. Unauthenticated requests can't make
+///   us do that, so they only see rooms and aliases known to this server
+/// - Rooms are only summarized for users who could see them in the space
+///   hierarchy, or if they are world readable
+pub(crate) async fn get_room_summary_route(
+	body: Ruma<get_room_summary::Request>,
+) -> Result<get_room_summary::Response> {
+	let sender_user = body.sender_user.as_deref();
+
+	let (room_id, mut servers) = match OwnedRoomId::try_from(body.room_id_or_alias.clone()) {
+		Ok(room_id) => (room_id, Vec::new()),
+		// Only users of this server can make us resolve aliases of other servers
+		Err(room_alias) if sender_user.is_some() || server_is_ours(room_alias.server_name()) => {
+			let response = get_alias_helper(room_alias, Some(body.via.clone())).await?;
+			(response.room_id, response.servers)
+		},
+		Err(_) => {
+			return Err(Error::BadRequest(ErrorKind::NotFound, "Room alias not found."));
+		},
+	};
+
+	if sender_user.is_some() {
+		servers.extend(body.via.iter().cloned());
+	}
+	if let Some(server) = room_id.server_name() {
+		servers.push(server.to_owned());
+	}
+
+	let Some(summary) = services()
+		.rooms
+		.spaces
+		.get_room_preview(&room_id, sender_user, &servers)
+		.await?
+	else {
+		return Err(Error::BadRequest(
+			ErrorKind::NotFound,
+			"Room does not exist or you are not allowed to see it.",
+		));
+	};
+
+	let state_accessor = &services().rooms.state_accessor;
+	let membership = match sender_user {
+		Some(user_id) => match state_accessor.get_member(&room_id, user_id)? {
+			Some(member) => Some(member.membership),
+			None => services()
+				.rooms
+				.state_cache
+				.is_invited(user_id, &room_id)?
+				.then_some(MembershipState::Invite),
+		},
+		None => None,
+	};
+
+	let encryption = state_accessor
+		.room_state_get(&room_id, &StateEventType::RoomEncryption, "")?
+		.map(|event| {
+			serde_json::from_str::<RoomEncryptionEventContent>(event.content.get())
+				.map(|content| content.algorithm)
+				.map_err(|_| Error::bad_database("Invalid room encryption event in database."))
+		})
+		.transpose()?;
+
+	Ok(get_room_summary::Response {
+		room_id: summary.room_id,
+		canonical_alias: summary.canonical_alias,
+		name: summary.name,
+		num_joined_members: summary.num_joined_members,
+		topic: summary.topic,
+		world_readable: summary.world_readable,
+		guest_can_join: summary.guest_can_join,
+		avatar_url: summary.avatar_url,
+		join_rule: summary.join_rule,
+		room_type: summary.room_type,
+		allowed_room_ids: summary.allowed_room_ids,
+		membership,
+		room_version: services().rooms.state.get_room_version(&room_id).ok(),
+		encryption,
+	})
+}
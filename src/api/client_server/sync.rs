@@ -253,6 +253,7 @@ async fn sync_helper(
 			lazy_load_enabled,
 			lazy_load_send_redundant,
 			full_state,
+			filter.room.timeline.unread_thread_notifications,
 			&mut device_list_updates,
 			&mut left_encrypted_users,
 		)
@@ -587,12 +588,13 @@ async fn handle_left_room(
 async fn process_presence_updates(
 	presence_updates: &mut HashMap<OwnedUserId, PresenceEvent>, since: u64, syncing_user: &OwnedUserId,
 ) -> Result<()> {
+	let settings = services().presence.settings(syncing_user)?;
+
 	// Take presence updates
 	for (user_id, _, presence_bytes) in services().presence.presence_since(since) {
 		if !services()
-			.rooms
-			.state_cache
-			.user_sees_user(syncing_user, &user_id)?
+			.presence
+			.wanted_by(&user_id, syncing_user, &settings)?
 		{
 			continue;
 		}
@@ -634,7 +636,8 @@ async fn process_presence_updates(
 async fn load_joined_room(
 	sender_user: &UserId, sender_device: &DeviceId, room_id: &RoomId, since: u64, sincecount: PduCount,
 	next_batch: u64, next_batchcount: PduCount, lazy_load_enabled: bool, lazy_load_send_redundant: bool,
-	full_state: bool, device_list_updates: &mut HashSet<OwnedUserId>, left_encrypted_users: &mut HashSet<OwnedUserId>,
+	full_state: bool, threaded_notifications: bool, device_list_updates: &mut HashSet<OwnedUserId>,
+	left_encrypted_users: &mut HashSet<OwnedUserId>,
 ) -> Result<JoinedRoom> {
 	{
 		// Get and drop the lock to wait for remaining operations to finish
@@ -1015,30 +1018,13 @@ async fn load_joined_room(
 			.filter_map(Result::ok),
 	);
 
-	let notification_count = if send_notification_counts {
-		Some(
-			services()
-				.rooms
-				.user
-				.notification_count(sender_user, room_id)?
-				.try_into()
-				.expect("notification count can't go that high"),
-		)
-	} else {
-		None
-	};
-
-	let highlight_count = if send_notification_counts {
-		Some(
-			services()
-				.rooms
-				.user
-				.highlight_count(sender_user, room_id)?
-				.try_into()
-				.expect("highlight count can't go that high"),
-		)
+	let (unread_notifications, unread_thread_notifications) = if send_notification_counts {
+		services()
+			.rooms
+			.user
+			.unread_notifications(sender_user, room_id, threaded_notifications)?
 	} else {
-		None
+		(UnreadNotificationsCount::new(), BTreeMap::new())
 	};
 
 	let prev_batch = timeline_pdus
@@ -1101,10 +1087,7 @@ async fn load_joined_room(
 			joined_member_count: joined_member_count.map(|n| (n as u32).into()),
 			invited_member_count: invited_member_count.map(|n| (n as u32).into()),
 		},
-		unread_notifications: UnreadNotificationsCount {
-			highlight_count,
-			notification_count,
-		},
+		unread_notifications,
 		timeline: Timeline {
 			limited: limited || joined_since_last_sync,
 			prev_batch,
@@ -1119,7 +1102,7 @@ async fn load_joined_room(
 		ephemeral: Ephemeral {
 			events: edus,
 		},
-		unread_thread_notifications: BTreeMap::new(),
+		unread_thread_notifications,
 	})
 }
 
@@ -51,6 +51,10 @@ pub(crate) async fn get_supported_versions_route(
 			("org.matrix.msc2946".to_owned(), true),
 			("org.matrix.msc3026.busy_presence".to_owned(), true),
 			("org.matrix.msc3827".to_owned(), true),
+			("fi.mau.msc2659".to_owned(), true),
+			("org.matrix.msc3202".to_owned(), true),
+			("io.element.msc4190".to_owned(), true),
+			("im.nheko.summary".to_owned(), true),
 		]),
 	};
 
@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, str};
+use std::{any::TypeId, collections::BTreeMap, str};
 
 use axum::{
 	async_trait,
@@ -19,7 +19,15 @@ use http::{uri::PathAndQuery, StatusCode};
 use http_body_util::Full;
 use hyper::Request;
 use ruma::{
-	api::{client::error::ErrorKind, AuthScheme, IncomingRequest, OutgoingResponse},
+	api::{
+		client::{
+			account::change_password,
+			error::ErrorKind,
+			media::create_content,
+			session::{logout, logout_all},
+		},
+		AuthScheme, IncomingRequest, OutgoingResponse,
+	},
 	CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 };
 use serde::Deserialize;
@@ -31,6 +39,7 @@ use crate::{debug_warn, service::appservice::RegistrationInfo, services, Error,
 enum Token {
 	Appservice(Box<RegistrationInfo>),
 	User((OwnedUserId, OwnedDeviceId)),
+	Expired,
 	Invalid,
 	None,
 }
@@ -39,12 +48,15 @@ enum Token {
 struct QueryParams {
 	access_token: Option<String>,
 	user_id: Option<String>,
+	/// Device of `user_id` the appservice acts as (MSC3202)
+	#[serde(rename = "org.matrix.msc3202.device_id", alias = "device_id")]
+	device_id: Option<OwnedDeviceId>,
 }
 
 #[async_trait]
 impl<T, S> FromRequest<S, axum::body::Body> for Ruma<T>
 where
-	T: IncomingRequest,
+	T: IncomingRequest + 'static,
 {
 	type Rejection = Error;
 
@@ -86,7 +98,15 @@ where
 			if let Some(reg_info) = services().appservice.find_from_token(token).await {
 				Token::Appservice(Box::new(reg_info))
 			} else if let Some((user_id, device_id)) = services().users.find_from_token(token)? {
-				Token::User((user_id, OwnedDeviceId::from(device_id)))
+				let device_id = OwnedDeviceId::from(device_id);
+				if services()
+					.users
+					.is_access_token_expired(&user_id, &device_id)?
+				{
+					Token::Expired
+				} else {
+					Token::User((user_id, device_id))
+				}
 			} else {
 				Token::Invalid
 			}
@@ -108,7 +128,7 @@ where
 								// we should have validated the token above
 								// already
 							},
-							Token::None | Token::Invalid => {
+							Token::None | Token::Expired | Token::Invalid => {
 								return Err(Error::BadRequest(
 									ErrorKind::MissingToken,
 									"Missing or invalid access token.",
@@ -124,6 +144,17 @@ where
 		let mut json_body = serde_json::from_slice::<CanonicalJsonValue>(&body).ok();
 
 		let (sender_user, sender_device, sender_servername, appservice_info) = match (metadata.authentication, token) {
+			// clients may still send their expired access token to unauthenticated
+			// endpoints such as /refresh
+			(AuthScheme::None, Token::Expired) => (None, None, None, None),
+			(_, Token::Expired) => {
+				return Err(Error::BadRequest(
+					ErrorKind::UnknownToken {
+						soft_logout: true,
+					},
+					"Access token has expired.",
+				))
+			},
 			(_, Token::Invalid) => {
 				return Err(Error::BadRequest(
 					ErrorKind::UnknownToken {
@@ -154,12 +185,33 @@ where
 					return Err(Error::BadRequest(ErrorKind::forbidden(), "User does not exist."));
 				}
 
-				(Some(user_id), None, None, Some(*info))
+				if let Some(device_id) = &query_params.device_id {
+					if !services()
+						.users
+						.all_device_ids(&user_id)
+						.filter_map(Result::ok)
+						.any(|existing| existing == *device_id)
+					{
+						return Err(Error::BadRequest(
+							ErrorKind::forbidden(),
+							"Device does not exist for the masqueraded user.",
+						));
+					}
+				}
+
+				(Some(user_id), query_params.device_id, None, Some(*info))
 			},
 			(
 				AuthScheme::None | AuthScheme::AccessTokenOptional | AuthScheme::AppserviceToken,
 				Token::Appservice(info),
 			) => (None, None, None, Some(*info)),
+			// passwords can be reset with a validated email address instead of logging in
+			(AuthScheme::AccessToken, Token::None)
+				if is_request::<T>(&[TypeId::of::<change_password::v3::Request>()])
+					&& services().globals.mailer.is_some() =>
+			{
+				(None, None, None, None)
+			},
 			(AuthScheme::AccessToken, Token::None) => {
 				return Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."));
 			},
@@ -240,7 +292,14 @@ where
 				let pub_key_map = BTreeMap::from_iter([(x_matrix.origin.as_str().to_owned(), keys)]);
 
 				match ruma::signatures::verify_json(&pub_key_map, &request_map) {
-					Ok(()) => (None, None, Some(x_matrix.origin), None),
+					Ok(()) => {
+						// the server is evidently up, so stop backing off from it
+						if let Err(e) = services().sending.reset_backoff(&x_matrix.origin) {
+							warn!("Failed to reset backoff for {}: {e}", x_matrix.origin);
+						}
+
+						(None, None, Some(x_matrix.origin), None)
+					},
 					Err(e) => {
 						warn!("Failed to verify json request from {}: {e}\n{request_map:?}", x_matrix.origin);
 
@@ -275,6 +334,23 @@ where
 			},
 		};
 
+		if let Some(user_id) = &sender_user {
+			// locked accounts (MSC3939) can only log out
+			if services().users.is_locked(user_id)?
+				&& !is_request::<T>(&[TypeId::of::<logout::v3::Request>(), TypeId::of::<logout_all::v3::Request>()])
+			{
+				return Err(Error::BadRequest(ErrorKind::UserLocked, "This account has been locked."));
+			}
+
+			// suspended accounts (MSC3823) can't upload media, sending events and joining
+			// rooms is refused when the events are built
+			if services().users.is_suspended(user_id)?
+				&& is_request::<T>(&[TypeId::of::<create_content::v3::Request>()])
+			{
+				return Err(Error::user_suspended());
+			}
+		}
+
 		let mut http_request = Request::builder().uri(parts.uri).method(parts.method);
 		*http_request.headers_mut().unwrap() = parts.headers;
 
@@ -333,6 +409,10 @@ where
 	}
 }
 
+/// Whether the request is one of `requests`, so restrictions can be decided
+/// per route instead of by matching paths
+fn is_request<T: 'static>(requests: &[TypeId]) -> bool { requests.contains(&TypeId::of::<T>()) }
+
 struct XMatrix {
 	origin: OwnedServerName,
 	destination: Option<String>,
@@ -1,11 +1,7 @@
 #![allow(deprecated)]
 // Conduit implements the older APIs
 
-use std::{
-	collections::BTreeMap,
-	sync::Arc,
-	time::{Duration, Instant, SystemTime},
-};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
 
 use axum::{response::IntoResponse, Json};
 use get_profile_information::v1::ProfileField;
@@ -18,7 +14,10 @@ use ruma::{
 			backfill::get_backfill,
 			device::get_devices::{self, v1::UserDevice},
 			directory::{get_public_rooms, get_public_rooms_filtered},
-			discovery::{discover_homeserver, get_server_keys, get_server_version, ServerSigningKeys, VerifyKey},
+			discovery::{
+				discover_homeserver, get_remote_server_keys, get_remote_server_keys_batch, get_server_keys,
+				get_server_version, ServerSigningKeys,
+			},
 			event::{get_event, get_missing_events, get_room_state, get_room_state_ids},
 			keys::{claim_keys, get_keys},
 			membership::{
@@ -42,10 +41,10 @@ use ruma::{
 		},
 		StateEventType, TimelineEventType,
 	},
-	serde::{Base64, JsonObject, Raw},
+	serde::{JsonObject, Raw},
 	to_device::DeviceIdOrAllDevices,
 	uint, user_id, CanonicalJsonObject, CanonicalJsonValue, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId,
-	OwnedRoomId, OwnedServerName, OwnedServerSigningKeyId, OwnedUserId, RoomId, RoomVersionId, ServerName,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
 };
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
 use tokio::sync::RwLock;
@@ -54,7 +53,11 @@ use tracing::{debug, error, trace, warn};
 use crate::{
 	api::client_server::{self, claim_keys_helper, get_keys_helper},
 	debug_error,
-	service::pdu::{gen_event_id_canonical_json, PduBuilder},
+	service::{
+		event_policy::PolicyCheck,
+		federation_queue::QueuedPdu,
+		pdu::{gen_event_id_canonical_json, PduBuilder},
+	},
 	services,
 	utils::{self, server_name::server_is_ours, user_id::user_is_local},
 	Error, PduEvent, Result, Ruma,
@@ -84,28 +87,9 @@ pub(crate) async fn get_server_version_route(
 // Response type for this endpoint is Json because we need to calculate a
 // signature for the response
 pub(crate) async fn get_server_keys_route() -> Result<impl IntoResponse> {
-	let mut verify_keys: BTreeMap<OwnedServerSigningKeyId, VerifyKey> = BTreeMap::new();
-	verify_keys.insert(
-		format!("ed25519:{}", services().globals.keypair().version())
-			.try_into()
-			.expect("found invalid server signing keys in DB"),
-		VerifyKey {
-			key: Base64::new(services().globals.keypair().public_key().to_vec()),
-		},
-	);
 	let mut response = serde_json::from_slice(
 		get_server_keys::v2::Response {
-			server_key: Raw::new(&ServerSigningKeys {
-				server_name: services().globals.server_name().to_owned(),
-				verify_keys,
-				old_verify_keys: BTreeMap::new(),
-				signatures: BTreeMap::new(),
-				valid_until_ts: MilliSecondsSinceUnixEpoch::from_system_time(
-					SystemTime::now() + Duration::from_secs(86400 * 7),
-				)
-				.expect("time is valid"),
-			})
-			.expect("static conversion, no errors"),
+			server_key: Raw::new(&services().globals.own_server_keys()?).expect("static conversion, no errors"),
 		}
 		.try_into_http_response::<Vec<u8>>()
 		.unwrap()
@@ -115,7 +99,7 @@ pub(crate) async fn get_server_keys_route() -> Result<impl IntoResponse> {
 
 	ruma::signatures::sign_json(
 		services().globals.server_name().as_str(),
-		services().globals.keypair(),
+		&*services().globals.keypair(),
 		&mut response,
 	)
 	.unwrap();
@@ -132,6 +116,107 @@ pub(crate) async fn get_server_keys_route() -> Result<impl IntoResponse> {
 /// forever.
 pub(crate) async fn get_server_keys_deprecated_route() -> impl IntoResponse { get_server_keys_route().await }
 
+/// # `POST /_matrix/key/v2/query`
+///
+/// Gets the public signing keys of other servers, acting as a notary.
+pub(crate) async fn get_remote_server_keys_batch_route(
+	body: Ruma<get_remote_server_keys_batch::v2::Request>,
+) -> Result<get_remote_server_keys_batch::v2::Response> {
+	let mut server_keys = Vec::new();
+	for (server_name, criteria) in &body.server_keys {
+		let minimum_valid_until_ts = criteria
+			.values()
+			.filter_map(|criteria| criteria.minimum_valid_until_ts)
+			.max();
+
+		server_keys.extend(notary_server_keys(server_name, minimum_valid_until_ts).await?);
+	}
+
+	Ok(get_remote_server_keys_batch::v2::Response {
+		server_keys,
+	})
+}
+
+/// # `GET /_matrix/key/v2/query/{serverName}`
+///
+/// Gets the public signing keys of another server, acting as a notary.
+pub(crate) async fn get_remote_server_keys_route(
+	body: Ruma<get_remote_server_keys::v2::Request>,
+) -> Result<get_remote_server_keys::v2::Response> {
+	Ok(get_remote_server_keys::v2::Response {
+		server_keys: notary_server_keys(&body.server_name, Some(body.minimum_valid_until_ts)).await?,
+	})
+}
+
+/// Returns the keys of the server from our cache, refreshing them from the
+/// server itself if they would expire before `minimum_valid_until_ts`. The
+/// origin's signatures are kept and ours is added.
+async fn notary_server_keys(
+	server_name: &ServerName, minimum_valid_until_ts: Option<MilliSecondsSinceUnixEpoch>,
+) -> Result<Vec<Raw<ServerSigningKeys>>> {
+	let keys = if server_is_ours(server_name) {
+		Some(Raw::new(&services().globals.own_server_keys()?).expect("static conversion, no errors"))
+	} else {
+		let minimum_valid_until_ts = minimum_valid_until_ts.unwrap_or_else(MilliSecondsSinceUnixEpoch::now);
+		let cached = services().globals.signed_server_keys(server_name)?;
+		if cached
+			.as_ref()
+			.and_then(|keys| keys.deserialize().ok())
+			.is_some_and(|keys| keys.valid_until_ts >= minimum_valid_until_ts)
+		{
+			cached
+		} else {
+			debug!("Refreshing signing keys of {server_name} for notary request");
+			match services()
+				.sending
+				.send_federation_request(server_name, get_server_keys::v2::Request::new())
+				.await
+				.and_then(|response| {
+					let keys = response
+						.server_key
+						.deserialize()
+						.map_err(|_| Error::BadServerResponse("Invalid server key response."))?;
+					Ok((keys, response.server_key))
+				}) {
+				Ok((keys, raw_keys)) if keys.server_name == server_name => {
+					services()
+						.globals
+						.add_signed_server_keys(server_name, &raw_keys)?;
+					services().globals.add_signing_key(server_name, keys)?;
+					Some(raw_keys)
+				},
+				Ok(_) => {
+					debug_error!("{server_name} returned keys for another server");
+					cached
+				},
+				Err(e) => {
+					debug_error!("Failed to fetch signing keys of {server_name}: {e}");
+					cached
+				},
+			}
+		}
+	};
+
+	let Some(keys) = keys else {
+		return Ok(Vec::new());
+	};
+
+	// Parsed from the JSON we received, so the origin's signatures still match
+	let mut keys: CanonicalJsonObject =
+		serde_json::from_str(keys.json().get()).map_err(|_| Error::bad_database("Invalid server keys."))?;
+
+	ruma::signatures::sign_json(
+		services().globals.server_name().as_str(),
+		&*services().globals.keypair(),
+		&mut keys,
+	)
+	.map_err(|_| Error::bad_database("Failed to sign server keys."))?;
+
+	Ok(vec![Raw::from_json(
+		to_raw_value(&keys).expect("CanonicalJsonObject is valid JSON"),
+	)])
+}
+
 /// # `POST /_matrix/federation/v1/publicRooms`
 ///
 /// Lists the public rooms on this server.
@@ -231,31 +316,28 @@ pub(crate) async fn send_transaction_message_route(
 		.as_ref()
 		.expect("server is authenticated");
 
-	// This is all the auth_events that have been recursively fetched so they don't
-	// have to be deserialized over and over again.
-	// TODO: make this persist across requests but not in a DB Tree (in globals?)
-	// TODO: This could potentially also be some sort of trie (suffix tree) like
-	// structure so that once an auth event is known it would know (using indexes
-	// maybe) all of the auth events that it references.
-	// let mut auth_cache = EventMap::new();
-
 	let txn_start_time = Instant::now();
-	let mut parsed_pdus = Vec::with_capacity(body.pdus.len());
+	let mut queued_pdus = Vec::with_capacity(body.pdus.len());
 	for pdu in &body.pdus {
-		parsed_pdus.push(match parse_incoming_pdu(pdu) {
-			Ok(t) => t,
+		match parse_incoming_pdu(pdu) {
+			Ok((event_id, pdu, room_id)) => queued_pdus.push(QueuedPdu {
+				origin: sender_servername.clone(),
+				room_id,
+				event_id,
+				pdu,
+			}),
 			Err(e) => {
 				warn!("Could not parse PDU: {e}");
 				continue;
 			},
-		});
+		};
 
 		// We do not add the event_id field to the pdu here because of signature
 		// and hashes checks
 	}
 
 	trace!(
-		pdus = ?parsed_pdus.len(),
+		pdus = ?queued_pdus.len(),
 		edus = ?body.edus.len(),
 		elapsed = ?txn_start_time.elapsed(),
 		id = ?body.transaction_id,
@@ -263,63 +345,16 @@ pub(crate) async fn send_transaction_message_route(
 		"Starting txn",
 	);
 
-	// We go through all the signatures we see on the PDUs and fetch the
-	// corresponding signing keys
-	let pub_key_map = RwLock::new(BTreeMap::new());
-	if !parsed_pdus.is_empty() {
-		services()
-			.rooms
-			.event_handler
-			.fetch_required_signing_keys(parsed_pdus.iter().map(|(_event_id, event, _room_id)| event), &pub_key_map)
-			.await
-			.unwrap_or_else(|e| {
-				warn!("Could not fetch all signatures for PDUs from {}: {:?}", sender_servername, e);
-			});
-
-		debug!(
-			elapsed = ?txn_start_time.elapsed(),
-			"Fetched signing keys"
-		);
-	}
-
-	let mut resolved_map = BTreeMap::new();
-	for (event_id, value, room_id) in parsed_pdus {
-		let pdu_start_time = Instant::now();
-		let mutex = Arc::clone(
-			services()
-				.globals
-				.roomid_mutex_federation
-				.write()
-				.await
-				.entry(room_id.clone())
-				.or_default(),
-		);
-		let mutex_lock = mutex.lock().await;
-		resolved_map.insert(
-			event_id.clone(),
-			services()
-				.rooms
-				.event_handler
-				.handle_incoming_pdu(sender_servername, &room_id, &event_id, value, true, &pub_key_map)
-				.await
-				.map(|_| ()),
-		);
-		drop(mutex_lock);
-
-		debug!(
-			pdu_elapsed = ?pdu_start_time.elapsed(),
-			txn_elapsed = ?txn_start_time.elapsed(),
-			"Finished PDU {event_id}",
-		);
-	}
+	// The pdus are handled by the room workers, so the remote does not have to wait
+	// for us to resolve them. If the queues are full it has to retry the whole
+	// transaction later.
+	services().federation_queue.queue(&queued_pdus)?;
 
-	for pdu in &resolved_map {
-		if let Err(e) = pdu.1 {
-			if matches!(e, Error::BadRequest(ErrorKind::NotFound, _)) {
-				warn!("Incoming PDU failed {:?}", pdu);
-			}
-		}
-	}
+	debug!(
+		elapsed = ?txn_start_time.elapsed(),
+		"Queued {} pdus",
+		queued_pdus.len()
+	);
 
 	for edu in body
 		.edus
@@ -501,9 +536,9 @@ pub(crate) async fn send_transaction_message_route(
 	);
 
 	Ok(send_transaction_message::v1::Response {
-		pdus: resolved_map
+		pdus: queued_pdus
 			.into_iter()
-			.map(|(e, r)| (e, r.map_err(|e| e.sanitized_error())))
+			.map(|queued| (queued.event_id, Ok(())))
 			.collect(),
 	})
 }
@@ -890,6 +925,14 @@ pub(crate) async fn create_join_event_template_route(
 		.event_handler
 		.acl_check(sender_servername, &body.room_id)?;
 
+	if let Some(rule) = services().rooms.policy.user_rule(&body.user_id).await? {
+		warn!(
+			"{} tried joining {} but is banned by policy room {}: {}",
+			&body.user_id, &body.room_id, rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "User is banned by a policy list."));
+	}
+
 	if services()
 		.globals
 		.config
@@ -1122,9 +1165,23 @@ async fn create_join_event(
 		));
 	};
 
+	let sender = value
+		.get("sender")
+		.and_then(CanonicalJsonValue::as_str)
+		.and_then(|sender| UserId::parse(sender).ok())
+		.ok_or(Error::BadRequest(ErrorKind::InvalidParam, "Event has an invalid sender."))?;
+
+	if let Some(rule) = services().rooms.policy.user_rule(&sender).await? {
+		warn!(
+			"{sender} tried joining {room_id} but is banned by policy room {}: {}",
+			rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "User is banned by a policy list."));
+	}
+
 	ruma::signatures::hash_and_sign_event(
 		services().globals.server_name().as_str(),
-		services().globals.keypair(),
+		&*services().globals.keypair(),
 		&mut value,
 		&room_version_id,
 	)
@@ -1558,7 +1615,7 @@ pub(crate) async fn create_invite_route(body: Ruma<create_invite::v2::Request>)
 
 	ruma::signatures::hash_and_sign_event(
 		services().globals.server_name().as_str(),
-		services().globals.keypair(),
+		&*services().globals.keypair(),
 		&mut signed_event,
 		&body.room_version,
 	)
@@ -1593,6 +1650,27 @@ pub(crate) async fn create_invite_route(body: Ruma<create_invite::v2::Request>)
 	)
 	.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "state_key is not a user id."))?;
 
+	if let Some(rule) = services().rooms.policy.user_rule(&sender).await? {
+		warn!(
+			"Received invite from {sender} for {invited_user} who is banned by policy room {}: {}",
+			rule.policy_room, rule.reason
+		);
+		return Err(Error::BadRequest(ErrorKind::forbidden(), "User is banned by a policy list."));
+	}
+
+	if services()
+		.rooms
+		.policy
+		.room_rule(&body.room_id)
+		.await?
+		.is_some()
+	{
+		return Err(Error::BadRequest(
+			ErrorKind::forbidden(),
+			"This room is banned by a policy list.",
+		));
+	}
+
 	if services().rooms.metadata.is_banned(&body.room_id)? && !services().users.is_admin(&invited_user)? {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
@@ -1600,6 +1678,15 @@ pub(crate) async fn create_invite_route(body: Ruma<create_invite::v2::Request>)
 		));
 	}
 
+	services()
+		.event_policy
+		.enforce(&PolicyCheck::Invite {
+			sender: &sender,
+			invitee: &invited_user,
+			room_id: &body.room_id,
+		})
+		.await?;
+
 	if services().globals.block_non_admin_invites() && !services().users.is_admin(&invited_user)? {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
@@ -9,6 +9,18 @@ pub(crate) fn check(config: &Config) -> Result<(), Error> {
 	config.warn_deprecated();
 	config.warn_unknown_key();
 
+	if config.max_queued_incoming_pdus == 0 || config.max_queued_incoming_pdus_per_room == 0 {
+		return Err(Error::bad_config(
+			"max_queued_incoming_pdus and max_queued_incoming_pdus_per_room must be greater than 0.",
+		));
+	}
+
+	if config.sender_shards == 0 || config.sender_shard_max_inflight == 0 {
+		return Err(Error::bad_config(
+			"sender_shards and sender_shard_max_inflight must be greater than 0.",
+		));
+	}
+
 	if config.sentry && config.sentry_endpoint.is_none() {
 		return Err(Error::bad_config("Sentry cannot be enabled without an endpoint set"));
 	}
@@ -129,6 +141,25 @@ For security and safety reasons, conduwuit will shut down. If you are extra sure
 		);
 	}
 
+	if config.registration_requires_email && config.smtp.is_none() {
+		return Err(Error::bad_config(
+			"Registration requires an email address but SMTP is not configured. Please configure the \"smtp\" section \
+			 or disable \"registration_requires_email\".",
+		));
+	}
+
+	if let Some(smtp) = &config.smtp {
+		if smtp.host.is_none() && smtp.file_sink_path.is_none() {
+			return Err(Error::bad_config(
+				"SMTP is configured without a \"host\" or \"file_sink_path\" to deliver emails to.",
+			));
+		}
+
+		if smtp.from.parse::<lettre::message::Mailbox>().is_err() {
+			return Err(Error::bad_config("SMTP \"from\" is not a valid mailbox."));
+		}
+	}
+
 	if config.allow_outgoing_presence && !config.allow_local_presence {
 		return Err(Error::bad_config(
 			"Outgoing presence requires allowing local presence. Please enable \"allow_local_presence\".",
@@ -139,10 +139,20 @@ pub(crate) struct Config {
 	pub(crate) sender_idle_timeout: u64,
 	#[serde(default = "default_sender_retry_backoff_limit")]
 	pub(crate) sender_retry_backoff_limit: u64,
+	#[serde(default = "default_max_queued_incoming_pdus")]
+	pub(crate) max_queued_incoming_pdus: usize,
+	#[serde(default = "default_max_queued_incoming_pdus_per_room")]
+	pub(crate) max_queued_incoming_pdus_per_room: usize,
+	#[serde(default = "default_sender_shards")]
+	pub(crate) sender_shards: usize,
+	#[serde(default = "default_sender_shard_max_inflight")]
+	pub(crate) sender_shard_max_inflight: usize,
 	#[serde(default = "default_appservice_timeout")]
 	pub(crate) appservice_timeout: u64,
 	#[serde(default = "default_appservice_idle_timeout")]
 	pub(crate) appservice_idle_timeout: u64,
+	#[serde(default)]
+	pub(crate) app_service_config_files: Vec<PathBuf>,
 	#[serde(default = "default_pusher_idle_timeout")]
 	pub(crate) pusher_idle_timeout: u64,
 
@@ -151,6 +161,8 @@ pub(crate) struct Config {
 	#[serde(default)]
 	pub(crate) yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse: bool,
 	pub(crate) registration_token: Option<String>,
+	#[serde(default = "default_access_token_ttl")]
+	pub(crate) access_token_ttl: u64,
 	#[serde(default = "true_fn")]
 	pub(crate) allow_encryption: bool,
 	#[serde(default = "true_fn")]
@@ -160,6 +172,10 @@ pub(crate) struct Config {
 	#[serde(default)]
 	pub(crate) allow_public_room_directory_without_auth: bool,
 	#[serde(default)]
+	pub(crate) user_directory_search_all_users: bool,
+	#[serde(default = "true_fn")]
+	pub(crate) user_directory_prefix_search: bool,
+	#[serde(default)]
 	pub(crate) lockdown_public_room_directory: bool,
 	#[serde(default)]
 	pub(crate) allow_device_name_federation: bool,
@@ -249,6 +265,8 @@ pub(crate) struct Config {
 
 	#[serde(default = "default_notification_push_path")]
 	pub(crate) notification_push_path: String,
+	#[serde(default = "default_notification_log_ttl")]
+	pub(crate) notification_log_ttl: u64,
 
 	#[serde(default = "true_fn")]
 	pub(crate) allow_local_presence: bool,
@@ -262,6 +280,8 @@ pub(crate) struct Config {
 	pub(crate) presence_offline_timeout_s: u64,
 	#[serde(default = "true_fn")]
 	pub(crate) presence_timeout_remote_users: bool,
+	#[serde(default = "default_presence_max_room_members")]
+	pub(crate) presence_max_room_members: u64,
 
 	#[serde(default = "true_fn")]
 	pub(crate) allow_incoming_read_receipts: bool,
@@ -299,6 +319,18 @@ pub(crate) struct Config {
 	pub(crate) forbidden_remote_server_names: Vec<OwnedServerName>,
 	#[serde(default = "Vec::new")]
 	pub(crate) forbidden_remote_room_directory_server_names: Vec<OwnedServerName>,
+	#[serde(default = "Vec::new")]
+	pub(crate) policy_rooms: Vec<OwnedRoomId>,
+
+	pub(crate) event_policy_webhook: Option<Url>,
+	pub(crate) event_policy_webhook_token: Option<String>,
+	#[serde(default = "default_event_policy_webhook_timeout")]
+	pub(crate) event_policy_webhook_timeout: u64,
+	#[serde(default = "true_fn")]
+	pub(crate) event_policy_webhook_fail_open: bool,
+
+	#[serde(default = "true_fn")]
+	pub(crate) report_notifications: bool,
 
 	#[serde(default = "default_ip_range_denylist")]
 	pub(crate) ip_range_denylist: Vec<String>,
@@ -332,6 +364,17 @@ pub(crate) struct Config {
 	#[serde(default)]
 	pub(crate) block_non_admin_invites: bool,
 
+	#[serde(default)]
+	pub(crate) allow_faster_joins: bool,
+
+	pub(crate) smtp: Option<SmtpConfig>,
+	#[serde(default)]
+	pub(crate) registration_requires_email: bool,
+	#[serde(default = "default_email_validation_token_ttl")]
+	pub(crate) email_validation_token_ttl: u64,
+	#[serde(default = "default_email_notification_delay")]
+	pub(crate) email_notification_delay: u64,
+
 	#[serde(default)]
 	pub(crate) sentry: bool,
 	#[serde(default = "default_sentry_endpoint")]
@@ -357,6 +400,32 @@ pub(crate) struct TlsConfig {
 	pub(crate) dual_protocol: bool,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct SmtpConfig {
+	/// Mailbox emails are sent from, e.g. `conduwuit <noreply@example.com>`
+	pub(crate) from: String,
+	pub(crate) host: Option<String>,
+	pub(crate) port: Option<u16>,
+	#[serde(default)]
+	pub(crate) security: SmtpSecurity,
+	pub(crate) username: Option<String>,
+	pub(crate) password: Option<String>,
+	/// Write emails as files to this directory instead of sending them, for
+	/// testing
+	pub(crate) file_sink_path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SmtpSecurity {
+	/// Plaintext connection, only suitable for a local relay or test sink
+	None,
+	#[default]
+	StartTls,
+	/// Implicit TLS
+	Tls,
+}
+
 #[derive(Clone, Debug, Deserialize, Default)]
 pub(crate) struct WellKnownConfig {
 	pub(crate) client: Option<Url>,
@@ -549,8 +618,25 @@ impl fmt::Display for Config {
 			("Federation pool idle timeout", &self.federation_idle_timeout.to_string()),
 			("Sender timeout", &self.sender_timeout.to_string()),
 			("Sender pool idle timeout", &self.sender_idle_timeout.to_string()),
+			("Sender shards", &self.sender_shards.to_string()),
+			("Maximum queued incoming PDUs", &self.max_queued_incoming_pdus.to_string()),
+			(
+				"Maximum queued incoming PDUs per room",
+				&self.max_queued_incoming_pdus_per_room.to_string(),
+			),
+			(
+				"Sender maximum in-flight transactions per shard",
+				&self.sender_shard_max_inflight.to_string(),
+			),
 			("Appservice timeout", &self.appservice_timeout.to_string()),
 			("Appservice pool idle timeout", &self.appservice_idle_timeout.to_string()),
+			("Appservice config files", {
+				&self
+					.app_service_config_files
+					.iter()
+					.map(|path| path.display())
+					.join(", ")
+			}),
 			("Pusher pool idle timeout", &self.pusher_idle_timeout.to_string()),
 			("Allow registration", &self.allow_registration.to_string()),
 			(
@@ -560,6 +646,7 @@ impl fmt::Display for Config {
 					None => "not set (open registration!)",
 				},
 			),
+			("Refreshable access token lifetime", &self.access_token_ttl.to_string()),
 			(
 				"Allow guest registration (inherently false if allow registration is false)",
 				&self.allow_guest_registration.to_string(),
@@ -587,6 +674,10 @@ impl fmt::Display for Config {
 				"Allow local presence requests (updates)",
 				&self.allow_local_presence.to_string(),
 			),
+			(
+				"Maximum members of rooms presence is shared through",
+				&self.presence_max_room_members.to_string(),
+			),
 			(
 				"Allow incoming remote read receipts",
 				&self.allow_incoming_read_receipts.to_string(),
@@ -599,6 +690,17 @@ impl fmt::Display for Config {
 				"Block non-admin room invites (local and remote, admins can still send and receive invites)",
 				&self.block_non_admin_invites.to_string(),
 			),
+			(
+				"SMTP email sending",
+				match &self.smtp {
+					Some(smtp) if smtp.file_sink_path.is_some() => "file sink",
+					Some(_) => "enabled",
+					None => "disabled",
+				},
+			),
+			("Registration requires email", &self.registration_requires_email.to_string()),
+			("Email validation token lifetime", &self.email_validation_token_ttl.to_string()),
+			("Email notification delay", &self.email_notification_delay.to_string()),
 			("Allow outgoing federated typing", &self.allow_outgoing_typing.to_string()),
 			("Allow incoming federated typing", &self.allow_incoming_typing.to_string()),
 			(
@@ -613,11 +715,17 @@ impl fmt::Display for Config {
 				&self.allow_profile_lookup_federation_requests.to_string(),
 			),
 			("Notification push path", &self.notification_push_path),
+			("Notification log TTL", &self.notification_log_ttl.to_string()),
 			("Allow room creation", &self.allow_room_creation.to_string()),
 			(
 				"Allow public room directory over federation",
 				&self.allow_public_room_directory_over_federation.to_string(),
 			),
+			(
+				"User directory searches all users",
+				&self.user_directory_search_all_users.to_string(),
+			),
+			("User directory prefix search", &self.user_directory_prefix_search.to_string()),
 			(
 				"Allow public room directory without authentication",
 				&self.allow_public_room_directory_without_auth.to_string(),
@@ -756,6 +864,26 @@ impl fmt::Display for Config {
 				}
 				&lst.join(", ")
 			}),
+			("Policy Rooms", {
+				let mut lst = vec![];
+				for room in &self.policy_rooms {
+					lst.push(room);
+				}
+				&lst.into_iter().join(", ")
+			}),
+			(
+				"Event policy webhook",
+				&self
+					.event_policy_webhook
+					.as_ref()
+					.map_or_else(|| "not set".to_owned(), ToString::to_string),
+			),
+			("Event policy webhook timeout", &self.event_policy_webhook_timeout.to_string()),
+			(
+				"Event policy webhook fails open",
+				&self.event_policy_webhook_fail_open.to_string(),
+			),
+			("Report notifications", &self.report_notifications.to_string()),
 			("Forbidden Remote Room Directory Server Names", {
 				let mut lst = vec![];
 				for domain in &self.forbidden_remote_room_directory_server_names {
@@ -800,6 +928,7 @@ impl fmt::Display for Config {
 				&self.allow_check_for_updates.to_string(),
 			),
 			("Enable netburst on startup", &self.startup_netburst.to_string()),
+			("Allow faster room joins", &self.allow_faster_joins.to_string()),
 			#[cfg(feature = "sentry_telemetry")]
 			("Sentry.io reporting and tracing", &self.sentry.to_string()),
 			#[cfg(feature = "sentry_telemetry")]
@@ -952,6 +1081,14 @@ fn default_sender_idle_timeout() -> u64 { 180 }
 
 fn default_sender_retry_backoff_limit() -> u64 { 86400 }
 
+fn default_max_queued_incoming_pdus() -> usize { 20_000 }
+
+fn default_max_queued_incoming_pdus_per_room() -> usize { 2_000 }
+
+fn default_sender_shards() -> usize { 4 }
+
+fn default_sender_shard_max_inflight() -> usize { 64 }
+
 fn default_appservice_timeout() -> u64 { 35 }
 
 fn default_appservice_idle_timeout() -> u64 { 300 }
@@ -966,6 +1103,8 @@ fn default_tracing_flame_filter() -> String { "trace,h2=off".to_owned() }
 #[cfg(feature = "perf_measurements")]
 fn default_tracing_flame_output_path() -> String { "./tracing.folded".to_owned() }
 
+fn default_event_policy_webhook_timeout() -> u64 { 5 }
+
 fn default_trusted_servers() -> Vec<OwnedServerName> { vec![OwnedServerName::try_from("matrix.org").unwrap()] }
 
 fn default_log() -> String {
@@ -979,12 +1118,22 @@ fn default_log() -> String {
 
 fn default_notification_push_path() -> String { "/_matrix/push/v1/notify".to_owned() }
 
+fn default_notification_log_ttl() -> u64 { 60 * 60 * 24 * 30 }
+
 fn default_turn_ttl() -> u64 { 60 * 60 * 24 }
 
+fn default_access_token_ttl() -> u64 { 60 * 60 }
+
+fn default_email_validation_token_ttl() -> u64 { 60 * 60 * 24 }
+
+fn default_email_notification_delay() -> u64 { 60 * 10 }
+
 fn default_presence_idle_timeout_s() -> u64 { 5 * 60 }
 
 fn default_presence_offline_timeout_s() -> u64 { 30 * 60 }
 
+fn default_presence_max_room_members() -> u64 { 100 }
+
 fn default_typing_federation_timeout_s() -> u64 { 30 }
 
 fn default_typing_client_timeout_min_s() -> u64 { 15 }
@@ -134,4 +134,28 @@ impl service::account_data::Data for KeyValueDatabase {
 
 		Ok(userdata)
 	}
+
+	/// Removes all account data of the user in the room, or the global account
+	/// data if no room is given.
+	#[tracing::instrument(skip(self, room_id, user_id))]
+	fn remove_all(&self, room_id: Option<&RoomId>, user_id: &UserId) -> Result<()> {
+		let mut prefix = room_id
+			.map(ToString::to_string)
+			.unwrap_or_default()
+			.as_bytes()
+			.to_vec();
+		prefix.push(0xFF);
+		prefix.extend_from_slice(user_id.as_bytes());
+		prefix.push(0xFF);
+
+		for (key, _) in self.roomuserdataid_accountdata.scan_prefix(prefix.clone()) {
+			self.roomuserdataid_accountdata.remove(&key)?;
+		}
+
+		for (key, _) in self.roomusertype_roomuserdataid.scan_prefix(prefix) {
+			self.roomusertype_roomuserdataid.remove(&key)?;
+		}
+
+		Ok(())
+	}
 }
@@ -1,13 +1,26 @@
 use ruma::api::appservice::Registration;
 
-use crate::{database::KeyValueDatabase, service, utils, Error, Result};
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, appservice::RegistrationExtensions},
+	utils, Error, Result,
+};
 
 impl service::appservice::Data for KeyValueDatabase {
 	/// Registers an appservice and returns the ID to the caller
-	fn register_appservice(&self, yaml: Registration) -> Result<String> {
+	fn register_appservice(&self, yaml: Registration, extensions: RegistrationExtensions) -> Result<String> {
 		let id = yaml.id.as_str();
+
+		// ruma doesn't know the MSC options, they are stored next to the registration
+		let mut registration = serde_yaml::to_value(&yaml).unwrap();
+		if let (Some(registration), serde_yaml::Value::Mapping(extensions)) =
+			(registration.as_mapping_mut(), serde_yaml::to_value(extensions).unwrap())
+		{
+			registration.extend(extensions);
+		}
+
 		self.id_appserviceregistrations
-			.insert(id.as_bytes(), serde_yaml::to_string(&yaml).unwrap().as_bytes())?;
+			.insert(id.as_bytes(), serde_yaml::to_string(&registration).unwrap().as_bytes())?;
 
 		Ok(id.to_owned())
 	}
@@ -20,6 +33,8 @@ impl service::appservice::Data for KeyValueDatabase {
 	fn unregister_appservice(&self, service_name: &str) -> Result<()> {
 		self.id_appserviceregistrations
 			.remove(service_name.as_bytes())?;
+		self.appserviceid_configfile
+			.remove(service_name.as_bytes())?;
 		Ok(())
 	}
 
@@ -33,6 +48,15 @@ impl service::appservice::Data for KeyValueDatabase {
 			.transpose()
 	}
 
+	fn get_extensions(&self, id: &str) -> Result<RegistrationExtensions> {
+		self.id_appserviceregistrations
+			.get(id.as_bytes())?
+			.map_or(Ok(RegistrationExtensions::default()), |bytes| {
+				serde_yaml::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid registration bytes in id_appserviceregistrations."))
+			})
+	}
+
 	fn iter_ids<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<String>> + 'a>> {
 		Ok(Box::new(self.id_appserviceregistrations.iter().map(|(id, _)| {
 			utils::string_from_bytes(&id)
@@ -52,4 +76,27 @@ impl service::appservice::Data for KeyValueDatabase {
 			})
 			.collect()
 	}
+
+	fn set_config_file(&self, id: &str, path: Option<&str>) -> Result<()> {
+		match path {
+			Some(path) => self
+				.appserviceid_configfile
+				.insert(id.as_bytes(), path.as_bytes()),
+			None => self.appserviceid_configfile.remove(id.as_bytes()),
+		}
+	}
+
+	fn config_files(&self) -> Result<Vec<(String, String)>> {
+		self.appserviceid_configfile
+			.iter()
+			.map(|(id, path)| {
+				Ok((
+					utils::string_from_bytes(&id)
+						.map_err(|_| Error::bad_database("Invalid id bytes in appserviceid_configfile."))?,
+					utils::string_from_bytes(&path)
+						.map_err(|_| Error::bad_database("Invalid path bytes in appserviceid_configfile."))?,
+				))
+			})
+			.collect()
+	}
 }
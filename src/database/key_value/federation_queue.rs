@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use ruma::{OwnedRoomId, RoomId};
+
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, federation_queue::QueuedPdu},
+	services, utils, Error, Result,
+};
+
+impl service::federation_queue::Data for KeyValueDatabase {
+	fn queue_incoming_pdus(&self, pdus: &[QueuedPdu]) -> Result<Vec<Vec<u8>>> {
+		let mut batch = Vec::with_capacity(pdus.len());
+		let mut keys = Vec::with_capacity(pdus.len());
+		for pdu in pdus {
+			let mut key = pdu.room_id.as_bytes().to_vec();
+			key.push(0xFF);
+			key.extend_from_slice(&services().globals.next_count()?.to_be_bytes());
+
+			batch.push((key.clone(), serde_json::to_vec(pdu).expect("QueuedPdu can be serialized")));
+			keys.push(key);
+		}
+
+		self.roomid_incomingpdu
+			.insert_batch(&mut batch.into_iter())?;
+
+		Ok(keys)
+	}
+
+	fn next_incoming_pdu(&self, room_id: &RoomId) -> Result<Option<(Vec<u8>, QueuedPdu)>> {
+		let mut prefix = room_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		self.roomid_incomingpdu
+			.scan_prefix(prefix)
+			.next()
+			.map(|(key, value)| {
+				let queued = serde_json::from_slice(&value)
+					.map_err(|_| Error::bad_database("Invalid queued pdu in roomid_incomingpdu."))?;
+				Ok((key, queued))
+			})
+			.transpose()
+	}
+
+	fn remove_incoming_pdu(&self, key: &[u8]) -> Result<()> { self.roomid_incomingpdu.remove(key) }
+
+	fn queued_incoming_rooms(&self) -> Result<Vec<(OwnedRoomId, usize)>> {
+		let mut rooms = BTreeMap::<OwnedRoomId, usize>::new();
+		for (key, _) in self.roomid_incomingpdu.iter() {
+			let room_id = key
+				.split(|&b| b == 0xFF)
+				.next()
+				.and_then(|bytes| utils::string_from_bytes(bytes).ok())
+				.and_then(|room_id| OwnedRoomId::try_from(room_id).ok())
+				.ok_or_else(|| Error::bad_database("Invalid room id in roomid_incomingpdu."))?;
+
+			*rooms.entry(room_id).or_default() += 1;
+		}
+
+		Ok(rooms.into_iter().collect())
+	}
+}
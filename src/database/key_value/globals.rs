@@ -4,7 +4,8 @@ use async_trait::async_trait;
 use futures_util::{stream::FuturesUnordered, StreamExt};
 use lru_cache::LruCache;
 use ruma::{
-	api::federation::discovery::{ServerSigningKeys, VerifyKey},
+	api::federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
+	serde::Raw,
 	signatures::Ed25519KeyPair,
 	DeviceId, MilliSecondsSinceUnixEpoch, OwnedServerSigningKeyId, ServerName, UserId,
 };
@@ -16,6 +17,7 @@ use crate::{
 
 const COUNTER: &[u8] = b"c";
 const LAST_CHECK_FOR_UPDATES_COUNT: &[u8] = b"u";
+const OLD_VERIFY_KEYS: &[u8] = b"old_verify_keys";
 
 #[async_trait]
 impl service::globals::Data for KeyValueDatabase {
@@ -225,6 +227,25 @@ lasttimelinecount_cache: {lasttimelinecount_cache} / {max_lasttimelinecount_cach
 
 	fn remove_keypair(&self) -> Result<()> { self.global.remove(b"keypair") }
 
+	fn replace_keypair(&self, keypair: &[u8]) -> Result<()> { self.global.insert(b"keypair", keypair) }
+
+	fn old_verify_keys(&self) -> Result<BTreeMap<OwnedServerSigningKeyId, OldVerifyKey>> {
+		self.global
+			.get(OLD_VERIFY_KEYS)?
+			.map_or(Ok(BTreeMap::new()), |bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid old verify keys in database."))
+			})
+	}
+
+	fn add_old_verify_key(&self, key_id: OwnedServerSigningKeyId, key: OldVerifyKey) -> Result<()> {
+		let mut keys = self.old_verify_keys()?;
+		keys.insert(key_id, key);
+		self.global.insert(
+			OLD_VERIFY_KEYS,
+			&serde_json::to_vec(&keys).expect("old verify keys can be serialized"),
+		)
+	}
+
 	fn add_signing_key(
 		&self, origin: &ServerName, new_keys: ServerSigningKeys,
 	) -> Result<BTreeMap<OwnedServerSigningKeyId, VerifyKey>> {
@@ -282,6 +303,33 @@ lasttimelinecount_cache: {lasttimelinecount_cache} / {max_lasttimelinecount_cach
 		Ok(signingkeys)
 	}
 
+	fn add_signed_server_keys(&self, origin: &ServerName, keys: &Raw<ServerSigningKeys>) -> Result<()> {
+		let Ok(new_keys) = keys.deserialize() else {
+			return Ok(());
+		};
+		if new_keys.server_name != origin {
+			return Ok(());
+		}
+
+		let newer = self
+			.signed_server_keys(origin)?
+			.and_then(|signed| signed.deserialize().ok())
+			.map_or(true, |signed| signed.valid_until_ts <= new_keys.valid_until_ts);
+		if newer {
+			self.server_signedkeys
+				.insert(origin.as_bytes(), keys.json().get().as_bytes())?;
+		}
+
+		Ok(())
+	}
+
+	fn signed_server_keys(&self, origin: &ServerName) -> Result<Option<Raw<ServerSigningKeys>>> {
+		Ok(self
+			.server_signedkeys
+			.get(origin.as_bytes())?
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok()))
+	}
+
 	fn database_version(&self) -> Result<u64> {
 		self.global.get(b"version")?.map_or(Ok(0), |version| {
 			utils::u64_from_bytes(&version).map_err(|_| Error::bad_database("Database version id is invalid."))
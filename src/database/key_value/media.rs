@@ -1,4 +1,4 @@
-use ruma::api::client::error::ErrorKind;
+use ruma::{api::client::error::ErrorKind, UserId};
 use tracing::debug;
 
 use crate::{
@@ -132,6 +132,16 @@ impl service::media::Data for KeyValueDatabase {
 		Ok((content_disposition, content_type, key))
 	}
 
+	fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<String> {
+		let user_id = user_id.as_bytes().to_vec();
+
+		self.mediaid_user
+			.iter()
+			.filter(|(_, user)| *user == user_id)
+			.filter_map(|(key, _)| string_from_bytes(&key).ok())
+			.collect()
+	}
+
 	/// Gets all the media keys in our database (this includes all the metadata
 	/// associated with it such as width, height, content-type, etc)
 	fn get_all_media_keys(&self) -> Result<Vec<Vec<u8>>> {
@@ -1,14 +1,18 @@
 mod account_data;
 //mod admin;
 mod appservice;
+mod federation_queue;
 mod globals;
 mod key_backups;
 mod media;
 //mod pdu;
 mod presence;
 mod pusher;
+mod reports;
 mod rooms;
 mod sending;
+mod threepid;
 mod transaction_ids;
 mod uiaa;
+mod user_directory;
 mod users;
@@ -1,9 +1,18 @@
+use std::mem::size_of;
+
 use ruma::{
 	api::client::push::{set_pusher, Pusher},
-	UserId,
+	OwnedUserId, UserId,
 };
 
-use crate::{database::KeyValueDatabase, service, utils, Error, Result};
+use crate::{
+	database::KeyValueDatabase,
+	service::{
+		self,
+		pusher::{EmailDigest, LoggedNotification},
+	},
+	utils, Error, Result,
+};
 
 impl service::pusher::Data for KeyValueDatabase {
 	fn set_pusher(&self, sender: &UserId, pusher: set_pusher::v3::PusherAction) -> Result<()> {
@@ -20,6 +29,7 @@ impl service::pusher::Data for KeyValueDatabase {
 				let mut key = sender.as_bytes().to_vec();
 				key.push(0xFF);
 				key.extend_from_slice(ids.pushkey.as_bytes());
+				self.senderkey_emaildigest.remove(&key)?;
 				self.senderkey_pusher.remove(&key).map_err(Into::into)
 			},
 		}
@@ -62,4 +72,107 @@ impl service::pusher::Data for KeyValueDatabase {
 			Ok(push_key_string)
 		}))
 	}
+
+	fn log_notification(&self, user_id: &UserId, count: u64, notification: &LoggedNotification) -> Result<()> {
+		let mut useridcount = user_id.as_bytes().to_vec();
+		useridcount.push(0xFF);
+		useridcount.extend_from_slice(&count.to_be_bytes());
+
+		self.useridcount_notification.insert(
+			&useridcount,
+			&serde_json::to_vec(notification).expect("LoggedNotification can be serialized"),
+		)?;
+
+		let mut key = notification.ts.to_be_bytes().to_vec();
+		key.extend_from_slice(&useridcount);
+		self.notificationts_useridcount.insert(&key, &[])
+	}
+
+	fn notifications_until<'a>(
+		&'a self, user_id: &UserId, until: u64,
+	) -> Box<dyn Iterator<Item = Result<(u64, LoggedNotification)>> + 'a> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		// Start right below `until`, iterating backwards
+		let mut current = prefix.clone();
+		current.extend_from_slice(&until.saturating_sub(1).to_be_bytes());
+
+		Box::new(
+			self.useridcount_notification
+				.iter_from(&current, true)
+				.take_while(move |(key, _)| key.starts_with(&prefix))
+				.map(|(key, value)| {
+					let count = utils::u64_from_bytes(&key[key.len() - size_of::<u64>()..])
+						.map_err(|_| Error::bad_database("Invalid count in useridcount_notification."))?;
+					let notification = serde_json::from_slice(&value)
+						.map_err(|_| Error::bad_database("Invalid notification in useridcount_notification."))?;
+
+					Ok((count, notification))
+				}),
+		)
+	}
+
+	fn prune_notifications(&self, ts: u64) -> Result<usize> {
+		let mut pruned: usize = 0;
+		for (key, _) in self
+			.notificationts_useridcount
+			.iter()
+			.take_while(|(key, _)| key.get(..size_of::<u64>()) < Some(&ts.to_be_bytes()[..]))
+		{
+			self.useridcount_notification
+				.remove(&key[size_of::<u64>()..])?;
+			self.notificationts_useridcount.remove(&key)?;
+			pruned = pruned.saturating_add(1);
+		}
+
+		Ok(pruned)
+	}
+
+	fn email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<Option<EmailDigest>> {
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(pushkey.as_bytes());
+
+		self.senderkey_emaildigest
+			.get(&key)?
+			.map(|bytes| serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid EmailDigest in db.")))
+			.transpose()
+	}
+
+	fn set_email_digest(&self, user_id: &UserId, pushkey: &str, digest: &EmailDigest) -> Result<()> {
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(pushkey.as_bytes());
+
+		self.senderkey_emaildigest
+			.insert(&key, &serde_json::to_vec(digest).expect("EmailDigest can be serialized"))
+	}
+
+	fn remove_email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<()> {
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(pushkey.as_bytes());
+
+		self.senderkey_emaildigest.remove(&key)
+	}
+
+	fn email_digests<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(OwnedUserId, String, EmailDigest)>> + 'a> {
+		Box::new(self.senderkey_emaildigest.iter().map(|(key, value)| {
+			let mut parts = key.splitn(2, |&b| b == 0xFF);
+			let user_id = parts
+				.next()
+				.and_then(|bytes| utils::string_from_bytes(bytes).ok())
+				.and_then(|user_id| UserId::parse(user_id).ok())
+				.ok_or_else(|| Error::bad_database("Invalid user ID in senderkey_emaildigest."))?;
+			let pushkey = parts
+				.next()
+				.and_then(|bytes| utils::string_from_bytes(bytes).ok())
+				.ok_or_else(|| Error::bad_database("Invalid pushkey in senderkey_emaildigest."))?;
+			let digest = serde_json::from_slice(&value)
+				.map_err(|_| Error::bad_database("Invalid EmailDigest in senderkey_emaildigest."))?;
+
+			Ok((user_id, pushkey, digest))
+		}))
+	}
 }
@@ -0,0 +1,31 @@
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, reports::Report},
+	Error, Result,
+};
+
+impl service::reports::Data for KeyValueDatabase {
+	fn put_report(&self, report: &Report) -> Result<()> {
+		self.reportid_report.insert(
+			&report.id.to_be_bytes(),
+			&serde_json::to_vec(report).expect("Report can be serialized"),
+		)
+	}
+
+	fn get_report(&self, id: u64) -> Result<Option<Report>> {
+		self.reportid_report
+			.get(&id.to_be_bytes())?
+			.map(|bytes| serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid report in db.")))
+			.transpose()
+	}
+
+	fn all_reports<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Report>> + 'a> {
+		Box::new(
+			self.reportid_report
+				.iter_from(&u64::MAX.to_be_bytes(), true)
+				.map(|(_, bytes)| {
+					serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid report in db."))
+				}),
+		)
+	}
+}
@@ -1,7 +1,11 @@
 use ruma::{OwnedRoomId, RoomId};
 use tracing::error;
 
-use crate::{database::KeyValueDatabase, service, services, utils, Error, Result};
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, rooms::metadata::PartialState},
+	services, utils, Error, Result,
+};
 
 impl service::rooms::metadata::Data for KeyValueDatabase {
 	fn exists(&self, room_id: &RoomId) -> Result<bool> {
@@ -73,4 +77,34 @@ impl service::rooms::metadata::Data for KeyValueDatabase {
 			},
 		))
 	}
+
+	fn partial_state(&self, room_id: &RoomId) -> Result<Option<PartialState>> {
+		self.roomid_partialstate
+			.get(room_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid partial state in roomid_partialstate."))
+			})
+			.transpose()
+	}
+
+	fn set_partial_state(&self, room_id: &RoomId, partial_state: Option<&PartialState>) -> Result<()> {
+		if let Some(partial_state) = partial_state {
+			self.roomid_partialstate.insert(
+				room_id.as_bytes(),
+				&serde_json::to_vec(partial_state).expect("PartialState can be serialized"),
+			)
+		} else {
+			self.roomid_partialstate.remove(room_id.as_bytes())
+		}
+	}
+
+	fn partial_state_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a> {
+		Box::new(self.roomid_partialstate.iter().map(|(room_id_bytes, _)| {
+			utils::string_from_bytes(&room_id_bytes)
+				.ok()
+				.and_then(|room_id| OwnedRoomId::try_from(room_id).ok())
+				.ok_or_else(|| Error::bad_database("Invalid room_id in roomid_partialstate."))
+		}))
+	}
 }
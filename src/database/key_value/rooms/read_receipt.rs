@@ -1,6 +1,10 @@
 use std::mem;
 
-use ruma::{events::receipt::ReceiptEvent, serde::Raw, CanonicalJsonObject, OwnedUserId, RoomId, UserId};
+use ruma::{
+	events::receipt::{ReceiptEvent, ReceiptThread},
+	serde::Raw,
+	CanonicalJsonObject, OwnedUserId, RoomId, UserId,
+};
 
 use crate::{database::KeyValueDatabase, service, services, utils, Error, Result};
 
@@ -12,16 +16,19 @@ impl service::rooms::read_receipt::Data for KeyValueDatabase {
 		let mut last_possible_key = prefix.clone();
 		last_possible_key.extend_from_slice(&u64::MAX.to_be_bytes());
 
-		// Remove old entry
+		// Remove old entry, users have one receipt per thread
+		let thread = receipt_thread(&event, user_id);
 		if let Some((old, _)) = self
 			.readreceiptid_readreceipt
 			.iter_from(&last_possible_key, true)
 			.take_while(|(key, _)| key.starts_with(&prefix))
-			.find(|(key, _)| {
+			.find(|(key, value)| {
 				key.rsplit(|&b| b == 0xFF)
 					.next()
 					.expect("rsplit always returns an element")
 					== user_id.as_bytes()
+					&& serde_json::from_slice::<ReceiptEvent>(value)
+						.map_or(true, |old| receipt_thread(&old, user_id) == thread)
 			}) {
 			// This is the old room_latest
 			self.readreceiptid_readreceipt.remove(&old)?;
@@ -118,3 +125,14 @@ impl service::rooms::read_receipt::Data for KeyValueDatabase {
 			.unwrap_or(0))
 	}
 }
+
+/// The thread of the user's receipt in the event
+fn receipt_thread(event: &ReceiptEvent, user_id: &UserId) -> ReceiptThread {
+	event
+		.content
+		.0
+		.values()
+		.flat_map(|receipts| receipts.values())
+		.find_map(|users| users.get(user_id))
+		.map_or(ReceiptThread::Unthreaded, |receipt| receipt.thread.clone())
+}
@@ -223,27 +223,30 @@ impl service::rooms::timeline::Data for KeyValueDatabase {
 	}
 
 	fn increment_notification_counts(
-		&self, room_id: &RoomId, notifies: Vec<OwnedUserId>, highlights: Vec<OwnedUserId>,
+		&self, room_id: &RoomId, thread_root: Option<&EventId>, notifies: Vec<OwnedUserId>,
+		highlights: Vec<OwnedUserId>,
 	) -> Result<()> {
-		let mut notifies_batch = Vec::new();
-		let mut highlights_batch = Vec::new();
-		for user in notifies {
+		let key = |user: OwnedUserId| {
 			let mut userroom_id = user.as_bytes().to_vec();
 			userroom_id.push(0xFF);
 			userroom_id.extend_from_slice(room_id.as_bytes());
-			notifies_batch.push(userroom_id);
-		}
-		for user in highlights {
-			let mut userroom_id = user.as_bytes().to_vec();
-			userroom_id.push(0xFF);
-			userroom_id.extend_from_slice(room_id.as_bytes());
-			highlights_batch.push(userroom_id);
-		}
+			if let Some(thread_root) = thread_root {
+				userroom_id.push(0xFF);
+				userroom_id.extend_from_slice(thread_root.as_bytes());
+			}
+			userroom_id
+		};
+		let notifies_batch: Vec<_> = notifies.into_iter().map(key).collect();
+		let highlights_batch: Vec<_> = highlights.into_iter().map(key).collect();
+
+		let (notification_counts, highlight_counts) = if thread_root.is_some() {
+			(&self.userroomthreadid_notificationcount, &self.userroomthreadid_highlightcount)
+		} else {
+			(&self.userroomid_notificationcount, &self.userroomid_highlightcount)
+		};
 
-		self.userroomid_notificationcount
-			.increment_batch(&mut notifies_batch.into_iter())?;
-		self.userroomid_highlightcount
-			.increment_batch(&mut highlights_batch.into_iter())?;
+		notification_counts.increment_batch(&mut notifies_batch.into_iter())?;
+		highlight_counts.increment_batch(&mut highlights_batch.into_iter())?;
 		Ok(())
 	}
 }
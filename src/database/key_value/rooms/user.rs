@@ -1,25 +1,55 @@
-use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use ruma::{EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
 
 use crate::{database::KeyValueDatabase, service, services, utils, Error, Result};
 
 impl service::rooms::user::Data for KeyValueDatabase {
 	fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		prefix.extend_from_slice(room_id.as_bytes());
+		prefix.push(0xFF);
+
+		for (key, _) in self
+			.userroomthreadid_notificationcount
+			.scan_prefix(prefix.clone())
+		{
+			self.userroomthreadid_notificationcount.remove(&key)?;
+		}
+		for (key, _) in self.userroomthreadid_highlightcount.scan_prefix(prefix) {
+			self.userroomthreadid_highlightcount.remove(&key)?;
+		}
+
+		self.reset_main_notification_counts(user_id, room_id)
+	}
+
+	fn reset_main_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
 		let mut userroom_id = user_id.as_bytes().to_vec();
 		userroom_id.push(0xFF);
 		userroom_id.extend_from_slice(room_id.as_bytes());
-		let mut roomuser_id = room_id.as_bytes().to_vec();
-		roomuser_id.push(0xFF);
-		roomuser_id.extend_from_slice(user_id.as_bytes());
 
 		self.userroomid_notificationcount
 			.insert(&userroom_id, &0_u64.to_be_bytes())?;
 		self.userroomid_highlightcount
 			.insert(&userroom_id, &0_u64.to_be_bytes())?;
 
-		self.roomuserid_lastnotificationread
-			.insert(&roomuser_id, &services().globals.next_count()?.to_be_bytes())?;
+		self.mark_notifications_read(user_id, room_id)
+	}
 
-		Ok(())
+	fn reset_thread_notification_counts(
+		&self, user_id: &UserId, room_id: &RoomId, thread_root: &EventId,
+	) -> Result<()> {
+		let mut userroomthread_id = user_id.as_bytes().to_vec();
+		userroomthread_id.push(0xFF);
+		userroomthread_id.extend_from_slice(room_id.as_bytes());
+		userroomthread_id.push(0xFF);
+		userroomthread_id.extend_from_slice(thread_root.as_bytes());
+
+		self.userroomthreadid_notificationcount
+			.remove(&userroomthread_id)?;
+		self.userroomthreadid_highlightcount
+			.remove(&userroomthread_id)?;
+
+		self.mark_notifications_read(user_id, room_id)
 	}
 
 	fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
@@ -46,6 +76,41 @@ impl service::rooms::user::Data for KeyValueDatabase {
 			})
 	}
 
+	fn thread_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<Vec<(OwnedEventId, u64, u64)>> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		prefix.extend_from_slice(room_id.as_bytes());
+		prefix.push(0xFF);
+
+		let thread_root = |key: &[u8]| {
+			EventId::parse(
+				utils::string_from_bytes(&key[prefix.len()..])
+					.map_err(|_| Error::bad_database("Invalid thread root bytes in notification count key."))?,
+			)
+			.map_err(|_| Error::bad_database("Invalid thread root in notification count key."))
+		};
+
+		let mut counts = Vec::new();
+		for (key, bytes) in self
+			.userroomthreadid_notificationcount
+			.scan_prefix(prefix.clone())
+		{
+			let notifications = utils::u64_from_bytes(&bytes)
+				.map_err(|_| Error::bad_database("Invalid thread notification count in db."))?;
+			let highlights = self
+				.userroomthreadid_highlightcount
+				.get(&key)?
+				.map_or(Ok(0), |bytes| {
+					utils::u64_from_bytes(&bytes)
+						.map_err(|_| Error::bad_database("Invalid thread highlight count in db."))
+				})?;
+
+			counts.push((thread_root(&key)?, notifications, highlights));
+		}
+
+		Ok(counts)
+	}
+
 	fn last_notification_read(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
 		let mut key = room_id.as_bytes().to_vec();
 		key.push(0xFF);
@@ -134,3 +199,15 @@ impl service::rooms::user::Data for KeyValueDatabase {
 		))
 	}
 }
+
+impl KeyValueDatabase {
+	/// Remembers when the counts were reset so the next sync sends them
+	fn mark_notifications_read(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
+		let mut roomuser_id = room_id.as_bytes().to_vec();
+		roomuser_id.push(0xFF);
+		roomuser_id.extend_from_slice(user_id.as_bytes());
+
+		self.roomuserid_lastnotificationread
+			.insert(&roomuser_id, &services().globals.next_count()?.to_be_bytes())
+	}
+}
@@ -1,10 +1,10 @@
-use ruma::{ServerName, UserId};
+use ruma::{OwnedServerName, ServerName, UserId};
 
 use crate::{
 	database::KeyValueDatabase,
 	service::{
 		self,
-		sending::{Destination, SendingEvent},
+		sending::{Destination, DestinationHealth, SendingEvent},
 	},
 	services, utils, Error, Result,
 };
@@ -117,6 +117,62 @@ impl service::sending::Data for KeyValueDatabase {
 				utils::u64_from_bytes(&bytes).map_err(|_| Error::bad_database("Invalid u64 in servername_educount."))
 			})
 	}
+
+	fn destination_health(&self, server_name: &ServerName) -> Result<Option<DestinationHealth>> {
+		self.servername_health
+			.get(server_name.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid health in servername_health."))
+			})
+			.transpose()
+	}
+
+	fn set_destination_health(&self, server_name: &ServerName, health: &DestinationHealth) -> Result<()> {
+		self.servername_health.insert(
+			server_name.as_bytes(),
+			&serde_json::to_vec(health).expect("DestinationHealth can be serialized"),
+		)
+	}
+
+	fn remove_destination_health(&self, server_name: &ServerName) -> Result<()> {
+		self.servername_health.remove(server_name.as_bytes())
+	}
+
+	fn all_destination_health<'a>(
+		&'a self,
+	) -> Box<dyn Iterator<Item = Result<(OwnedServerName, DestinationHealth)>> + 'a> {
+		Box::new(self.servername_health.iter().map(|(key, value)| {
+			let server_name = utils::string_from_bytes(&key)
+				.ok()
+				.and_then(|s| OwnedServerName::try_from(s).ok())
+				.ok_or_else(|| Error::bad_database("Invalid server name in servername_health."))?;
+			let health = serde_json::from_slice(&value)
+				.map_err(|_| Error::bad_database("Invalid health in servername_health."))?;
+
+			Ok((server_name, health))
+		}))
+	}
+
+	fn appservice_health(&self, appservice_id: &str) -> Result<Option<DestinationHealth>> {
+		self.appserviceid_health
+			.get(appservice_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid health in appserviceid_health."))
+			})
+			.transpose()
+	}
+
+	fn set_appservice_health(&self, appservice_id: &str, health: &DestinationHealth) -> Result<()> {
+		self.appserviceid_health.insert(
+			appservice_id.as_bytes(),
+			&serde_json::to_vec(health).expect("DestinationHealth can be serialized"),
+		)
+	}
+
+	fn remove_appservice_health(&self, appservice_id: &str) -> Result<()> {
+		self.appserviceid_health.remove(appservice_id.as_bytes())
+	}
 }
 
 #[tracing::instrument(skip(key))]
@@ -0,0 +1,120 @@
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	OwnedUserId, UserId,
+};
+
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, threepid::EmailSession},
+	utils, Error, Result,
+};
+
+impl service::threepid::Data for KeyValueDatabase {
+	fn email_session(&self, client_secret: &str, email: &str) -> Result<Option<EmailSession>> {
+		let mut key = client_secret.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(email.as_bytes());
+
+		self.clientsecretemail_emailsession
+			.get(&key)?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes)
+					.map_err(|_| Error::bad_database("Invalid email session in clientsecretemail_emailsession."))
+			})
+			.transpose()
+	}
+
+	fn find_email_session(&self, sid: &str, client_secret: &str) -> Result<Option<EmailSession>> {
+		let mut prefix = client_secret.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		for (_, bytes) in self.clientsecretemail_emailsession.scan_prefix(prefix) {
+			let session: EmailSession = serde_json::from_slice(&bytes)
+				.map_err(|_| Error::bad_database("Invalid email session in clientsecretemail_emailsession."))?;
+
+			if session.sid == sid {
+				return Ok(Some(session));
+			}
+		}
+
+		Ok(None)
+	}
+
+	fn set_email_session(&self, session: &EmailSession) -> Result<()> {
+		let mut key = session.client_secret.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(session.email.as_bytes());
+
+		self.clientsecretemail_emailsession
+			.insert(&key, &serde_json::to_vec(session).expect("EmailSession::to_vec always works"))
+	}
+
+	fn add_threepid(&self, user_id: &UserId, threepid: &ThirdPartyIdentifier) -> Result<()> {
+		let mut threepid_key = threepid.medium.as_str().as_bytes().to_vec();
+		threepid_key.push(0xFF);
+		threepid_key.extend_from_slice(threepid.address.as_bytes());
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key);
+
+		self.userthreepid_threepid.insert(
+			&key,
+			&serde_json::to_vec(threepid).expect("ThirdPartyIdentifier::to_vec always works"),
+		)?;
+		self.threepid_userid
+			.insert(&threepid_key, user_id.as_bytes())?;
+
+		Ok(())
+	}
+
+	fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<bool> {
+		let mut threepid_key = medium.as_str().as_bytes().to_vec();
+		threepid_key.push(0xFF);
+		threepid_key.extend_from_slice(address.as_bytes());
+
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(&threepid_key);
+
+		if self.userthreepid_threepid.get(&key)?.is_none() {
+			return Ok(false);
+		}
+
+		self.userthreepid_threepid.remove(&key)?;
+		self.threepid_userid.remove(&threepid_key)?;
+
+		Ok(true)
+	}
+
+	fn threepids<'a>(&'a self, user_id: &UserId) -> Box<dyn Iterator<Item = Result<ThirdPartyIdentifier>> + 'a> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		Box::new(
+			self.userthreepid_threepid
+				.scan_prefix(prefix)
+				.map(|(_, bytes)| {
+					serde_json::from_slice(&bytes)
+						.map_err(|_| Error::bad_database("Invalid 3PID in userthreepid_threepid."))
+				}),
+		)
+	}
+
+	fn find_user_by_threepid(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>> {
+		let mut key = medium.as_str().as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(address.as_bytes());
+
+		self.threepid_userid
+			.get(&key)?
+			.map(|bytes| {
+				UserId::parse(
+					utils::string_from_bytes(&bytes)
+						.map_err(|_| Error::bad_database("User ID in threepid_userid is invalid unicode."))?,
+				)
+				.map_err(|_| Error::bad_database("User ID in threepid_userid is invalid."))
+			})
+			.transpose()
+	}
+}
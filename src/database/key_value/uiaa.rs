@@ -28,6 +28,20 @@ impl service::uiaa::Data for KeyValueDatabase {
 			.map(ToOwned::to_owned)
 	}
 
+	fn set_uiaa_email(&self, user_id: &UserId, device_id: &DeviceId, session: &str, email: (String, u64)) {
+		self.userdevicesessionid_uiaaemail
+			.write()
+			.unwrap()
+			.insert((user_id.to_owned(), device_id.to_owned(), session.to_owned()), email);
+	}
+
+	fn take_uiaa_email(&self, user_id: &UserId, device_id: &DeviceId, session: &str) -> Option<(String, u64)> {
+		self.userdevicesessionid_uiaaemail
+			.write()
+			.unwrap()
+			.remove(&(user_id.to_owned(), device_id.to_owned(), session.to_owned()))
+	}
+
 	fn update_uiaa_session(
 		&self, user_id: &UserId, device_id: &DeviceId, session: &str, uiaainfo: Option<&UiaaInfo>,
 	) -> Result<()> {
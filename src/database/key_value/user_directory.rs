@@ -0,0 +1,113 @@
+use ruma::{OwnedUserId, RoomId, UserId};
+
+use crate::{
+	database::KeyValueDatabase,
+	service::{self, user_directory::DirectoryEntry},
+	utils, Error, Result,
+};
+
+const BUILT: &[u8] = b"user_directory_built";
+
+impl service::user_directory::Data for KeyValueDatabase {
+	fn set_entry(&self, user_id: &UserId, entry: &DirectoryEntry) -> Result<()> {
+		if let Some(old) = self.get_entry(user_id)? {
+			for token in &old.tokens {
+				self.directorytoken_userid
+					.remove(&token_key(token, user_id))?;
+			}
+		}
+
+		self.userid_directoryentry.insert(
+			user_id.as_bytes(),
+			&serde_json::to_vec(entry).expect("DirectoryEntry can be serialized"),
+		)?;
+
+		let mut batch = entry
+			.tokens
+			.iter()
+			.map(|token| (token_key(token, user_id), Vec::new()));
+		self.directorytoken_userid.insert_batch(&mut batch)
+	}
+
+	fn get_entry(&self, user_id: &UserId) -> Result<Option<DirectoryEntry>> {
+		self.userid_directoryentry
+			.get(user_id.as_bytes())?
+			.map(|bytes| {
+				serde_json::from_slice(&bytes).map_err(|_| Error::bad_database("Invalid directory entry in db."))
+			})
+			.transpose()
+	}
+
+	fn remove_entry(&self, user_id: &UserId) -> Result<()> {
+		if let Some(old) = self.get_entry(user_id)? {
+			for token in &old.tokens {
+				self.directorytoken_userid
+					.remove(&token_key(token, user_id))?;
+			}
+		}
+		self.userid_directoryentry.remove(user_id.as_bytes())?;
+
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		for (key, _) in self.userid_publicroomid.scan_prefix(prefix) {
+			self.userid_publicroomid.remove(&key)?;
+		}
+
+		Ok(())
+	}
+
+	fn users_with_token<'a>(&'a self, token: &str, prefix: bool) -> Box<dyn Iterator<Item = Result<OwnedUserId>> + 'a> {
+		let mut key = token.as_bytes().to_vec();
+		if !prefix {
+			key.push(0xFF);
+		}
+
+		Box::new(self.directorytoken_userid.scan_prefix(key).map(|(key, _)| {
+			let user_id = key
+				.iter()
+				.position(|&b| b == 0xFF)
+				.map(|separator| &key[separator + 1..])
+				.ok_or_else(|| Error::bad_database("Invalid directorytoken_userid in db."))?;
+
+			UserId::parse(
+				utils::string_from_bytes(user_id)
+					.map_err(|_| Error::bad_database("User ID in directorytoken_userid is invalid unicode."))?,
+			)
+			.map_err(|_| Error::bad_database("User ID in directorytoken_userid is invalid."))
+		}))
+	}
+
+	fn set_in_public_room(&self, user_id: &UserId, room_id: &RoomId, public: bool) -> Result<()> {
+		let mut key = user_id.as_bytes().to_vec();
+		key.push(0xFF);
+		key.extend_from_slice(room_id.as_bytes());
+
+		if public {
+			self.userid_publicroomid.insert(&key, &[])
+		} else {
+			self.userid_publicroomid.remove(&key)
+		}
+	}
+
+	fn in_public_room(&self, user_id: &UserId) -> Result<bool> {
+		let mut prefix = user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+
+		Ok(self
+			.userid_publicroomid
+			.scan_prefix(prefix)
+			.next()
+			.is_some())
+	}
+
+	fn is_built(&self) -> Result<bool> { Ok(self.global.get(BUILT)?.is_some()) }
+
+	fn mark_built(&self) -> Result<()> { self.global.insert(BUILT, &[]) }
+}
+
+fn token_key(token: &str, user_id: &UserId) -> Vec<u8> {
+	let mut key = token.as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(user_id.as_bytes());
+	key
+}
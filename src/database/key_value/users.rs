@@ -12,7 +12,7 @@ use tracing::warn;
 
 use crate::{
 	database::KeyValueDatabase,
-	service::{self, users::clean_signatures},
+	service::{self, sending::AppserviceEdu, users::clean_signatures},
 	services, utils, Error, Result,
 };
 
@@ -29,6 +29,43 @@ impl service::users::Data for KeyValueDatabase {
 			.is_empty())
 	}
 
+	/// Check if the account was erased on deactivation
+	fn is_erased(&self, user_id: &UserId) -> Result<bool> { Ok(self.userid_erased.get(user_id.as_bytes())?.is_some()) }
+
+	/// Marks the account as erased, so its events are only served redacted
+	fn set_erased(&self, user_id: &UserId) -> Result<()> {
+		self.userid_erased
+			.insert(user_id.as_bytes(), &utils::millis_since_unix_epoch().to_be_bytes())
+	}
+
+	/// Check if the account is suspended (MSC3823)
+	fn is_suspended(&self, user_id: &UserId) -> Result<bool> {
+		Ok(self.userid_suspended.get(user_id.as_bytes())?.is_some())
+	}
+
+	/// Suspends or unsuspends the account
+	fn set_suspended(&self, user_id: &UserId, suspended: bool) -> Result<()> {
+		if suspended {
+			self.userid_suspended
+				.insert(user_id.as_bytes(), &utils::millis_since_unix_epoch().to_be_bytes())
+		} else {
+			self.userid_suspended.remove(user_id.as_bytes())
+		}
+	}
+
+	/// Check if the account is locked (MSC3939)
+	fn is_locked(&self, user_id: &UserId) -> Result<bool> { Ok(self.userid_locked.get(user_id.as_bytes())?.is_some()) }
+
+	/// Locks or unlocks the account
+	fn set_locked(&self, user_id: &UserId, locked: bool) -> Result<()> {
+		if locked {
+			self.userid_locked
+				.insert(user_id.as_bytes(), &utils::millis_since_unix_epoch().to_be_bytes())
+		} else {
+			self.userid_locked.remove(user_id.as_bytes())
+		}
+	}
+
 	/// Returns the number of users registered on this server.
 	fn count(&self) -> Result<usize> { Ok(self.userid_password.iter().count()) }
 
@@ -233,6 +270,12 @@ impl service::users::Data for KeyValueDatabase {
 			self.userdeviceid_token.remove(&userdeviceid)?;
 			self.token_userdeviceid.remove(&old_token)?;
 		}
+		self.userdeviceid_tokenexpiresat.remove(&userdeviceid)?;
+
+		if let Some(old_refresh_token) = self.userdeviceid_refreshtoken.get(&userdeviceid)? {
+			self.userdeviceid_refreshtoken.remove(&userdeviceid)?;
+			self.refreshtoken_userdeviceid.remove(&old_refresh_token)?;
+		}
 
 		// Remove todevice events
 		let mut prefix = userdeviceid.clone();
@@ -302,6 +345,79 @@ impl service::users::Data for KeyValueDatabase {
 		self.token_userdeviceid
 			.insert(token.as_bytes(), &userdeviceid)?;
 
+		// A plain token never expires, and the refresh token of the old session is no
+		// longer valid
+		self.userdeviceid_tokenexpiresat.remove(&userdeviceid)?;
+		if let Some(old_refresh_token) = self.userdeviceid_refreshtoken.get(&userdeviceid)? {
+			self.userdeviceid_refreshtoken.remove(&userdeviceid)?;
+			self.refreshtoken_userdeviceid.remove(&old_refresh_token)?;
+		}
+
+		Ok(())
+	}
+
+	fn access_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<u64>> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		self.userdeviceid_tokenexpiresat
+			.get(&userdeviceid)?
+			.map(|bytes| {
+				utils::u64_from_bytes(&bytes)
+					.map_err(|_| Error::bad_database("Token expiry in userdeviceid_tokenexpiresat is invalid."))
+			})
+			.transpose()
+	}
+
+	fn set_access_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId, expires_at: u64) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		self.userdeviceid_tokenexpiresat
+			.insert(&userdeviceid, &expires_at.to_be_bytes())
+	}
+
+	fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, OwnedDeviceId)>> {
+		self.refreshtoken_userdeviceid
+			.get(refresh_token.as_bytes())?
+			.map_or(Ok(None), |bytes| {
+				let mut parts = bytes.split(|&b| b == 0xFF);
+				let user_bytes = parts
+					.next()
+					.ok_or_else(|| Error::bad_database("User ID in refreshtoken_userdeviceid is invalid."))?;
+				let device_bytes = parts
+					.next()
+					.ok_or_else(|| Error::bad_database("Device ID in refreshtoken_userdeviceid is invalid."))?;
+
+				Ok(Some((
+					UserId::parse(utils::string_from_bytes(user_bytes).map_err(|_| {
+						Error::bad_database("User ID in refreshtoken_userdeviceid is invalid unicode.")
+					})?)
+					.map_err(|_| Error::bad_database("User ID in refreshtoken_userdeviceid is invalid."))?,
+					utils::string_from_bytes(device_bytes)
+						.map_err(|_| Error::bad_database("Device ID in refreshtoken_userdeviceid is invalid."))?
+						.into(),
+				)))
+			})
+	}
+
+	fn set_refresh_token(&self, user_id: &UserId, device_id: &DeviceId, refresh_token: &str) -> Result<()> {
+		let mut userdeviceid = user_id.as_bytes().to_vec();
+		userdeviceid.push(0xFF);
+		userdeviceid.extend_from_slice(device_id.as_bytes());
+
+		// Refresh tokens are single-use, so the old one is always invalidated
+		if let Some(old_refresh_token) = self.userdeviceid_refreshtoken.get(&userdeviceid)? {
+			self.refreshtoken_userdeviceid.remove(&old_refresh_token)?;
+		}
+
+		self.userdeviceid_refreshtoken
+			.insert(&userdeviceid, refresh_token.as_bytes())?;
+		self.refreshtoken_userdeviceid
+			.insert(refresh_token.as_bytes(), &userdeviceid)?;
+
 		Ok(())
 	}
 
@@ -623,6 +739,12 @@ impl service::users::Data for KeyValueDatabase {
 		key.extend_from_slice(&count);
 		self.keychangeid_userid.insert(&key, user_id.as_bytes())?;
 
+		services()
+			.sending
+			.send_edu_appservices(&AppserviceEdu::DeviceListUpdate {
+				user_id: user_id.to_owned(),
+			})?;
+
 		Ok(())
 	}
 
@@ -636,5 +636,8 @@ pub(crate) async fn migrations(db: &KeyValueDatabase, config: &Config) -> Result
 		);
 	}
 
+	// Databases from before the user directory existed have to be indexed once
+	services().user_directory.build_if_missing()?;
+
 	Ok(())
 }
@@ -50,6 +50,12 @@ pub(crate) struct KeyValueDatabase {
 	//pub(crate) globals: globals::Globals,
 	pub(crate) global: Arc<dyn KvTree>,
 	pub(crate) server_signingkeys: Arc<dyn KvTree>,
+	pub(crate) server_signedkeys: Arc<dyn KvTree>, // The latest key response as signed by the origin
+	pub(crate) reportid_report: Arc<dyn KvTree>,   // ReportId = u64 count, Report = json
+
+	pub(crate) userid_directoryentry: Arc<dyn KvTree>, // DirectoryEntry = json
+	pub(crate) directorytoken_userid: Arc<dyn KvTree>, // Token = lowercased word of the user ID or display name
+	pub(crate) userid_publicroomid: Arc<dyn KvTree>,   // Joined rooms with a public join rule
 
 	pub(crate) roomid_inviteviaservers: Arc<dyn KvTree>,
 
@@ -62,6 +68,12 @@ pub(crate) struct KeyValueDatabase {
 	pub(crate) userdeviceid_metadata: Arc<dyn KvTree>, // This is also used to check if a device exists
 	pub(crate) userid_devicelistversion: Arc<dyn KvTree>, // DevicelistVersion = u64
 	pub(crate) token_userdeviceid: Arc<dyn KvTree>,
+	pub(crate) userdeviceid_tokenexpiresat: Arc<dyn KvTree>, // TokenExpiresAt = u64 (millis)
+	pub(crate) userdeviceid_refreshtoken: Arc<dyn KvTree>,
+	pub(crate) refreshtoken_userdeviceid: Arc<dyn KvTree>,
+	pub(crate) userid_erased: Arc<dyn KvTree>,    // Erased = u64 (millis)
+	pub(crate) userid_suspended: Arc<dyn KvTree>, // Suspended = u64 (millis)
+	pub(crate) userid_locked: Arc<dyn KvTree>,    // Locked = u64 (millis)
 
 	pub(crate) onetimekeyid_onetimekeys: Arc<dyn KvTree>, // OneTimeKeyId = UserId + DeviceKeyId
 	pub(crate) userid_lastonetimekeyupdate: Arc<dyn KvTree>, // LastOneTimeKeyUpdate = Count
@@ -80,6 +92,12 @@ pub(crate) struct KeyValueDatabase {
 	pub(crate) userdevicesessionid_uiaainfo: Arc<dyn KvTree>, // User-interactive authentication
 	pub(crate) userdevicesessionid_uiaarequest:
 		RwLock<BTreeMap<(OwnedUserId, OwnedDeviceId, String), CanonicalJsonValue>>,
+	pub(crate) userdevicesessionid_uiaaemail: RwLock<BTreeMap<(OwnedUserId, OwnedDeviceId, String), (String, u64)>>,
+
+	//pub(crate) threepid: threepid::Threepid,
+	pub(crate) clientsecretemail_emailsession: Arc<dyn KvTree>, // ClientSecretEmail = ClientSecret + Email
+	pub(crate) userthreepid_threepid: Arc<dyn KvTree>,          // UserThreepid = UserId + Medium + Address
+	pub(crate) threepid_userid: Arc<dyn KvTree>,                // Threepid = Medium + Address
 
 	//pub(crate) edus: RoomEdus,
 	pub(crate) readreceiptid_readreceipt: Arc<dyn KvTree>, // ReadReceiptId = RoomId + Count + UserId
@@ -121,6 +139,10 @@ pub(crate) struct KeyValueDatabase {
 	pub(crate) userroomid_notificationcount: Arc<dyn KvTree>, // NotifyCount = u64
 	pub(crate) userroomid_highlightcount: Arc<dyn KvTree>,    // HightlightCount = u64
 	pub(crate) roomuserid_lastnotificationread: Arc<dyn KvTree>, // LastNotificationRead = u64
+	pub(crate) userroomthreadid_notificationcount: Arc<dyn KvTree>, // ThreadId = event ID of the thread root
+	pub(crate) userroomthreadid_highlightcount: Arc<dyn KvTree>,
+	pub(crate) useridcount_notification: Arc<dyn KvTree>, // Count = PDU count of the event
+	pub(crate) notificationts_useridcount: Arc<dyn KvTree>, // For pruning old notifications
 
 	/// Remember the current state hash of a room.
 	pub(crate) roomid_shortstatehash: Arc<dyn KvTree>,
@@ -172,6 +194,10 @@ pub(crate) struct KeyValueDatabase {
 	                                                       * (/send) */
 	//pub(crate) sending: sending::Sending,
 	pub(crate) servername_educount: Arc<dyn KvTree>, // EduCount: Count of last EDU sync
+	pub(crate) servername_health: Arc<dyn KvTree>,   // DestinationHealth as json
+	pub(crate) appserviceid_health: Arc<dyn KvTree>, // DestinationHealth as json
+	pub(crate) roomid_incomingpdu: Arc<dyn KvTree>,  // RoomId + Count = QueuedPdu as json
+	pub(crate) roomid_partialstate: Arc<dyn KvTree>, // PartialState as json
 	pub(crate) servernameevent_data: Arc<dyn KvTree>, /* ServernameEvent = (+ / $)SenderKey / ServerName / UserId +
 	                                                  * PduId / Id (for edus), Data = EDU content */
 	pub(crate) servercurrentevent_data: Arc<dyn KvTree>, /* ServerCurrentEvents = (+ / $)ServerName / UserId + PduId
@@ -179,9 +205,11 @@ pub(crate) struct KeyValueDatabase {
 
 	//pub(crate) appservice: appservice::Appservice,
 	pub(crate) id_appserviceregistrations: Arc<dyn KvTree>,
+	pub(crate) appserviceid_configfile: Arc<dyn KvTree>, // Path of the config file the registration came from
 
 	//pub(crate) pusher: pusher::PushData,
 	pub(crate) senderkey_pusher: Arc<dyn KvTree>,
+	pub(crate) senderkey_emaildigest: Arc<dyn KvTree>, // EmailDigest = json
 
 	pub(crate) auth_chain_cache: Mutex<LruCache<Vec<u64>, Arc<[u64]>>>,
 	pub(crate) our_real_users_cache: RwLock<HashMap<OwnedRoomId, Arc<HashSet<OwnedUserId>>>>,
@@ -250,6 +278,12 @@ impl KeyValueDatabase {
 			userdeviceid_metadata: builder.open_tree("userdeviceid_metadata")?,
 			userid_devicelistversion: builder.open_tree("userid_devicelistversion")?,
 			token_userdeviceid: builder.open_tree("token_userdeviceid")?,
+			userdeviceid_tokenexpiresat: builder.open_tree("userdeviceid_tokenexpiresat")?,
+			userdeviceid_refreshtoken: builder.open_tree("userdeviceid_refreshtoken")?,
+			refreshtoken_userdeviceid: builder.open_tree("refreshtoken_userdeviceid")?,
+			userid_erased: builder.open_tree("userid_erased")?,
+			userid_suspended: builder.open_tree("userid_suspended")?,
+			userid_locked: builder.open_tree("userid_locked")?,
 			onetimekeyid_onetimekeys: builder.open_tree("onetimekeyid_onetimekeys")?,
 			userid_lastonetimekeyupdate: builder.open_tree("userid_lastonetimekeyupdate")?,
 			keychangeid_userid: builder.open_tree("keychangeid_userid")?,
@@ -264,6 +298,10 @@ impl KeyValueDatabase {
 
 			userdevicesessionid_uiaainfo: builder.open_tree("userdevicesessionid_uiaainfo")?,
 			userdevicesessionid_uiaarequest: RwLock::new(BTreeMap::new()),
+			userdevicesessionid_uiaaemail: RwLock::new(BTreeMap::new()),
+			clientsecretemail_emailsession: builder.open_tree("clientsecretemail_emailsession")?,
+			userthreepid_threepid: builder.open_tree("userthreepid_threepid")?,
+			threepid_userid: builder.open_tree("threepid_userid")?,
 			readreceiptid_readreceipt: builder.open_tree("readreceiptid_readreceipt")?,
 			roomuserid_privateread: builder.open_tree("roomuserid_privateread")?, // "Private" read receipt
 			roomuserid_lastprivatereadupdate: builder.open_tree("roomuserid_lastprivatereadupdate")?,
@@ -300,6 +338,10 @@ impl KeyValueDatabase {
 			userroomid_notificationcount: builder.open_tree("userroomid_notificationcount")?,
 			userroomid_highlightcount: builder.open_tree("userroomid_highlightcount")?,
 			roomuserid_lastnotificationread: builder.open_tree("userroomid_highlightcount")?,
+			userroomthreadid_notificationcount: builder.open_tree("userroomthreadid_notificationcount")?,
+			userroomthreadid_highlightcount: builder.open_tree("userroomthreadid_highlightcount")?,
+			useridcount_notification: builder.open_tree("useridcount_notification")?,
+			notificationts_useridcount: builder.open_tree("notificationts_useridcount")?,
 
 			statekey_shortstatekey: builder.open_tree("statekey_shortstatekey")?,
 			shortstatekey_statekey: builder.open_tree("shortstatekey_statekey")?,
@@ -331,12 +373,24 @@ impl KeyValueDatabase {
 			backupkeyid_backup: builder.open_tree("backupkeyid_backup")?,
 			userdevicetxnid_response: builder.open_tree("userdevicetxnid_response")?,
 			servername_educount: builder.open_tree("servername_educount")?,
+			servername_health: builder.open_tree("servername_health")?,
+			appserviceid_health: builder.open_tree("appserviceid_health")?,
+			roomid_incomingpdu: builder.open_tree("roomid_incomingpdu")?,
+			roomid_partialstate: builder.open_tree("roomid_partialstate")?,
 			servernameevent_data: builder.open_tree("servernameevent_data")?,
 			servercurrentevent_data: builder.open_tree("servercurrentevent_data")?,
 			id_appserviceregistrations: builder.open_tree("id_appserviceregistrations")?,
+			appserviceid_configfile: builder.open_tree("appserviceid_configfile")?,
 			senderkey_pusher: builder.open_tree("senderkey_pusher")?,
+			senderkey_emaildigest: builder.open_tree("senderkey_emaildigest")?,
 			global: builder.open_tree("global")?,
 			server_signingkeys: builder.open_tree("server_signingkeys")?,
+			server_signedkeys: builder.open_tree("server_signedkeys")?,
+			reportid_report: builder.open_tree("reportid_report")?,
+
+			userid_directoryentry: builder.open_tree("userid_directoryentry")?,
+			directorytoken_userid: builder.open_tree("directorytoken_userid")?,
+			userid_publicroomid: builder.open_tree("userid_publicroomid")?,
 
 			roomid_inviteviaservers: builder.open_tree("roomid_inviteviaservers")?,
 
@@ -357,6 +411,8 @@ impl KeyValueDatabase {
 
 		migrations(db, &config).await?;
 
+		services().appservice.load_config_files().await?;
+
 		services().admin.start_handler();
 
 		// Set emergency access for the conduit user
@@ -382,6 +438,15 @@ impl KeyValueDatabase {
 		};
 
 		services().sending.start_handler();
+		services().federation_queue.start_handler();
+		if services().globals.mailer.is_some() {
+			services().pusher.start_email_handler();
+		}
+		services()
+			.rooms
+			.event_handler
+			.resume_partial_state_resyncs();
+		services().rooms.policy.start_subscriptions();
 
 		if config.allow_local_presence {
 			services().presence.start_handler();
@@ -507,6 +572,9 @@ impl KeyValueDatabase {
 					}
 					_ = hangup.recv() => {
 						debug!(target: "database-cleanup","Received SIGHUP");
+						if let Err(e) = services().appservice.load_config_files().await {
+							error!("Failed to reload app_service_config_files: {e}");
+						}
 					}
 					_ = ctrl_c.recv() => {
 						debug!(target: "database-cleanup", "Received Ctrl+C");
@@ -528,6 +596,10 @@ impl KeyValueDatabase {
 	}
 
 	fn perform_cleanup() {
+		if let Err(e) = services().pusher.prune_notifications() {
+			error!(target: "database-cleanup", "Failed to prune the notification log: {}", e);
+		}
+
 		if !services().globals.config.rocksdb_periodic_cleanup {
 			return;
 		}
@@ -19,14 +19,19 @@ pub(crate) fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_supported_versions_route)
 		.ruma_route(client_server::get_register_available_route)
 		.ruma_route(client_server::register_route)
+		.ruma_route(client_server::request_registration_token_via_email_route)
 		.ruma_route(client_server::get_login_types_route)
 		.ruma_route(client_server::login_route)
+		.ruma_route(client_server::refresh_token_route)
 		.ruma_route(client_server::whoami_route)
 		.ruma_route(client_server::logout_route)
 		.ruma_route(client_server::logout_all_route)
 		.ruma_route(client_server::change_password_route)
+		.ruma_route(client_server::request_password_change_token_via_email_route)
 		.ruma_route(client_server::deactivate_route)
 		.ruma_route(client_server::third_party_route)
+		.ruma_route(client_server::add_3pid_route)
+		.ruma_route(client_server::delete_3pid_route)
 		.ruma_route(client_server::request_3pid_management_token_via_email_route)
 		.ruma_route(client_server::request_3pid_management_token_via_msisdn_route)
 		.ruma_route(client_server::get_capabilities_route)
@@ -38,6 +43,7 @@ pub(crate) fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_pushrule_actions_route)
 		.ruma_route(client_server::set_pushrule_actions_route)
 		.ruma_route(client_server::delete_pushrule_route)
+		.ruma_route(client_server::get_notifications_route)
 		.ruma_route(client_server::get_room_event_route)
 		.ruma_route(client_server::get_room_aliases_route)
 		.ruma_route(client_server::get_filter_route)
@@ -76,6 +82,8 @@ pub(crate) fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::create_room_route)
 		.ruma_route(client_server::redact_event_route)
 		.ruma_route(client_server::report_event_route)
+		.ruma_route(client_server::report_room_route)
+		.ruma_route(client_server::appservice_ping_route)
 		.ruma_route(client_server::create_alias_route)
 		.ruma_route(client_server::delete_alias_route)
 		.ruma_route(client_server::get_alias_route)
@@ -181,10 +189,15 @@ pub(crate) fn routes(config: &Config) -> Router {
 		.ruma_route(client_server::get_relating_events_with_rel_type_route)
 		.ruma_route(client_server::get_relating_events_route)
 		.ruma_route(client_server::get_hierarchy_route)
+		.ruma_route(client_server::get_room_summary_route)
         .ruma_route(client_server::get_mutual_rooms_route)
         .ruma_route(client_server::well_known_support)
         .ruma_route(client_server::well_known_client)
         .route("/_conduwuit/server_version", get(client_server::conduwuit_server_version))
+		.route(
+			"/_conduwuit/email/submit_token",
+			get(client_server::submit_email_token_link_route).post(client_server::submit_email_token_route),
+		)
 		.route("/_matrix/client/r0/rooms/:room_id/initialSync", get(initial_sync))
 		.route("/_matrix/client/v3/rooms/:room_id/initialSync", get(initial_sync))
 		.route("/client/server.json", get(client_server::syncv3_client_server_json))
@@ -199,6 +212,8 @@ pub(crate) fn routes(config: &Config) -> Router {
 				"/_matrix/key/v2/server/:key_id",
 				get(server_server::get_server_keys_deprecated_route),
 			)
+			.ruma_route(server_server::get_remote_server_keys_route)
+			.ruma_route(server_server::get_remote_server_keys_batch_route)
 			.ruma_route(server_server::get_public_rooms_route)
 			.ruma_route(server_server::get_public_rooms_filtered_route)
 			.ruma_route(server_server::send_transaction_message_route)
@@ -25,4 +25,8 @@ pub(crate) trait Data: Send + Sync {
 	fn changes_since(
 		&self, room_id: Option<&RoomId>, user_id: &UserId, since: u64,
 	) -> Result<HashMap<RoomAccountDataEventType, Raw<AnyEphemeralRoomEvent>>>;
+
+	/// Removes all account data of the user in the room, or the global account
+	/// data if no room is given.
+	fn remove_all(&self, room_id: Option<&RoomId>, user_id: &UserId) -> Result<()>;
 }
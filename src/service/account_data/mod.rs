@@ -1,12 +1,12 @@
 mod data;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 pub(crate) use data::Data;
 use ruma::{
-	events::{AnyEphemeralRoomEvent, RoomAccountDataEventType},
+	events::{direct::DirectEvent, AnyEphemeralRoomEvent, GlobalAccountDataEventType, RoomAccountDataEventType},
 	serde::Raw,
-	RoomId, UserId,
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 
 use crate::Result;
@@ -34,6 +34,24 @@ impl Service {
 		self.db.get(room_id, user_id, event_type)
 	}
 
+	/// The user's direct chats from `m.direct`, by the user they are with
+	pub(crate) fn direct_chats(&self, user_id: &UserId) -> Result<BTreeMap<OwnedUserId, Vec<OwnedRoomId>>> {
+		Ok(self
+			.get(None, user_id, GlobalAccountDataEventType::Direct.to_string().into())?
+			.and_then(|event| serde_json::from_str::<DirectEvent>(event.get()).ok())
+			.map(|event| event.content.0)
+			.unwrap_or_default())
+	}
+
+	/// The rooms the user marked as direct chats in `m.direct`
+	pub(crate) fn direct_room_ids(&self, user_id: &UserId) -> Result<HashSet<OwnedRoomId>> {
+		Ok(self
+			.direct_chats(user_id)?
+			.into_values()
+			.flatten()
+			.collect())
+	}
+
 	/// Returns all changes to the account data that happened after `since`.
 	#[tracing::instrument(skip(self, room_id, user_id, since))]
 	pub(crate) fn changes_since(
@@ -41,4 +59,11 @@ impl Service {
 	) -> Result<HashMap<RoomAccountDataEventType, Raw<AnyEphemeralRoomEvent>>> {
 		self.db.changes_since(room_id, user_id, since)
 	}
+
+	/// Removes all account data of the user in the room, or the global account
+	/// data if no room is given.
+	#[tracing::instrument(skip(self, room_id, user_id))]
+	pub(crate) fn remove_all(&self, room_id: Option<&RoomId>, user_id: &UserId) -> Result<()> {
+		self.db.remove_all(room_id, user_id)
+	}
 }
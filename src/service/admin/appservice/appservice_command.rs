@@ -1,13 +1,23 @@
+use std::fmt::Write;
+
 use ruma::{api::appservice::Registration, events::room::message::RoomMessageEventContent};
 
-use crate::{service::admin::escape_html, services, Result};
+use crate::{
+	service::{admin::escape_html, appservice::RegistrationExtensions, sending::Destination},
+	services, utils, Result,
+};
 
 pub(crate) async fn register(body: Vec<&str>) -> Result<RoomMessageEventContent> {
 	if body.len() > 2 && body[0].trim().starts_with("```") && body.last().unwrap().trim() == "```" {
 		let appservice_config = body[1..body.len() - 1].join("\n");
-		let parsed_config = serde_yaml::from_str::<Registration>(&appservice_config);
+		let parsed_config = serde_yaml::from_str::<Registration>(&appservice_config)
+			.and_then(|yaml| Ok((yaml, serde_yaml::from_str::<RegistrationExtensions>(&appservice_config)?)));
 		match parsed_config {
-			Ok(yaml) => match services().appservice.register_appservice(yaml).await {
+			Ok((yaml, extensions)) => match services()
+				.appservice
+				.register_appservice_from_admin(yaml, extensions)
+				.await
+			{
 				Ok(id) => Ok(RoomMessageEventContent::text_plain(format!(
 					"Appservice registered with ID: {id}."
 				))),
@@ -47,11 +57,16 @@ pub(crate) async fn show(_body: Vec<&str>, appservice_identifier: String) -> Res
 	{
 		Some(config) => {
 			let config_str = serde_yaml::to_string(&config).expect("config should've been validated on register");
-			let output = format!("Config for {}:\n\n```yaml\n{}\n```", appservice_identifier, config_str,);
+			let health = health_summary(&appservice_identifier)?;
+			let output = format!(
+				"Config for {}:\n\n```yaml\n{}\n```\n{}",
+				appservice_identifier, config_str, health
+			);
 			let output_html = format!(
-				"Config for {}:\n\n<pre><code class=\"language-yaml\">{}</code></pre>",
+				"Config for {}:\n\n<pre><code class=\"language-yaml\">{}</code></pre>\n<p>{}</p>",
 				escape_html(&appservice_identifier),
 				escape_html(&config_str),
+				escape_html(&health),
 			);
 			Ok(RoomMessageEventContent::text_html(output, output_html))
 		},
@@ -64,3 +79,47 @@ pub(crate) async fn list(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
 	let output = format!("Appservices ({}): {}", appservices.len(), appservices.join(", "));
 	Ok(RoomMessageEventContent::text_plain(output))
 }
+
+pub(crate) async fn status(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	let appservices = services().appservice.iter_ids().await;
+	if appservices.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No appservices are registered."));
+	}
+
+	let mut msg = format!("{} appservice(s):\n", appservices.len());
+	for appservice_identifier in appservices {
+		let _ = writeln!(msg, "{appservice_identifier}\t{}", health_summary(&appservice_identifier)?);
+	}
+
+	Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
+}
+
+/// One line about how sending to the appservice went lately
+fn health_summary(appservice_identifier: &str) -> Result<String> {
+	let now = utils::millis_since_unix_epoch();
+	let ago =
+		|ts: Option<u64>| ts.map_or_else(|| "never".to_owned(), |ts| format!("{}s ago", now.saturating_sub(ts) / 1000));
+
+	let health = services()
+		.sending
+		.appservice_health(appservice_identifier)?
+		.unwrap_or_default();
+	let queued = services()
+		.sending
+		.pending_requests(&Destination::Appservice(appservice_identifier.to_owned()));
+
+	let mut summary = format!(
+		"Failures: {}\tLast success: {}\tQueued: {queued}",
+		health.failures,
+		ago(health.last_success)
+	);
+	if let Some(last_error) = health.last_error.filter(|_| health.failures > 0) {
+		let _ = write!(
+			summary,
+			"\tLast failure: {}\tLast error: {last_error}",
+			ago(health.last_failure)
+		);
+	}
+
+	Ok(summary)
+}
@@ -1,7 +1,7 @@
 use clap::Subcommand;
 use ruma::events::room::message::RoomMessageEventContent;
 
-use self::appservice_command::{list, register, show, unregister};
+use self::appservice_command::{list, register, show, status, unregister};
 use crate::Result;
 
 pub(crate) mod appservice_command;
@@ -26,7 +26,7 @@ pub(crate) enum AppserviceCommand {
 		appservice_identifier: String,
 	},
 
-	/// - Show an appservice's config using its ID
+	/// - Show an appservice's config and health using its ID
 	///
 	/// You can find the ID using the `list-appservices` command.
 	Show {
@@ -36,6 +36,12 @@ pub(crate) enum AppserviceCommand {
 
 	/// - List all the currently registered appservices
 	List,
+
+	/// - Show the health of all appservices
+	///
+	/// Lists the consecutive failed transactions, the last successful one and
+	/// the number of events waiting to be sent for every appservice.
+	Status,
 }
 
 pub(crate) async fn process(command: AppserviceCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -48,5 +54,6 @@ pub(crate) async fn process(command: AppserviceCommand, body: Vec<&str>) -> Resu
 			appservice_identifier,
 		} => show(body, appservice_identifier).await?,
 		AppserviceCommand::List => list(body).await?,
+		AppserviceCommand::Status => status(body).await?,
 	})
 }
@@ -1,15 +1,24 @@
-use std::{collections::BTreeMap, sync::Arc, time::Instant};
+use std::{
+	collections::{BTreeMap, HashSet},
+	sync::Arc,
+	time::Instant,
+};
 
 use ruma::{
-	api::client::error::ErrorKind, events::room::message::RoomMessageEventContent, CanonicalJsonObject, EventId,
-	RoomId, RoomVersionId, ServerName,
+	api::client::error::ErrorKind,
+	events::room::message::RoomMessageEventContent,
+	state_res::{self, StateMap},
+	CanonicalJsonObject, EventId, RoomId, RoomVersionId, ServerName,
 };
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 use tracing_subscriber::EnvFilter;
 
 use crate::{
-	api::server_server::parse_incoming_pdu, service::sending::send::resolve_actual_dest, services, utils::HtmlEscape,
+	api::server_server::parse_incoming_pdu,
+	service::{rooms::event_handler::state_resolution::separate, sending::send::resolve_actual_dest},
+	services,
+	utils::HtmlEscape,
 	Error, PduEvent, Result,
 };
 
@@ -386,7 +395,7 @@ pub(crate) async fn sign_json(body: Vec<&str>) -> Result<RoomMessageEventContent
 			Ok(mut value) => {
 				ruma::signatures::sign_json(
 					services().globals.server_name().as_str(),
-					services().globals.keypair(),
+					&*services().globals.keypair(),
 					&mut value,
 				)
 				.expect("our request json is what ruma expects");
@@ -466,3 +475,89 @@ pub(crate) fn memory_stats() -> RoomMessageEventContent {
 		html_body,
 	)
 }
+
+pub(crate) async fn benchmark_state_resolution(
+	_body: Vec<&str>, room_id: Box<RoomId>, event_id: Box<EventId>,
+) -> Result<RoomMessageEventContent> {
+	let Some(current_shortstatehash) = services().rooms.state.get_room_shortstatehash(&room_id)? else {
+		return Ok(RoomMessageEventContent::text_plain("We do not have the state of this room."));
+	};
+	let Some(event_shortstatehash) = services()
+		.rooms
+		.state_accessor
+		.pdu_shortstatehash(&event_id)?
+	else {
+		return Ok(RoomMessageEventContent::text_plain("We do not have the state at this event."));
+	};
+	let room_version_id = services().rooms.state.get_room_version(&room_id)?;
+
+	let mut fork_states = Vec::new();
+	for shortstatehash in [current_shortstatehash, event_shortstatehash] {
+		fork_states.push(
+			services()
+				.rooms
+				.state_accessor
+				.state_full_ids(shortstatehash)
+				.await?
+				.into_iter()
+				.filter_map(|(k, id)| {
+					services()
+						.rooms
+						.short
+						.get_statekey_from_short(k)
+						.map(|(ty, st_key)| ((ty.to_string().into(), st_key), id))
+						.ok()
+				})
+				.collect::<StateMap<_>>(),
+		);
+	}
+	let (_, conflicted) = separate(&fork_states);
+
+	// Resolve the full fork states with their full auth chains, like we did
+	// before only the conflicted subset was handed to state resolution
+	let started = Instant::now();
+	let mut auth_chain_sets = Vec::with_capacity(fork_states.len());
+	for state in &fork_states {
+		auth_chain_sets.push(
+			services()
+				.rooms
+				.auth_chain
+				.event_ids_iter(&room_id, state.values().cloned().collect())
+				.await?
+				.collect::<HashSet<_>>(),
+		);
+	}
+	let lock = services().globals.stateres_mutex.lock();
+	let full_result = state_res::resolve(&room_version_id, &fork_states, auth_chain_sets, |id| {
+		services().rooms.timeline.get_pdu(id).ok().flatten()
+	});
+	drop(lock);
+	let full_elapsed = started.elapsed();
+
+	let started = Instant::now();
+	let conflicted_result = services()
+		.rooms
+		.event_handler
+		.resolve_fork_states(&room_id, &room_version_id, &fork_states)
+		.await;
+	let conflicted_elapsed = started.elapsed();
+
+	let (Ok(full_state), Ok(conflicted_state)) = (full_result, conflicted_result) else {
+		return Ok(RoomMessageEventContent::text_plain(
+			"State resolution failed, some events of the forks could not be loaded.",
+		));
+	};
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Resolved the current state of {room_id} with the state at {event_id}: {} state events, {} of them \
+		 conflicted.\nFull fork states: {full_elapsed:?}.\nConflicted subset: {conflicted_elapsed:?}.\nResolved \
+		 state: {}.",
+		full_state.len(),
+		conflicted.len(),
+		if full_state == conflicted_state {
+			"identical for both"
+		} else {
+			"MISMATCH"
+		},
+	)))
+}
@@ -2,8 +2,9 @@ use clap::Subcommand;
 use ruma::{events::room::message::RoomMessageEventContent, EventId, RoomId, ServerName};
 
 use self::debug_commands::{
-	change_log_level, force_device_list_updates, get_auth_chain, get_pdu, get_remote_pdu, get_remote_pdu_list,
-	get_room_state, memory_stats, parse_pdu, ping, resolve_true_destination, sign_json, verify_json,
+	benchmark_state_resolution, change_log_level, force_device_list_updates, get_auth_chain, get_pdu, get_remote_pdu,
+	get_remote_pdu_list, get_room_state, memory_stats, parse_pdu, ping, resolve_true_destination, sign_json,
+	verify_json,
 };
 use crate::Result;
 
@@ -120,6 +121,18 @@ pub(crate) enum DebugCommand {
 
 	/// - Print extended memory usage
 	MemoryStats,
+
+	/// - Benchmarks state resolution of the room's current state with the state
+	///   at an event, once with the full fork states and once with only the
+	///   conflicted subset
+	BenchmarkStateResolution {
+		/// The room ID
+		room_id: Box<RoomId>,
+
+		/// An event ID (a $ followed by the base64 reference hash) whose state
+		/// is the second fork
+		event_id: Box<EventId>,
+	},
 }
 
 pub(crate) async fn process(command: DebugCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -157,5 +170,9 @@ pub(crate) async fn process(command: DebugCommand, body: Vec<&str>) -> Result<Ro
 			no_cache,
 		} => resolve_true_destination(body, server_name, no_cache).await?,
 		DebugCommand::MemoryStats => memory_stats(),
+		DebugCommand::BenchmarkStateResolution {
+			room_id,
+			event_id,
+		} => benchmark_state_resolution(body, room_id, event_id).await?,
 	})
 }
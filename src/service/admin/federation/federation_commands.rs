@@ -5,7 +5,7 @@ use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, RoomId,
 use crate::{
 	service::admin::{escape_html, get_room_info},
 	services,
-	utils::HtmlEscape,
+	utils::{self, HtmlEscape},
 	Result,
 };
 
@@ -27,6 +27,18 @@ pub(crate) async fn incoming_federeation(_body: Vec<&str>) -> Result<RoomMessage
 		let elapsed = i.elapsed();
 		let _ = writeln!(msg, "{} {}: {}m{}s", r, e, elapsed.as_secs() / 60, elapsed.as_secs() % 60);
 	}
+
+	let depths = services().federation_queue.queue_depths();
+	let _ = writeln!(
+		msg,
+		"\n{} queued pdus in {} rooms:",
+		depths.iter().map(|(_, count)| count).sum::<usize>(),
+		depths.len()
+	);
+	for (room_id, count) in depths {
+		let _ = writeln!(msg, "{room_id}: {count}");
+	}
+
 	Ok(RoomMessageEventContent::text_plain(&msg))
 }
 
@@ -132,3 +144,61 @@ pub(crate) async fn remote_user_in_rooms(_body: Vec<&str>, user_id: Box<UserId>)
 
 	Ok(RoomMessageEventContent::text_html(output_plain, output_html))
 }
+
+pub(crate) async fn destinations(_body: Vec<&str>, failing: bool) -> Result<RoomMessageEventContent> {
+	let now = utils::millis_since_unix_epoch();
+	let mut destinations = services()
+		.sending
+		.destinations()
+		.filter_map(Result::ok)
+		.filter(|(_, health)| !failing || health.is_backed_off(now))
+		.collect::<Vec<_>>();
+
+	if destinations.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No destinations found."));
+	}
+
+	destinations.sort_by(|(a, a_health), (b, b_health)| b_health.failures.cmp(&a_health.failures).then(a.cmp(b)));
+
+	let ago =
+		|ts: Option<u64>| ts.map_or_else(|| "never".to_owned(), |ts| format!("{}s ago", now.saturating_sub(ts) / 1000));
+	let mut msg = format!("{} destination(s):\n", destinations.len());
+	for (server_name, health) in destinations {
+		let _ = write!(
+			msg,
+			"{server_name}\tFailures: {}\tLast success: {}",
+			health.failures,
+			ago(health.last_success)
+		);
+		if health.is_backed_off(now) {
+			let next_retry = health.next_retry.unwrap_or(now);
+			let _ = write!(msg, "\tNext retry: in {}s", next_retry.saturating_sub(now) / 1000);
+		}
+		if let Some(last_error) = health.last_error.filter(|_| health.failures > 0) {
+			let _ = write!(msg, "\tLast error: {last_error}");
+		}
+		msg.push('\n');
+	}
+
+	Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
+}
+
+pub(crate) async fn reset_destination(
+	_body: Vec<&str>, server_name: Box<ServerName>,
+) -> Result<RoomMessageEventContent> {
+	if services()
+		.sending
+		.destination_health(&server_name)?
+		.is_none()
+	{
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"We don't know anything about the health of {server_name}."
+		)));
+	}
+
+	services().sending.forget_destination(&server_name)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Reset the failures of {server_name}, queued transactions are retried now."
+	)))
+}
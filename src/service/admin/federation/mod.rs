@@ -2,7 +2,8 @@ use clap::Subcommand;
 use ruma::{events::room::message::RoomMessageEventContent, RoomId, ServerName, UserId};
 
 use self::federation_commands::{
-	disable_room, enable_room, fetch_support_well_known, incoming_federeation, remote_user_in_rooms,
+	destinations, disable_room, enable_room, fetch_support_well_known, incoming_federeation, remote_user_in_rooms,
+	reset_destination,
 };
 use crate::Result;
 
@@ -11,7 +12,8 @@ pub(crate) mod federation_commands;
 #[cfg_attr(test, derive(Debug))]
 #[derive(Subcommand)]
 pub(crate) enum FederationCommand {
-	/// - List all rooms we are currently handling an incoming pdu from
+	/// - List all rooms we are currently handling an incoming pdu from, and the
+	///   number of pdus queued per room
 	IncomingFederation,
 
 	/// - Disables incoming federation handling for a room.
@@ -41,6 +43,21 @@ pub(crate) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - List the health of the servers we send to
+	///
+	/// Shows the number of consecutive failures, the last success and the time
+	/// of the next retry of every destination we have sent to. Use --failing
+	/// to only list destinations that are currently backed off.
+	Destinations {
+		#[arg(short, long)]
+		failing: bool,
+	},
+
+	/// - Forget the failures of a destination and retry it right away
+	ResetDestination {
+		server_name: Box<ServerName>,
+	},
 }
 
 pub(crate) async fn process(command: FederationCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -58,5 +75,11 @@ pub(crate) async fn process(command: FederationCommand, body: Vec<&str>) -> Resu
 		FederationCommand::RemoteUserInRooms {
 			user_id,
 		} => remote_user_in_rooms(body, user_id).await?,
+		FederationCommand::Destinations {
+			failing,
+		} => destinations(body, failing).await?,
+		FederationCommand::ResetDestination {
+			server_name,
+		} => reset_destination(body, server_name).await?,
 	})
 }
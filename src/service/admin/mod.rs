@@ -31,7 +31,7 @@ use super::pdu::PduBuilder;
 use crate::{
 	service::admin::{
 		appservice::AppserviceCommand, debug::DebugCommand, federation::FederationCommand, media::MediaCommand,
-		query::QueryCommand, room::RoomCommand, server::ServerCommand, user::UserCommand,
+		query::QueryCommand, reports::ReportsCommand, room::RoomCommand, server::ServerCommand, user::UserCommand,
 	},
 	services, Error, Result,
 };
@@ -42,6 +42,7 @@ pub(crate) mod federation;
 pub(crate) mod fsck;
 pub(crate) mod media;
 pub(crate) mod query;
+pub(crate) mod reports;
 pub(crate) mod room;
 pub(crate) mod server;
 pub(crate) mod tester;
@@ -69,6 +70,10 @@ enum AdminCommand {
 	/// - Commands for managing federation
 	Federation(FederationCommand),
 
+	#[command(subcommand)]
+	/// - Commands for handling room and event reports
+	Reports(ReportsCommand),
+
 	#[command(subcommand)]
 	/// - Commands for managing the server
 	Server(ServerCommand),
@@ -304,6 +309,7 @@ impl Service {
 			AdminCommand::Users(command) => user::process(command, body).await?,
 			AdminCommand::Rooms(command) => room::process(command, body).await?,
 			AdminCommand::Federation(command) => federation::process(command, body).await?,
+			AdminCommand::Reports(command) => reports::process(command, body).await?,
 			AdminCommand::Server(command) => server::process(command, body).await?,
 			AdminCommand::Debug(command) => debug::process(command, body).await?,
 			AdminCommand::Query(command) => query::process(command, body).await?,
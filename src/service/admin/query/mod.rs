@@ -2,6 +2,7 @@ pub(crate) mod account_data;
 pub(crate) mod appservice;
 pub(crate) mod globals;
 pub(crate) mod presence;
+pub(crate) mod pusher;
 pub(crate) mod room_alias;
 pub(crate) mod sending;
 pub(crate) mod users;
@@ -13,8 +14,8 @@ use ruma::{
 };
 
 use self::{
-	account_data::account_data, appservice::appservice, globals::globals, presence::presence, room_alias::room_alias,
-	sending::sending, users::users,
+	account_data::account_data, appservice::appservice, globals::globals, presence::presence, pusher::pusher,
+	room_alias::room_alias, sending::sending, users::users,
 };
 use crate::Result;
 
@@ -34,6 +35,10 @@ pub(crate) enum QueryCommand {
 	#[command(subcommand)]
 	Presence(Presence),
 
+	/// - pusher.rs iterators and getters
+	#[command(subcommand)]
+	Pusher(Pusher),
+
 	/// - rooms/alias.rs iterators and getters
 	#[command(subcommand)]
 	RoomAlias(RoomAlias),
@@ -108,6 +113,24 @@ pub(crate) enum Presence {
 	},
 }
 
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+/// All the getters and iterators from src/database/key_value/pusher.rs
+pub(crate) enum Pusher {
+	/// - Returns all the pushers of the user
+	GetPushers {
+		/// Full user ID
+		user_id: Box<UserId>,
+	},
+
+	/// - Returns how many push notifications are waiting to be sent to each of
+	///   the user's pushers
+	PendingPushes {
+		/// Full user ID
+		user_id: Box<UserId>,
+	},
+}
+
 #[cfg_attr(test, derive(Debug))]
 #[derive(Subcommand)]
 /// All the getters and iterators from src/database/key_value/rooms/alias.rs
@@ -215,6 +238,7 @@ pub(crate) async fn process(command: QueryCommand, _body: Vec<&str>) -> Result<R
 		QueryCommand::AccountData(command) => account_data(command).await?,
 		QueryCommand::Appservice(command) => appservice(command).await?,
 		QueryCommand::Presence(command) => presence(command).await?,
+		QueryCommand::Pusher(command) => pusher(command).await?,
 		QueryCommand::RoomAlias(command) => room_alias(command).await?,
 		QueryCommand::Globals(command) => globals(command).await?,
 		QueryCommand::Sending(command) => sending(command).await?,
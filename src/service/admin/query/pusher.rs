@@ -0,0 +1,51 @@
+use ruma::events::room::message::RoomMessageEventContent;
+
+use super::Pusher;
+use crate::{service::sending::Destination, services, Result};
+
+/// All the getters and iterators in key_value/pusher.rs
+pub(crate) async fn pusher(subcommand: Pusher) -> Result<RoomMessageEventContent> {
+	match subcommand {
+		Pusher::GetPushers {
+			user_id,
+		} => {
+			let timer = tokio::time::Instant::now();
+			let results = services().pusher.db.get_pushers(&user_id)?;
+			let query_time = timer.elapsed();
+
+			Ok(RoomMessageEventContent::text_html(
+				format!("Query completed in {query_time:?}:\n\n```\n{:#?}```", results),
+				format!(
+					"<p>Query completed in {query_time:?}:</p>\n<pre><code>{:#?}\n</code></pre>",
+					results
+				),
+			))
+		},
+		Pusher::PendingPushes {
+			user_id,
+		} => {
+			let timer = tokio::time::Instant::now();
+			let results: Vec<(String, usize)> = services()
+				.pusher
+				.db
+				.get_pushkeys(&user_id)
+				.filter_map(Result::ok)
+				.map(|pushkey| {
+					let pending = services()
+						.sending
+						.pending_requests(&Destination::Push(user_id.clone().into(), pushkey.clone()));
+					(pushkey, pending)
+				})
+				.collect();
+			let query_time = timer.elapsed();
+
+			Ok(RoomMessageEventContent::text_html(
+				format!("Query completed in {query_time:?}:\n\n```\n{:#?}```", results),
+				format!(
+					"<p>Query completed in {query_time:?}:</p>\n<pre><code>{:#?}\n</code></pre>",
+					results
+				),
+			))
+		},
+	}
+}
@@ -0,0 +1,50 @@
+use clap::Subcommand;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use self::reports_commands::{list, resolve, show};
+use crate::{service::reports::ReportStatus, Result};
+
+pub(crate) mod reports_commands;
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Subcommand)]
+pub(crate) enum ReportsCommand {
+	/// - List room and event reports by local users, newest first
+	List {
+		/// Only list reports with this status
+		#[arg(short, long)]
+		status: Option<ReportStatus>,
+	},
+
+	/// - Show the details of a report
+	Show {
+		id: u64,
+	},
+
+	/// - Mark a report as resolved, or as dismissed with --dismiss
+	Resolve {
+		id: u64,
+
+		#[arg(short, long)]
+		dismiss: bool,
+
+		/// Note for other admins about how the report was handled
+		note: Option<String>,
+	},
+}
+
+pub(crate) async fn process(command: ReportsCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	Ok(match command {
+		ReportsCommand::List {
+			status,
+		} => list(body, status).await?,
+		ReportsCommand::Show {
+			id,
+		} => show(body, id).await?,
+		ReportsCommand::Resolve {
+			id,
+			dismiss,
+			note,
+		} => resolve(body, id, dismiss, note).await?,
+	})
+}
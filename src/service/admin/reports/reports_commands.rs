@@ -0,0 +1,105 @@
+use std::fmt::Write as _;
+
+use ruma::{events::room::message::RoomMessageEventContent, UserId};
+
+use crate::{
+	service::reports::{Report, ReportStatus},
+	services, utils, Result,
+};
+
+pub(crate) async fn list(_body: Vec<&str>, status: Option<ReportStatus>) -> Result<RoomMessageEventContent> {
+	let now = utils::millis_since_unix_epoch();
+	let reports = services()
+		.reports
+		.list(status)
+		.collect::<Result<Vec<_>>>()?;
+
+	if reports.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No reports found."));
+	}
+
+	let mut msg = format!("{} report(s):\n", reports.len());
+	for report in reports {
+		let _ = write!(
+			msg,
+			"{}\t{}\t{}s ago\tby {}\tin {}",
+			report.id,
+			report.status,
+			now.saturating_sub(report.created) / 1000,
+			report.reporter,
+			report.room_id
+		);
+		if let Some(event_id) = &report.event_id {
+			let _ = write!(msg, "\tevent {event_id}");
+		}
+		if let Some(reason) = &report.reason {
+			let _ = write!(msg, "\t{reason}");
+		}
+		msg.push('\n');
+	}
+
+	Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
+}
+
+pub(crate) async fn show(_body: Vec<&str>, id: u64) -> Result<RoomMessageEventContent> {
+	let Some(report) = services().reports.get(id)? else {
+		return Ok(RoomMessageEventContent::text_plain("Report not found."));
+	};
+
+	Ok(RoomMessageEventContent::text_plain(report_details(&report)))
+}
+
+pub(crate) async fn resolve(
+	_body: Vec<&str>, id: u64, dismiss: bool, note: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let status = if dismiss {
+		ReportStatus::Dismissed
+	} else {
+		ReportStatus::Resolved
+	};
+
+	let server_user =
+		UserId::parse(format!("@conduit:{}", services().globals.server_name())).expect("server's username is valid");
+
+	let Some(report) = services()
+		.reports
+		.set_status(id, status, &server_user, note)?
+	else {
+		return Ok(RoomMessageEventContent::text_plain("Report not found."));
+	};
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Report {} marked as {}.",
+		report.id, report.status
+	)))
+}
+
+fn report_details(report: &Report) -> String {
+	let now = utils::millis_since_unix_epoch();
+	let mut msg = format!(
+		"Report {}\nStatus: {}\nReporter: {}\nRoom ID: {}\nCreated: {}s ago\n",
+		report.id,
+		report.status,
+		report.reporter,
+		report.room_id,
+		now.saturating_sub(report.created) / 1000
+	);
+	if let Some(event_id) = &report.event_id {
+		let _ = writeln!(msg, "Event ID: {event_id}");
+	}
+	if let Some(event_sender) = &report.event_sender {
+		let _ = writeln!(msg, "Sent By: {event_sender}");
+	}
+	if let Some(score) = report.score {
+		let _ = writeln!(msg, "Score: {score}");
+	}
+	let _ = writeln!(msg, "Reason: {}", report.reason.as_deref().unwrap_or(""));
+	if let (Some(handled_by), Some(handled_at)) = (&report.handled_by, report.handled_at) {
+		let _ = writeln!(msg, "Handled by {handled_by} {}s ago", now.saturating_sub(handled_at) / 1000);
+	}
+	if let Some(note) = &report.note {
+		let _ = writeln!(msg, "Note: {note}");
+	}
+
+	msg.trim_end().to_owned()
+}
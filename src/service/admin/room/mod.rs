@@ -143,6 +143,10 @@ pub(crate) enum RoomModerationCommand {
 
 	/// - List of all rooms we have banned
 	ListBannedRooms,
+
+	/// - List the active ban rules of the policy rooms configured in
+	///   `policy_rooms`
+	ListPolicyRules,
 }
 
 pub(crate) async fn process(command: RoomCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -501,5 +501,38 @@ pub(crate) async fn process(command: RoomModerationCommand, body: Vec<&str>) ->
 				},
 			}
 		},
+		RoomModerationCommand::ListPolicyRules => {
+			let rules = services().rooms.policy.rules().await?;
+			if rules.is_empty() {
+				return Ok(RoomMessageEventContent::text_plain("There are no active policy rules."));
+			}
+
+			let plain_list = rules.iter().fold(String::new(), |mut output, rule| {
+				writeln!(
+					output,
+					"- {:?} `{}` from {}: {}",
+					rule.kind, rule.entity, rule.policy_room, rule.reason
+				)
+				.unwrap();
+				output
+			});
+
+			let html_list = rules.iter().fold(String::new(), |mut output, rule| {
+				writeln!(
+					output,
+					"<li>{:?} <code>{}</code> from {}: {}</li>",
+					rule.kind,
+					escape_html(&rule.entity),
+					escape_html(rule.policy_room.as_str()),
+					escape_html(&rule.reason)
+				)
+				.unwrap();
+				output
+			});
+
+			let plain = format!("Policy rules ({}):\n{}", rules.len(), plain_list);
+			let html = format!("Policy rules ({}):\n<ul>{}</ul>", rules.len(), html_list);
+			Ok(RoomMessageEventContent::text_html(plain, html))
+		},
 	}
 }
@@ -5,7 +5,7 @@ use ruma::events::room::message::RoomMessageEventContent;
 
 use self::server_commands::{
 	backup_database, clear_database_caches, clear_service_caches, list_backups, list_database_files, memory_usage,
-	show_config, uptime,
+	rotate_signing_key, show_config, uptime,
 };
 use crate::Result;
 
@@ -42,6 +42,12 @@ pub(crate) enum ServerCommand {
 
 	/// - List database files
 	ListDatabaseFiles,
+
+	/// - Generates a new signing key for this server
+	///
+	/// The current key is kept as an old verify key so events signed with it
+	/// can still be verified.
+	RotateSigningKey,
 }
 
 pub(crate) async fn process(command: ServerCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -58,5 +64,6 @@ pub(crate) async fn process(command: ServerCommand, body: Vec<&str>) -> Result<R
 		ServerCommand::ListBackups => list_backups(body).await?,
 		ServerCommand::BackupDatabase => backup_database(body).await?,
 		ServerCommand::ListDatabaseFiles => list_database_files(body).await?,
+		ServerCommand::RotateSigningKey => rotate_signing_key(body).await?,
 	})
 }
@@ -93,3 +93,12 @@ pub(crate) async fn list_database_files(_body: Vec<&str>) -> Result<RoomMessageE
 	let result = services().globals.db.file_list()?;
 	Ok(RoomMessageEventContent::notice_html(String::new(), result))
 }
+
+pub(crate) async fn rotate_signing_key(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
+	let old_key = services().globals.keypair().version().to_owned();
+	let new_key_id = services().globals.rotate_keypair()?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Signing with new key {new_key_id}. The old key ed25519:{old_key} is now published as an old verify key."
+	)))
+}
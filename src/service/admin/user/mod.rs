@@ -3,7 +3,9 @@ pub(crate) mod user_commands;
 use clap::Subcommand;
 use ruma::events::room::message::RoomMessageEventContent;
 
-use self::user_commands::{create, deactivate, deactivate_all, list, list_joined_rooms, reset_password};
+use self::user_commands::{
+	create, deactivate, deactivate_all, export, list, list_joined_rooms, lock, reactivate, reset_password, suspend,
+};
 use crate::Result;
 
 #[cfg_attr(test, derive(Debug))]
@@ -27,9 +29,49 @@ pub(crate) enum UserCommand {
 	///
 	/// User will not be removed from all rooms by default.
 	/// Use --leave-rooms to force the user to leave all rooms
+	/// Use --erase to also redact the user's events, delete their media and
+	/// clear their profile, account data and key backups. This implies
+	/// --leave-rooms.
 	Deactivate {
 		#[arg(short, long)]
 		leave_rooms: bool,
+		#[arg(short, long)]
+		erase: bool,
+		user_id: String,
+	},
+
+	/// - Reactivate a deactivated user
+	///
+	/// Sets a new password, if unspecified one is generated. Erased users
+	/// cannot be reactivated.
+	Reactivate {
+		user_id: String,
+		password: Option<String>,
+	},
+
+	/// - Suspend a user (MSC3823)
+	///
+	/// Suspended users can still log in and read, but can't send events other
+	/// than redactions and leaves, join rooms, send invites or upload media.
+	Suspend {
+		user_id: String,
+	},
+
+	/// - Lift the suspension of a user
+	Unsuspend {
+		user_id: String,
+	},
+
+	/// - Lock a user (MSC3939)
+	///
+	/// Locked users can't use their account until they are unlocked, every
+	/// request except logging out fails with M_USER_LOCKED.
+	Lock {
+		user_id: String,
+	},
+
+	/// - Unlock a locked user
+	Unlock {
 		user_id: String,
 	},
 
@@ -62,6 +104,16 @@ pub(crate) enum UserCommand {
 	ListJoinedRooms {
 		user_id: String,
 	},
+
+	/// - Export all data we hold about a local user
+	///
+	/// Writes the profile, devices, account data, push rules, pushers, key
+	/// backup metadata, room memberships, sent events and uploaded media of
+	/// the user into a new directory under `exports` in the database
+	/// directory, e.g. to answer a data access request.
+	Export {
+		user_id: String,
+	},
 }
 
 pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<RoomMessageEventContent> {
@@ -73,8 +125,25 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 		} => create(body, username, password).await?,
 		UserCommand::Deactivate {
 			leave_rooms,
+			erase,
 			user_id,
-		} => deactivate(body, leave_rooms, user_id).await?,
+		} => deactivate(body, leave_rooms, erase, user_id).await?,
+		UserCommand::Reactivate {
+			user_id,
+			password,
+		} => reactivate(body, user_id, password).await?,
+		UserCommand::Suspend {
+			user_id,
+		} => suspend(body, user_id, true).await?,
+		UserCommand::Unsuspend {
+			user_id,
+		} => suspend(body, user_id, false).await?,
+		UserCommand::Lock {
+			user_id,
+		} => lock(body, user_id, true).await?,
+		UserCommand::Unlock {
+			user_id,
+		} => lock(body, user_id, false).await?,
 		UserCommand::ResetPassword {
 			username,
 		} => reset_password(body, username).await?,
@@ -85,5 +154,8 @@ pub(crate) async fn process(command: UserCommand, body: Vec<&str>) -> Result<Roo
 		UserCommand::ListJoinedRooms {
 			user_id,
 		} => list_joined_rooms(body, user_id).await?,
+		UserCommand::Export {
+			user_id,
+		} => export(body, user_id).await?,
 	})
 }
@@ -1,10 +1,14 @@
-use std::{fmt::Write as _, sync::Arc};
+use std::{collections::BTreeMap, fmt::Write as _, sync::Arc};
 
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, UserId};
+use ruma::{
+	events::{room::message::RoomMessageEventContent, GlobalAccountDataEventType},
+	OwnedRoomId, OwnedUserId, UserId,
+};
+use serde_json::json;
 use tracing::{error, info, warn};
 
 use crate::{
-	api::client_server::{join_room_by_id_helper, leave_all_rooms, AUTO_GEN_PASSWORD_LENGTH},
+	api::client_server::{erase_account, join_room_by_id_helper, leave_all_rooms, AUTO_GEN_PASSWORD_LENGTH},
 	service::admin::{escape_html, get_room_info},
 	services,
 	utils::{self, user_id::user_is_local},
@@ -15,8 +19,19 @@ pub(crate) async fn list(_body: Vec<&str>) -> Result<RoomMessageEventContent> {
 	match services().users.list_local_users() {
 		Ok(users) => {
 			let mut msg = format!("Found {} local user account(s):\n", users.len());
-			msg += &users.join("\n");
-			Ok(RoomMessageEventContent::text_plain(&msg))
+			for user in &users {
+				msg += user;
+				if let Ok(user_id) = <&UserId>::try_from(user.as_str()) {
+					if services().users.is_suspended(user_id)? {
+						msg += " (suspended)";
+					}
+					if services().users.is_locked(user_id)? {
+						msg += " (locked)";
+					}
+				}
+				msg += "\n";
+			}
+			Ok(RoomMessageEventContent::text_plain(msg.trim_end()))
 		},
 		Err(e) => Ok(RoomMessageEventContent::text_plain(e.to_string())),
 	}
@@ -127,7 +142,7 @@ pub(crate) async fn create(
 }
 
 pub(crate) async fn deactivate(
-	_body: Vec<&str>, leave_rooms: bool, user_id: String,
+	_body: Vec<&str>, leave_rooms: bool, erase: bool, user_id: String,
 ) -> Result<RoomMessageEventContent> {
 	// Validate user id
 	let user_id =
@@ -159,14 +174,24 @@ pub(crate) async fn deactivate(
 	if services().users.exists(&user_id)? {
 		RoomMessageEventContent::text_plain(format!("Making {user_id} leave all rooms before deactivation..."));
 
+		// events have to be redacted while the user is still in the rooms
+		if erase {
+			erase_account(&user_id).await?;
+		}
+
 		services().users.deactivate_account(&user_id)?;
 
-		if leave_rooms {
+		if leave_rooms || erase {
 			leave_all_rooms(&user_id).await?;
 		}
 
 		Ok(RoomMessageEventContent::text_plain(format!(
-			"User {user_id} has been deactivated"
+			"User {user_id} has been deactivated{}",
+			if erase {
+				" and erased"
+			} else {
+				""
+			}
 		)))
 	} else {
 		Ok(RoomMessageEventContent::text_plain(format!(
@@ -217,6 +242,103 @@ pub(crate) async fn reset_password(_body: Vec<&str>, username: String) -> Result
 	}
 }
 
+pub(crate) async fn suspend(_body: Vec<&str>, user_id: String, suspend: bool) -> Result<RoomMessageEventContent> {
+	let user_id = match local_user_id(&user_id) {
+		Ok(user_id) => user_id,
+		Err(msg) => return Ok(RoomMessageEventContent::text_plain(msg)),
+	};
+
+	if suspend && services().users.is_admin(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain("Not allowed to suspend an admin account."));
+	}
+
+	if services().users.is_suspended(&user_id)? == suspend {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already {}",
+			if suspend {
+				"suspended"
+			} else {
+				"not suspended"
+			}
+		)));
+	}
+
+	services().users.set_suspended(&user_id, suspend)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been {}",
+		if suspend {
+			"suspended"
+		} else {
+			"unsuspended"
+		}
+	)))
+}
+
+pub(crate) async fn lock(_body: Vec<&str>, user_id: String, lock: bool) -> Result<RoomMessageEventContent> {
+	let user_id = match local_user_id(&user_id) {
+		Ok(user_id) => user_id,
+		Err(msg) => return Ok(RoomMessageEventContent::text_plain(msg)),
+	};
+
+	if lock && services().users.is_admin(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain("Not allowed to lock an admin account."));
+	}
+
+	if services().users.is_locked(&user_id)? == lock {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is already {}",
+			if lock {
+				"locked"
+			} else {
+				"not locked"
+			}
+		)));
+	}
+
+	services().users.set_locked(&user_id, lock)?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been {}",
+		if lock {
+			"locked"
+		} else {
+			"unlocked"
+		}
+	)))
+}
+
+pub(crate) async fn reactivate(
+	_body: Vec<&str>, user_id: String, password: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = match local_user_id(&user_id) {
+		Ok(user_id) => user_id,
+		Err(msg) => return Ok(RoomMessageEventContent::text_plain(msg)),
+	};
+
+	if !services().users.is_deactivated(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is not deactivated."
+		)));
+	}
+
+	// the events and profile of erased accounts are gone for good
+	if services().users.is_erased(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} has been erased and cannot be reactivated."
+		)));
+	}
+
+	let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+	services()
+		.users
+		.set_password(&user_id, Some(password.as_str()))?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"User {user_id} has been reactivated with password: `{password}`"
+	)))
+}
+
 pub(crate) async fn deactivate_all(body: Vec<&str>, leave_rooms: bool, force: bool) -> Result<RoomMessageEventContent> {
 	if body.len() > 2 && body[0].trim().starts_with("```") && body.last().unwrap().trim() == "```" {
 		let usernames = body.clone().drain(1..body.len() - 1).collect::<Vec<_>>();
@@ -361,3 +483,187 @@ pub(crate) async fn list_joined_rooms(_body: Vec<&str>, user_id: String) -> Resu
 	);
 	Ok(RoomMessageEventContent::text_html(output_plain, output_html))
 }
+
+pub(crate) async fn export(_body: Vec<&str>, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id =
+		match UserId::parse_with_server_name(user_id.as_str().to_lowercase(), services().globals.server_name()) {
+			Ok(id) => id,
+			Err(e) => {
+				return Ok(RoomMessageEventContent::text_plain(format!(
+					"The supplied username is not a valid username: {e}"
+				)))
+			},
+		};
+
+	if !user_is_local(&user_id) {
+		return Ok(RoomMessageEventContent::text_plain("User does not belong to our server."));
+	}
+
+	if !services().users.exists(&user_id)? {
+		return Ok(RoomMessageEventContent::text_plain("User does not exist on this server."));
+	}
+
+	let export_dir = services().globals.get_export_folder().join(format!(
+		"{}-{}",
+		user_id.localpart(),
+		utils::millis_since_unix_epoch()
+	));
+	let media_dir = export_dir.join("media");
+	tokio::fs::create_dir_all(&media_dir).await?;
+
+	let devices = services()
+		.users
+		.all_devices_metadata(&user_id)
+		.filter_map(Result::ok)
+		.collect::<Vec<_>>();
+
+	let threepids = services()
+		.threepid
+		.threepids(&user_id)
+		.filter_map(Result::ok)
+		.collect::<Vec<_>>();
+
+	let joined_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_joined(&user_id)
+		.filter_map(Result::ok)
+		.collect::<Vec<_>>();
+	let left_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_left(&user_id)
+		.filter_map(Result::ok)
+		.map(|(room_id, _)| room_id)
+		.collect::<Vec<_>>();
+	let invited_rooms = services()
+		.rooms
+		.state_cache
+		.rooms_invited(&user_id)
+		.filter_map(Result::ok)
+		.map(|(room_id, _)| room_id)
+		.collect::<Vec<_>>();
+
+	let mut room_account_data = BTreeMap::new();
+	for room_id in joined_rooms.iter().chain(&left_rooms).chain(&invited_rooms) {
+		let account_data = services()
+			.account_data
+			.changes_since(Some(room_id), &user_id, 0)?;
+		if !account_data.is_empty() {
+			room_account_data.insert(room_id.clone(), account_data);
+		}
+	}
+
+	let key_backup = match services().key_backups.get_latest_backup(&user_id)? {
+		Some((version, algorithm)) => Some(json!({
+			"version": version,
+			"algorithm": algorithm,
+			"etag": services().key_backups.get_etag(&user_id, &version)?,
+			"count": services().key_backups.count_keys(&user_id, &version)?,
+		})),
+		None => None,
+	};
+
+	// Events are only kept in rooms we were in, which are the ones the user joined
+	// or left at some point
+	let mut events = BTreeMap::new();
+	let mut event_count: usize = 0;
+	for room_id in joined_rooms.iter().chain(&left_rooms) {
+		let room_events = services()
+			.rooms
+			.timeline
+			.all_pdus(&user_id, room_id)?
+			.filter_map(Result::ok)
+			.filter(|(_, pdu)| pdu.sender == user_id)
+			.map(|(_, pdu)| pdu.to_room_event())
+			.collect::<Vec<_>>();
+		if !room_events.is_empty() {
+			event_count = event_count.saturating_add(room_events.len());
+			events.insert(room_id.clone(), room_events);
+		}
+	}
+
+	let mut media = Vec::new();
+	for mxc in services().media.get_all_user_mxcs(&user_id) {
+		let Some(file) = services().media.get(mxc.clone()).await? else {
+			continue;
+		};
+
+		let file_name = mxc.rsplit('/').next().unwrap_or_default().to_owned();
+		tokio::fs::write(media_dir.join(&file_name), &file.file).await?;
+
+		media.push(json!({
+			"mxc": mxc,
+			"content_type": file.content_type,
+			"content_disposition": file.content_disposition,
+			"file": format!("media/{file_name}"),
+		}));
+	}
+
+	let export = json!({
+		"user_id": user_id,
+		"exported_at": utils::millis_since_unix_epoch(),
+		"profile": {
+			"displayname": services().users.displayname(&user_id)?,
+			"avatar_url": services().users.avatar_url(&user_id)?,
+			"blurhash": services().users.blurhash(&user_id)?,
+		},
+		"threepids": threepids,
+		"devices": devices,
+		"account_data": {
+			"global": services().account_data.changes_since(None, &user_id, 0)?,
+			"rooms": room_account_data,
+		},
+		"push_rules": services().account_data.get(
+			None,
+			&user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+		)?,
+		"pushers": services().pusher.get_pushers(&user_id)?,
+		"key_backup": key_backup,
+		"rooms": {
+			"joined": joined_rooms,
+			"left": left_rooms,
+			"invited": invited_rooms,
+		},
+		"events": events,
+		"media": media,
+	});
+
+	tokio::fs::write(
+		export_dir.join("export.json"),
+		serde_json::to_vec_pretty(&export).expect("export is valid json"),
+	)
+	.await?;
+
+	info!("Exported data of user {user_id} to {}", export_dir.display());
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Exported data of {user_id} ({event_count} events, {} media files) to {}",
+		media.len(),
+		export_dir.display()
+	)))
+}
+
+/// Parses a user id or localpart into a user id of an existing local user that
+/// is not the service account.
+fn local_user_id(user_id: &str) -> std::result::Result<OwnedUserId, String> {
+	let user_id = UserId::parse_with_server_name(user_id.to_lowercase(), services().globals.server_name())
+		.map_err(|e| format!("The supplied username is not a valid username: {e}"))?;
+
+	if !user_is_local(&user_id) {
+		return Err(format!("User {user_id} does not belong to our server."));
+	}
+
+	if user_id
+		== UserId::parse_with_server_name("conduit", services().globals.server_name()).expect("conduit user exists")
+	{
+		return Err("Not allowed to modify the Conduit service account.".to_owned());
+	}
+
+	match services().users.exists(&user_id) {
+		Ok(true) => Ok(user_id),
+		Ok(false) => Err(format!("User {user_id} doesn't exist on this server")),
+		Err(e) => Err(e.to_string()),
+	}
+}
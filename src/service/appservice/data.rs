@@ -1,10 +1,11 @@
 use ruma::api::appservice::Registration;
 
+use super::RegistrationExtensions;
 use crate::Result;
 
 pub(crate) trait Data: Send + Sync {
 	/// Registers an appservice and returns the ID to the caller
-	fn register_appservice(&self, yaml: Registration) -> Result<String>;
+	fn register_appservice(&self, yaml: Registration, extensions: RegistrationExtensions) -> Result<String>;
 
 	/// Remove an appservice registration
 	///
@@ -15,7 +16,18 @@ pub(crate) trait Data: Send + Sync {
 
 	fn get_registration(&self, id: &str) -> Result<Option<Registration>>;
 
+	/// Returns the MSC options of the registration, all disabled if the
+	/// appservice doesn't exist
+	fn get_extensions(&self, id: &str) -> Result<RegistrationExtensions>;
+
 	fn iter_ids<'a>(&'a self) -> Result<Box<dyn Iterator<Item = Result<String>> + 'a>>;
 
 	fn all(&self) -> Result<Vec<(String, Registration)>>;
+
+	/// Remembers that the registration is managed by the config file at
+	/// `path`, or that it isn't anymore
+	fn set_config_file(&self, id: &str, path: Option<&str>) -> Result<()>;
+
+	/// IDs and config file paths of all registrations managed by config files
+	fn config_files(&self) -> Result<Vec<(String, String)>>;
 }
@@ -1,6 +1,10 @@
 mod data;
 
-use std::collections::BTreeMap;
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fs,
+	path::Path,
+};
 
 pub(crate) use data::Data;
 use futures_util::Future;
@@ -9,9 +13,11 @@ use ruma::{
 	api::appservice::{Namespace, Registration},
 	RoomAliasId, RoomId, UserId,
 };
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
+use tracing::info;
 
-use crate::{services, Result};
+use crate::{services, Error, Result};
 
 /// Compiled regular expressions for a namespace
 #[derive(Clone, Debug)]
@@ -86,10 +92,28 @@ impl TryFrom<Vec<Namespace>> for NamespaceRegex {
 	}
 }
 
+/// Registration options from MSCs which aren't part of ruma's `Registration`
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct RegistrationExtensions {
+	/// MSC2409: push typing, receipts, presence and to-device messages
+	#[serde(
+		default,
+		rename = "de.sorunome.msc2409.push_ephemeral",
+		alias = "receive_ephemeral",
+		alias = "receivesEphemeral"
+	)]
+	pub(crate) receive_ephemeral: bool,
+
+	/// MSC3202: push device list changes and one-time key counts
+	#[serde(default, rename = "org.matrix.msc3202")]
+	pub(crate) msc3202: bool,
+}
+
 /// Appservice registration combined with its compiled regular expressions.
 #[derive(Clone, Debug)]
 pub(crate) struct RegistrationInfo {
 	pub(crate) registration: Registration,
+	pub(crate) extensions: RegistrationExtensions,
 	pub(crate) users: NamespaceRegex,
 	pub(crate) aliases: NamespaceRegex,
 	pub(crate) rooms: NamespaceRegex,
@@ -104,6 +128,7 @@ impl TryFrom<Registration> for RegistrationInfo {
 			aliases: value.namespaces.aliases.clone().try_into()?,
 			rooms: value.namespaces.rooms.clone().try_into()?,
 			registration: value,
+			extensions: RegistrationExtensions::default(),
 		})
 	}
 }
@@ -117,14 +142,12 @@ impl Service {
 	pub(crate) fn build(db: &'static dyn Data) -> Result<Self> {
 		let mut registration_info = BTreeMap::new();
 		// Inserting registrations into cache
-		for appservice in db.all()? {
-			registration_info.insert(
-				appservice.0,
-				appservice
-					.1
-					.try_into()
-					.expect("Should be validated on registration"),
-			);
+		for (id, registration) in db.all()? {
+			let mut info: RegistrationInfo = registration
+				.try_into()
+				.expect("Should be validated on registration");
+			info.extensions = db.get_extensions(&id)?;
+			registration_info.insert(id, info);
 		}
 
 		Ok(Self {
@@ -133,17 +156,47 @@ impl Service {
 		})
 	}
 
+	/// Registers an appservice from the admin room and returns its ID, unless
+	/// its ID is managed by `app_service_config_files` or it conflicts with
+	/// another appservice
+	pub(crate) async fn register_appservice_from_admin(
+		&self, yaml: Registration, extensions: RegistrationExtensions,
+	) -> Result<String> {
+		if self.db.config_files()?.iter().any(|(id, _)| *id == yaml.id) {
+			return Err(Error::AdminCommand(
+				"This appservice ID is managed by app_service_config_files, edit its file instead.",
+			));
+		}
+
+		let mut info: RegistrationInfo = yaml.clone().try_into()?;
+		info.extensions = extensions;
+		let mut infos = self
+			.read()
+			.await
+			.values()
+			.filter(|other| other.registration.id != yaml.id)
+			.cloned()
+			.collect::<Vec<_>>();
+		infos.push(info);
+		check_conflicts(&infos).map_err(Error::Err)?;
+
+		self.register_appservice(yaml, extensions).await
+	}
+
 	/// Registers an appservice and returns the ID to the caller
-	pub(crate) async fn register_appservice(&self, yaml: Registration) -> Result<String> {
-		//TODO: Check for collisions between exclusive appservice namespaces
+	pub(crate) async fn register_appservice(
+		&self, yaml: Registration, extensions: RegistrationExtensions,
+	) -> Result<String> {
+		let mut info: RegistrationInfo = yaml.clone().try_into()?;
+		info.extensions = extensions;
 		services()
 			.appservice
 			.registration_info
 			.write()
 			.await
-			.insert(yaml.id.clone(), yaml.clone().try_into()?);
+			.insert(yaml.id.clone(), info);
 
-		self.db.register_appservice(yaml)
+		self.db.register_appservice(yaml, extensions)
 	}
 
 	/// Remove an appservice registration
@@ -171,6 +224,72 @@ impl Service {
 		Ok(())
 	}
 
+	/// Registers, updates or unregisters appservices to match the registrations
+	/// in `app_service_config_files`. Appservices registered in the admin room
+	/// are kept unless a file uses the same ID. Nothing is changed if a file
+	/// can't be read or the registrations conflict.
+	pub(crate) async fn load_config_files(&self) -> Result<()> {
+		let mut registrations = BTreeMap::new();
+		for path in &services().globals.config.app_service_config_files {
+			let (registration, extensions) = read_registration_file(path)?;
+			if registrations.contains_key(&registration.id) {
+				return Err(Error::bad_config(&format!(
+					"Appservice ID {} is used by more than one file in app_service_config_files.",
+					registration.id
+				)));
+			}
+
+			registrations.insert(registration.id.clone(), (path.display().to_string(), registration, extensions));
+		}
+
+		let managed = self
+			.db
+			.config_files()?
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+
+		let mut infos = self
+			.read()
+			.await
+			.values()
+			.filter(|info| {
+				!registrations.contains_key(&info.registration.id) && !managed.contains_key(&info.registration.id)
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		for (path, registration, extensions) in registrations.values() {
+			let mut info = RegistrationInfo::try_from(registration.clone()).map_err(|e| {
+				Error::bad_config(&format!("Invalid namespace regex in appservice config file {path}: {e}"))
+			})?;
+			info.extensions = *extensions;
+			infos.push(info);
+		}
+		check_conflicts(&infos).map_err(|e| Error::bad_config(&e))?;
+
+		for (id, (path, registration, extensions)) in &registrations {
+			let unchanged = managed.get(id) == Some(path)
+				&& self.db.get_extensions(id)? == *extensions
+				&& self
+					.db
+					.get_registration(id)?
+					.is_some_and(|stored| serde_yaml::to_value(stored).ok() == serde_yaml::to_value(registration).ok());
+
+			if !unchanged {
+				info!("Registering appservice {id} from {path}");
+				self.register_appservice(registration.clone(), *extensions)
+					.await?;
+				self.db.set_config_file(id, Some(path))?;
+			}
+		}
+
+		for id in managed.keys().filter(|id| !registrations.contains_key(*id)) {
+			info!("Unregistering appservice {id} as it was removed from app_service_config_files");
+			self.unregister_appservice(id).await?;
+		}
+
+		Ok(())
+	}
+
 	pub(crate) async fn get_registration(&self, id: &str) -> Option<Registration> {
 		self.registration_info
 			.read()
@@ -229,4 +348,62 @@ impl Service {
 	) -> impl Future<Output = tokio::sync::RwLockReadGuard<'_, BTreeMap<String, RegistrationInfo>>> {
 		self.registration_info.read()
 	}
+
+	/// Like `read`, for code that can't await the lock. It is only written
+	/// while an appservice is (un)registered, so this rarely has to wait.
+	pub(crate) fn read_sync(&self) -> tokio::sync::RwLockReadGuard<'_, BTreeMap<String, RegistrationInfo>> {
+		self.registration_info
+			.try_read()
+			.unwrap_or_else(|_| tokio::task::block_in_place(|| self.registration_info.blocking_read()))
+	}
+}
+
+fn read_registration_file(path: &Path) -> Result<(Registration, RegistrationExtensions)> {
+	let yaml = fs::read_to_string(path)
+		.map_err(|e| Error::bad_config(&format!("Failed to read appservice config file {}: {e}", path.display())))?;
+
+	serde_yaml::from_str::<Registration>(&yaml)
+		.and_then(|registration| Ok((registration, serde_yaml::from_str::<RegistrationExtensions>(&yaml)?)))
+		.map_err(|e| Error::bad_config(&format!("Invalid appservice config file {}: {e}", path.display())))
+}
+
+/// Makes sure no two appservices share an ID, an `as_token` or an exclusive
+/// namespace
+///
+/// Exclusive namespaces only conflict if their regexes are written the same
+/// way. Whether two different regexes can match the same ID isn't checked, so
+/// overlapping namespaces like `@bridge_.*` and `@bridge_a.*` are accepted.
+fn check_conflicts(infos: &[RegistrationInfo]) -> Result<(), String> {
+	let mut ids = HashSet::new();
+	let mut as_tokens = HashMap::new();
+	let mut exclusive_namespaces = HashMap::new();
+
+	for info in infos {
+		let id = info.registration.id.as_str();
+		if !ids.insert(id) {
+			return Err(format!("Appservice ID {id} is registered more than once."));
+		}
+
+		if let Some(other) = as_tokens.insert(info.registration.as_token.as_str(), id) {
+			return Err(format!("Appservices {other} and {id} use the same as_token."));
+		}
+
+		let namespaces = &info.registration.namespaces;
+		for (kind, namespaces) in [
+			("users", &namespaces.users),
+			("aliases", &namespaces.aliases),
+			("rooms", &namespaces.rooms),
+		] {
+			for namespace in namespaces.iter().filter(|namespace| namespace.exclusive) {
+				if let Some(other) = exclusive_namespaces.insert((kind, namespace.regex.as_str()), id) {
+					return Err(format!(
+						"Appservices {other} and {id} both claim the exclusive {kind} namespace {}.",
+						namespace.regex
+					));
+				}
+			}
+		}
+	}
+
+	Ok(())
 }
@@ -0,0 +1,103 @@
+mod webhook;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ruma::{api::client::error::ErrorKind, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{Config, Error, PduEvent, Result};
+
+/// What is being checked. This is also what the webhook receives as JSON.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum PolicyCheck<'a> {
+	/// A new event, either created by a local user or received over federation
+	Event {
+		event: &'a PduEvent,
+		local: bool,
+	},
+	Invite {
+		sender: &'a UserId,
+		invitee: &'a UserId,
+		room_id: &'a RoomId,
+	},
+	Registration {
+		username: &'a str,
+		guest: bool,
+	},
+	MediaUpload {
+		sender: &'a UserId,
+		content_type: Option<&'a str>,
+		filename: Option<&'a str>,
+		size: usize,
+	},
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum Verdict {
+	Allow,
+	Deny {
+		#[serde(default)]
+		reason: Option<String>,
+	},
+	/// The event is accepted but not shown to anyone. For anything that isn't
+	/// an event this is the same as denying.
+	SoftFail,
+}
+
+/// A hook which can veto events, invites, registrations and media uploads.
+/// The first hook returning something other than `Allow` decides.
+#[async_trait]
+pub(crate) trait EventPolicy: Send + Sync {
+	async fn check(&self, check: &PolicyCheck<'_>) -> Result<Verdict>;
+}
+
+pub(crate) struct Service {
+	hooks: Vec<Box<dyn EventPolicy>>,
+}
+
+impl Service {
+	pub(crate) fn build(config: &Config) -> Self {
+		let mut hooks: Vec<Box<dyn EventPolicy>> = Vec::new();
+		if let Some(url) = &config.event_policy_webhook {
+			hooks.push(Box::new(webhook::Webhook {
+				url: url.clone(),
+				token: config.event_policy_webhook_token.clone(),
+				timeout: Duration::from_secs(config.event_policy_webhook_timeout),
+				fail_open: config.event_policy_webhook_fail_open,
+			}));
+		}
+
+		Self {
+			hooks,
+		}
+	}
+
+	pub(crate) async fn check(&self, check: &PolicyCheck<'_>) -> Result<Verdict> {
+		for hook in &self.hooks {
+			match hook.check(check).await? {
+				Verdict::Allow => continue,
+				verdict => return Ok(verdict),
+			}
+		}
+
+		Ok(Verdict::Allow)
+	}
+
+	/// Like `check`, but returns a forbidden error unless the check is allowed
+	pub(crate) async fn enforce(&self, check: &PolicyCheck<'_>) -> Result<()> {
+		match self.check(check).await? {
+			Verdict::Allow => Ok(()),
+			verdict => {
+				info!("Event policy rejected {check:?}: {verdict:?}");
+				Err(Error::BadRequest(
+					ErrorKind::forbidden(),
+					"Rejected by this server's event policy.",
+				))
+			},
+		}
+	}
+}
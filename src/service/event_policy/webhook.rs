@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tracing::warn;
+use url::Url;
+
+use super::{EventPolicy, PolicyCheck, Verdict};
+use crate::{services, Error, Result};
+
+/// Asks an HTTP service for a verdict by POSTing the check as JSON. The
+/// service answers with `{"action": "allow" | "deny" | "soft_fail"}` and an
+/// optional `reason`.
+pub(super) struct Webhook {
+	pub(super) url: Url,
+	pub(super) token: Option<String>,
+	pub(super) timeout: Duration,
+	/// Whether to allow everything while the webhook is unreachable
+	pub(super) fail_open: bool,
+}
+
+impl Webhook {
+	async fn request(&self, check: &PolicyCheck<'_>) -> Result<Verdict> {
+		let body = serde_json::to_vec(check).expect("policy checks can be serialized");
+		let mut request = services()
+			.globals
+			.client
+			.default
+			.post(self.url.clone())
+			.timeout(self.timeout)
+			.header("Content-Type", "application/json")
+			.body(body);
+
+		if let Some(token) = &self.token {
+			request = request.bearer_auth(token);
+		}
+
+		let response = request.send().await?.error_for_status()?;
+		serde_json::from_slice(&response.bytes().await?)
+			.map_err(|_| Error::BadServerResponse("Invalid response from event policy webhook."))
+	}
+}
+
+#[async_trait]
+impl EventPolicy for Webhook {
+	async fn check(&self, check: &PolicyCheck<'_>) -> Result<Verdict> {
+		match self.request(check).await {
+			Ok(verdict) => Ok(verdict),
+			Err(e) if self.fail_open => {
+				warn!("Event policy webhook failed, allowing: {e}");
+				Ok(Verdict::Allow)
+			},
+			Err(e) => {
+				warn!("Event policy webhook failed, denying: {e}");
+				Ok(Verdict::Deny {
+					reason: Some("Event policy webhook is unavailable".to_owned()),
+				})
+			},
+		}
+	}
+}
@@ -0,0 +1,17 @@
+use ruma::{OwnedRoomId, RoomId};
+
+use super::QueuedPdu;
+use crate::Result;
+
+pub(crate) trait Data: Send + Sync {
+	/// Appends the pdus to the queues of their rooms, returns their queue keys
+	fn queue_incoming_pdus(&self, pdus: &[QueuedPdu]) -> Result<Vec<Vec<u8>>>;
+
+	/// Returns the oldest queued pdu of the room and its queue key
+	fn next_incoming_pdu(&self, room_id: &RoomId) -> Result<Option<(Vec<u8>, QueuedPdu)>>;
+
+	fn remove_incoming_pdu(&self, key: &[u8]) -> Result<()>;
+
+	/// Returns all rooms with queued pdus and the number of queued pdus
+	fn queued_incoming_rooms(&self) -> Result<Vec<(OwnedRoomId, usize)>>;
+}
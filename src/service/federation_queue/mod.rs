@@ -0,0 +1,212 @@
+mod data;
+
+use std::{
+	collections::{hash_map::Entry, BTreeMap, HashMap},
+	sync::{Arc, Mutex as StdMutex},
+	time::{Duration, Instant},
+};
+
+pub(crate) use data::Data;
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	CanonicalJsonObject, OwnedEventId, OwnedRoomId, OwnedServerName, RoomId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{debug, error, warn};
+
+use crate::{services, Config, Error, Result};
+
+/// A pdu received in a federation transaction that has not been handled yet
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct QueuedPdu {
+	pub(crate) origin: OwnedServerName,
+	pub(crate) room_id: OwnedRoomId,
+	pub(crate) event_id: OwnedEventId,
+	pub(crate) pdu: CanonicalJsonObject,
+}
+
+/// Queues incoming federation pdus in the database and handles them with one
+/// worker per room, so rooms are processed concurrently while the pdus of a
+/// room are handled in the order they arrived.
+pub(crate) struct Service {
+	pub(crate) db: &'static dyn Data,
+
+	/// Number of queued pdus of every room that currently has a worker
+	rooms: StdMutex<HashMap<OwnedRoomId, usize>>,
+	max_queued_per_room: usize,
+	max_queued: usize,
+}
+
+impl Service {
+	pub(crate) fn build(db: &'static dyn Data, config: &Config) -> Arc<Self> {
+		Arc::new(Self {
+			db,
+			rooms: StdMutex::new(HashMap::new()),
+			max_queued_per_room: config.max_queued_incoming_pdus_per_room,
+			max_queued: config.max_queued_incoming_pdus,
+		})
+	}
+
+	/// Resumes handling the pdus that were still queued on shutdown
+	pub(crate) fn start_handler(self: &Arc<Self>) {
+		let queued = match self.db.queued_incoming_rooms() {
+			Ok(queued) => queued,
+			Err(e) => {
+				error!("Failed to load queued incoming pdus: {e}");
+				return;
+			},
+		};
+
+		let mut rooms = self.rooms.lock().expect("locked");
+		for (room_id, count) in queued {
+			debug!("Resuming {count} queued incoming pdus in {room_id}");
+			rooms.insert(room_id.clone(), count);
+			self.spawn_worker(room_id);
+		}
+	}
+
+	/// Queues the pdus of a transaction. Either all or none of them are queued;
+	/// if the queues are too full the remote is told to retry later.
+	pub(crate) fn queue(self: &Arc<Self>, pdus: &[QueuedPdu]) -> Result<()> {
+		if pdus.is_empty() {
+			return Ok(());
+		}
+
+		let mut new_counts = HashMap::<&RoomId, usize>::new();
+		for pdu in pdus {
+			*new_counts.entry(&pdu.room_id).or_default() += 1;
+		}
+
+		let mut rooms = self.rooms.lock().expect("locked");
+		let total = rooms.values().sum::<usize>();
+		let room_full = new_counts
+			.iter()
+			.any(|(room_id, count)| rooms.get(*room_id).copied().unwrap_or(0) + count > self.max_queued_per_room);
+
+		if room_full || total + pdus.len() > self.max_queued {
+			warn!(
+				"Incoming federation queues are full, rejecting transaction with {} pdus",
+				pdus.len()
+			);
+			return Err(Error::BadRequest(
+				ErrorKind::LimitExceeded {
+					retry_after: Some(RetryAfter::Delay(Duration::from_secs(5))),
+				},
+				"Too many events are queued, try again later.",
+			));
+		}
+
+		self.db.queue_incoming_pdus(pdus)?;
+
+		for (room_id, count) in new_counts {
+			match rooms.entry(room_id.to_owned()) {
+				Entry::Occupied(mut entry) => *entry.get_mut() += count,
+				Entry::Vacant(entry) => {
+					entry.insert(count);
+					self.spawn_worker(room_id.to_owned());
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Rooms with queued pdus and the number of queued pdus, deepest first
+	pub(crate) fn queue_depths(&self) -> Vec<(OwnedRoomId, usize)> {
+		let mut depths = self
+			.rooms
+			.lock()
+			.expect("locked")
+			.iter()
+			.filter(|(_, count)| **count > 0)
+			.map(|(room_id, count)| (room_id.clone(), *count))
+			.collect::<Vec<_>>();
+
+		depths.sort_by(|a, b| b.1.cmp(&a.1));
+		depths
+	}
+
+	fn spawn_worker(self: &Arc<Self>, room_id: OwnedRoomId) {
+		let self2 = Arc::clone(self);
+		tokio::spawn(async move {
+			self2.handle_room(room_id).await;
+		});
+	}
+
+	#[tracing::instrument(skip(self))]
+	async fn handle_room(&self, room_id: OwnedRoomId) {
+		loop {
+			// the count is only changed while holding the lock, together with the database
+			{
+				let mut rooms = self.rooms.lock().expect("locked");
+				if rooms.get(&room_id).copied().unwrap_or(0) == 0 {
+					rooms.remove(&room_id);
+					return;
+				}
+			}
+
+			match self.db.next_incoming_pdu(&room_id) {
+				Ok(Some((key, queued))) => {
+					handle_pdu(queued).await;
+					if let Err(e) = self.db.remove_incoming_pdu(&key) {
+						error!("Failed to remove handled pdu from the queue: {e}");
+					}
+				},
+				Ok(None) => {
+					error!("Queue of {room_id} is empty while pdus are counted");
+				},
+				Err(e) => {
+					error!("Failed to load queued pdu: {e}");
+				},
+			}
+
+			if let Some(count) = self.rooms.lock().expect("locked").get_mut(&room_id) {
+				*count = count.saturating_sub(1);
+			}
+		}
+	}
+}
+
+async fn handle_pdu(queued: QueuedPdu) {
+	let start_time = Instant::now();
+	let QueuedPdu {
+		origin,
+		room_id,
+		event_id,
+		pdu,
+	} = queued;
+
+	let pub_key_map = RwLock::new(BTreeMap::new());
+	if let Err(e) = services()
+		.rooms
+		.event_handler
+		.fetch_required_signing_keys([&pdu], &pub_key_map)
+		.await
+	{
+		warn!("Could not fetch all signatures for PDU {event_id} from {origin}: {e}");
+	}
+
+	let mutex = Arc::clone(
+		services()
+			.globals
+			.roomid_mutex_federation
+			.write()
+			.await
+			.entry(room_id.clone())
+			.or_default(),
+	);
+	let mutex_lock = mutex.lock().await;
+	let result = services()
+		.rooms
+		.event_handler
+		.handle_incoming_pdu(&origin, &room_id, &event_id, pdu, true, &pub_key_map)
+		.await;
+	drop(mutex_lock);
+
+	if let Err(e) = result {
+		warn!("Incoming PDU {event_id} from {origin} failed: {e}");
+	}
+
+	debug!(elapsed = ?start_time.elapsed(), "Finished PDU {event_id}");
+}
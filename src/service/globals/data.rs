@@ -2,7 +2,8 @@ use std::{collections::BTreeMap, error::Error};
 
 use async_trait::async_trait;
 use ruma::{
-	api::federation::discovery::{ServerSigningKeys, VerifyKey},
+	api::federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
+	serde::Raw,
 	signatures::Ed25519KeyPair,
 	DeviceId, OwnedServerSigningKeyId, ServerName, UserId,
 };
@@ -32,6 +33,14 @@ pub(crate) trait Data: Send + Sync {
 	fn clear_caches(&self, amount: u32);
 	fn load_keypair(&self) -> Result<Ed25519KeyPair>;
 	fn remove_keypair(&self) -> Result<()>;
+
+	/// Replaces the stored keypair with the given one, in the format returned
+	/// by `utils::generate_keypair`.
+	fn replace_keypair(&self, keypair: &[u8]) -> Result<()>;
+
+	/// Our own keys which are no longer used for signing.
+	fn old_verify_keys(&self) -> Result<BTreeMap<OwnedServerSigningKeyId, OldVerifyKey>>;
+	fn add_old_verify_key(&self, key_id: OwnedServerSigningKeyId, key: OldVerifyKey) -> Result<()>;
 	fn add_signing_key(
 		&self, origin: &ServerName, new_keys: ServerSigningKeys,
 	) -> Result<BTreeMap<OwnedServerSigningKeyId, VerifyKey>>;
@@ -39,6 +48,14 @@ pub(crate) trait Data: Send + Sync {
 	/// This returns an empty `Ok(BTreeMap<..>)` when there are no keys found
 	/// for the server.
 	fn signing_keys_for(&self, origin: &ServerName) -> Result<BTreeMap<OwnedServerSigningKeyId, VerifyKey>>;
+
+	/// Stores the key response of the server as we received it, unless the
+	/// stored one is valid for longer.
+	fn add_signed_server_keys(&self, origin: &ServerName, keys: &Raw<ServerSigningKeys>) -> Result<()>;
+
+	/// The latest key response we received from the server, unmodified so the
+	/// origin's signatures are still valid.
+	fn signed_server_keys(&self, origin: &ServerName) -> Result<Option<Raw<ServerSigningKeys>>>;
 	fn database_version(&self) -> Result<u64>;
 	fn bump_database_version(&self, new_version: u64) -> Result<()>;
 	fn backup(&self) -> Result<(), Box<dyn Error>> { unimplemented!() }
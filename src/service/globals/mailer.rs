@@ -0,0 +1,101 @@
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	transport::smtp::authentication::Credentials,
+	AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use tracing::{debug, error};
+
+use crate::{
+	config::{SmtpConfig, SmtpSecurity},
+	Error, Result,
+};
+
+enum Transport {
+	Smtp(AsyncSmtpTransport<Tokio1Executor>),
+	File(AsyncFileTransport<Tokio1Executor>),
+}
+
+/// Sends plain text emails through the configured SMTP relay, or writes them
+/// into the configured file sink.
+pub(crate) struct Mailer {
+	from: Mailbox,
+	transport: Transport,
+}
+
+impl Mailer {
+	pub(crate) fn new(config: &SmtpConfig) -> Result<Self> {
+		let from = config
+			.from
+			.parse::<Mailbox>()
+			.map_err(|_| Error::bad_config("SMTP \"from\" is not a valid mailbox."))?;
+
+		let transport = if let Some(path) = &config.file_sink_path {
+			Transport::File(AsyncFileTransport::new(path))
+		} else {
+			let host = config
+				.host
+				.as_deref()
+				.ok_or_else(|| Error::bad_config("SMTP host is not configured."))?;
+
+			let mut builder = match config.security {
+				SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+				SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| {
+					error!("Failed to set up SMTP STARTTLS relay: {e}");
+					Error::bad_config("Invalid SMTP host.")
+				})?,
+				SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host).map_err(|e| {
+					error!("Failed to set up SMTP TLS relay: {e}");
+					Error::bad_config("Invalid SMTP host.")
+				})?,
+			};
+
+			if let Some(port) = config.port {
+				builder = builder.port(port);
+			}
+
+			if let (Some(username), Some(password)) = (&config.username, &config.password) {
+				builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+			}
+
+			Transport::Smtp(builder.build())
+		};
+
+		Ok(Self {
+			from,
+			transport,
+		})
+	}
+
+	/// Sends a plain text email to a single recipient.
+	pub(crate) async fn send(&self, to: &str, subject: &str, body: String) -> Result<()> {
+		let to = to
+			.parse::<Mailbox>()
+			.map_err(|_| Error::BadServerResponse("Invalid recipient email address."))?;
+
+		let message = Message::builder()
+			.from(self.from.clone())
+			.to(to)
+			.subject(subject)
+			.header(ContentType::TEXT_PLAIN)
+			.body(body)
+			.map_err(|e| Error::Err(format!("Failed to build email: {e}")))?;
+
+		match &self.transport {
+			Transport::Smtp(transport) => {
+				transport.send(message).await.map_err(|e| {
+					error!("Failed to send email over SMTP: {e}");
+					Error::BadServerResponse("Failed to send email.")
+				})?;
+			},
+			Transport::File(transport) => {
+				let id = transport.send(message).await.map_err(|e| {
+					error!("Failed to write email to file sink: {e}");
+					Error::BadServerResponse("Failed to send email.")
+				})?;
+				debug!("Wrote email {id} to file sink");
+			},
+		}
+
+		Ok(())
+	}
+}
@@ -7,7 +7,7 @@ use std::{
 		atomic::{self, AtomicBool},
 		Arc,
 	},
-	time::{Instant, SystemTime},
+	time::{Duration, Instant, SystemTime},
 };
 
 use argon2::Argon2;
@@ -19,20 +19,21 @@ use regex::RegexSet;
 use ruma::{
 	api::{
 		client::{discovery::discover_support::ContactRole, sync::sync_events},
-		federation::discovery::{ServerSigningKeys, VerifyKey},
+		federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
 	},
-	serde::Base64,
-	DeviceId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedServerSigningKeyId, OwnedUserId,
-	RoomVersionId, ServerName, UserId,
+	serde::{Base64, Raw},
+	DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedServerName,
+	OwnedServerSigningKeyId, OwnedUserId, RoomVersionId, ServerName, UserId,
 };
 use tokio::sync::{broadcast, watch::Receiver, Mutex, RwLock};
 use tracing::{error, info, trace};
 use url::Url;
 
-use crate::{services, Config, LogLevelReloadHandles, Result};
+use crate::{services, utils, Config, LogLevelReloadHandles, Result};
 
 mod client;
 mod data;
+pub(crate) mod mailer;
 mod resolver;
 
 type RateLimitState = (Instant, u32); // Time if last failed try, number of failed tries
@@ -47,10 +48,11 @@ pub(crate) struct Service<'a> {
 	pub(crate) tracing_reload_handle: LogLevelReloadHandles,
 	pub(crate) config: Config,
 	pub(crate) cidr_range_denylist: Vec<IPAddress>,
-	keypair: Arc<ruma::signatures::Ed25519KeyPair>,
+	keypair: std::sync::RwLock<Arc<ruma::signatures::Ed25519KeyPair>>,
 	jwt_decoding_key: Option<jsonwebtoken::DecodingKey>,
 	pub(crate) resolver: Arc<resolver::Resolver>,
 	pub(crate) client: client::Client,
+	pub(crate) mailer: Option<mailer::Mailer>,
 	pub(crate) stable_room_versions: Vec<RoomVersionId>,
 	pub(crate) unstable_room_versions: Vec<RoomVersionId>,
 	pub(crate) bad_event_ratelimiter: Arc<RwLock<HashMap<OwnedEventId, RateLimitState>>>,
@@ -118,6 +120,8 @@ impl Service<'_> {
 
 		let resolver = Arc::new(resolver::Resolver::new(config));
 
+		let mailer = config.smtp.as_ref().map(mailer::Mailer::new).transpose()?;
+
 		// Supported and stable room versions
 		let stable_room_versions = vec![
 			RoomVersionId::V6,
@@ -149,9 +153,10 @@ impl Service<'_> {
 			db,
 			config: config.clone(),
 			cidr_range_denylist,
-			keypair: Arc::new(keypair),
+			keypair: std::sync::RwLock::new(Arc::new(keypair)),
 			resolver: resolver.clone(),
 			client: client::Client::new(config, &resolver),
+			mailer,
 			jwt_decoding_key,
 			stable_room_versions,
 			unstable_room_versions,
@@ -184,7 +189,55 @@ impl Service<'_> {
 	}
 
 	/// Returns this server's keypair.
-	pub(crate) fn keypair(&self) -> &ruma::signatures::Ed25519KeyPair { &self.keypair }
+	pub(crate) fn keypair(&self) -> Arc<ruma::signatures::Ed25519KeyPair> {
+		self.keypair
+			.read()
+			.expect("keypair lock is not poisoned")
+			.clone()
+	}
+
+	/// Generates a new signing key and starts using it. The previous key is
+	/// kept as an old verify key so signatures made with it can still be
+	/// verified by other servers. Returns the ID of the new key.
+	pub(crate) fn rotate_keypair(&self) -> Result<OwnedServerSigningKeyId> {
+		let old = self.keypair();
+		self.db.add_old_verify_key(
+			key_id(old.version()),
+			OldVerifyKey::new(MilliSecondsSinceUnixEpoch::now(), Base64::new(old.public_key().to_vec())),
+		)?;
+
+		self.db.replace_keypair(&utils::generate_keypair())?;
+		let new = Arc::new(self.db.load_keypair()?);
+		let new_key_id = key_id(new.version());
+		*self.keypair.write().expect("keypair lock is not poisoned") = new;
+
+		info!("Rotated signing key {} to {new_key_id}", key_id(old.version()));
+		Ok(new_key_id)
+	}
+
+	/// Our own keys which are no longer used for signing.
+	pub(crate) fn old_verify_keys(&self) -> Result<BTreeMap<OwnedServerSigningKeyId, OldVerifyKey>> {
+		self.db.old_verify_keys()
+	}
+
+	/// This server's key response, valid for a week and not yet signed.
+	pub(crate) fn own_server_keys(&self) -> Result<ServerSigningKeys> {
+		let keypair = self.keypair();
+		let mut keys = ServerSigningKeys::new(
+			self.server_name().to_owned(),
+			MilliSecondsSinceUnixEpoch::from_system_time(SystemTime::now() + Duration::from_secs(86400 * 7))
+				.expect("time is valid"),
+		);
+		keys.verify_keys.insert(
+			key_id(keypair.version()),
+			VerifyKey {
+				key: Base64::new(keypair.public_key().to_vec()),
+			},
+		);
+		keys.old_verify_keys = self.old_verify_keys()?;
+
+		Ok(keys)
+	}
 
 	#[tracing::instrument(skip(self))]
 	pub(crate) fn next_count(&self) -> Result<u64> { self.db.next_count() }
@@ -349,19 +402,31 @@ impl Service<'_> {
 	pub(crate) fn signing_keys_for(&self, origin: &ServerName) -> Result<BTreeMap<OwnedServerSigningKeyId, VerifyKey>> {
 		let mut keys = self.db.signing_keys_for(origin)?;
 		if origin == self.server_name() {
-			keys.insert(
-				format!("ed25519:{}", services().globals.keypair().version())
-					.try_into()
-					.expect("found invalid server signing keys in DB"),
-				VerifyKey {
-					key: Base64::new(self.keypair.public_key().to_vec()),
-				},
+			let own_keys = self.own_server_keys()?;
+			keys.extend(own_keys.verify_keys);
+			keys.extend(
+				own_keys
+					.old_verify_keys
+					.into_iter()
+					.map(|(key_id, old)| (key_id, VerifyKey::new(old.key))),
 			);
 		}
 
 		Ok(keys)
 	}
 
+	/// Keeps the key response of the server as received, so it can be served
+	/// to other servers with the origin's signatures intact.
+	pub(crate) fn add_signed_server_keys(&self, origin: &ServerName, keys: &Raw<ServerSigningKeys>) -> Result<()> {
+		self.db.add_signed_server_keys(origin, keys)
+	}
+
+	/// The latest key response we received from the server, still carrying the
+	/// origin's signatures.
+	pub(crate) fn signed_server_keys(&self, origin: &ServerName) -> Result<Option<Raw<ServerSigningKeys>>> {
+		self.db.signed_server_keys(origin)
+	}
+
 	pub(crate) fn database_version(&self) -> Result<u64> { self.db.database_version() }
 
 	pub(crate) fn bump_database_version(&self, new_version: u64) -> Result<()> {
@@ -375,6 +440,13 @@ impl Service<'_> {
 		r
 	}
 
+	pub(crate) fn get_export_folder(&self) -> PathBuf {
+		let mut r = PathBuf::new();
+		r.push(self.config.database_path.clone());
+		r.push("exports");
+		r
+	}
+
 	/// new SHA256 file name media function, requires "sha256_media" feature
 	/// flag enabled and database migrated uses SHA256 hash of the base64 key as
 	/// the file name
@@ -437,3 +509,9 @@ impl Service<'_> {
 		services().globals.rotate.fire();
 	}
 }
+
+fn key_id(version: &str) -> OwnedServerSigningKeyId {
+	format!("ed25519:{version}")
+		.try_into()
+		.expect("found invalid server signing keys in DB")
+}
@@ -1,3 +1,5 @@
+use ruma::UserId;
+
 use crate::Result;
 
 pub(crate) trait Data: Send + Sync {
@@ -17,6 +19,9 @@ pub(crate) trait Data: Send + Sync {
 
 	fn get_all_media_keys(&self) -> Result<Vec<Vec<u8>>>;
 
+	/// Returns the MXC URIs of all media uploaded by the user.
+	fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<String>;
+
 	// TODO: use this
 	#[allow(dead_code)]
 	fn remove_url_preview(&self, url: &str) -> Result<()>;
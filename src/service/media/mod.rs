@@ -3,7 +3,7 @@ use std::{collections::HashMap, io::Cursor, sync::Arc, time::SystemTime};
 
 pub(crate) use data::Data;
 use image::imageops::FilterType;
-use ruma::{OwnedMxcUri, OwnedUserId};
+use ruma::{OwnedMxcUri, OwnedUserId, UserId};
 use serde::Serialize;
 use tokio::{
 	fs::{self, File},
@@ -113,6 +113,9 @@ impl Service {
 		}
 	}
 
+	/// Returns the MXC URIs of all media uploaded by the user.
+	pub(crate) fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<String> { self.db.get_all_user_mxcs(user_id) }
+
 	/// Uploads or replaces a file thumbnail.
 	#[allow(clippy::too_many_arguments)]
 	pub(crate) async fn upload_thumbnail(
@@ -521,6 +524,8 @@ mod tests {
 
 			fn get_all_media_keys(&self) -> Result<Vec<Vec<u8>>> { todo!() }
 
+			fn get_all_user_mxcs(&self, _user_id: &UserId) -> Vec<String> { todo!() }
+
 			fn search_file_metadata(
 				&self, _mxc: String, _width: u32, _height: u32,
 			) -> Result<(Option<String>, Option<String>, Vec<u8>)> {
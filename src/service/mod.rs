@@ -11,22 +11,28 @@ use crate::{Config, LogLevelReloadHandles, Result};
 pub(crate) mod account_data;
 pub(crate) mod admin;
 pub(crate) mod appservice;
+pub(crate) mod event_policy;
+pub(crate) mod federation_queue;
 pub(crate) mod globals;
 pub(crate) mod key_backups;
 pub(crate) mod media;
 pub(crate) mod pdu;
 pub(crate) mod presence;
 pub(crate) mod pusher;
+pub(crate) mod reports;
 pub(crate) mod rooms;
 pub(crate) mod sending;
+pub(crate) mod threepid;
 pub(crate) mod transaction_ids;
 pub(crate) mod uiaa;
+pub(crate) mod user_directory;
 pub(crate) mod users;
 
 pub(crate) struct Services<'a> {
 	pub(crate) appservice: appservice::Service,
 	pub(crate) pusher: pusher::Service,
 	pub(crate) rooms: rooms::Service,
+	pub(crate) threepid: threepid::Service,
 	pub(crate) transaction_ids: transaction_ids::Service,
 	pub(crate) uiaa: uiaa::Service,
 	pub(crate) users: users::Service,
@@ -37,6 +43,10 @@ pub(crate) struct Services<'a> {
 	pub(crate) key_backups: key_backups::Service,
 	pub(crate) media: media::Service,
 	pub(crate) sending: Arc<sending::Service>,
+	pub(crate) federation_queue: Arc<federation_queue::Service>,
+	pub(crate) event_policy: event_policy::Service,
+	pub(crate) reports: reports::Service,
+	pub(crate) user_directory: user_directory::Service,
 }
 
 impl Services<'_> {
@@ -44,6 +54,7 @@ impl Services<'_> {
 		D: appservice::Data
 			+ pusher::Data
 			+ rooms::Data
+			+ threepid::Data
 			+ transaction_ids::Data
 			+ uiaa::Data
 			+ users::Data
@@ -53,6 +64,9 @@ impl Services<'_> {
 			+ key_backups::Data
 			+ media::Data
 			+ sending::Data
+			+ federation_queue::Data
+			+ reports::Data
+			+ user_directory::Data
 			+ 'static,
 	>(
 		db: &'static D, config: &Config, tracing_reload_handle: LogLevelReloadHandles,
@@ -61,6 +75,7 @@ impl Services<'_> {
 			appservice: appservice::Service::build(db)?,
 			pusher: pusher::Service {
 				db,
+				gateway_backoff: pusher::GatewayBackoff::default(),
 			},
 			rooms: rooms::Service {
 				alias: rooms::alias::Service {
@@ -86,6 +101,9 @@ impl Services<'_> {
 				pdu_metadata: rooms::pdu_metadata::Service {
 					db,
 				},
+				policy: rooms::policy::Service {
+					rules: std::sync::RwLock::new(None),
+				},
 				read_receipt: rooms::read_receipt::Service {
 					db,
 				},
@@ -140,6 +158,9 @@ impl Services<'_> {
 					db,
 				},
 			},
+			threepid: threepid::Service {
+				db,
+			},
 			transaction_ids: transaction_ids::Service {
 				db,
 			},
@@ -163,6 +184,14 @@ impl Services<'_> {
 				url_preview_mutex: RwLock::new(HashMap::new()),
 			},
 			sending: sending::Service::build(db, config),
+			federation_queue: federation_queue::Service::build(db, config),
+			event_policy: event_policy::Service::build(config),
+			reports: reports::Service {
+				db,
+			},
+			user_directory: user_directory::Service {
+				db,
+			},
 
 			globals: globals::Service::load(db, config, tracing_reload_handle)?,
 		})
@@ -19,7 +19,7 @@ use serde_json::{
 };
 use tracing::warn;
 
-use crate::{services, Error};
+use crate::{services, utils::user_id::user_is_local, Error};
 
 /// Content hashes of a PDU.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -76,6 +76,14 @@ impl PduEvent {
 		Ok(())
 	}
 
+	/// Whether the event has been redacted already.
+	pub(crate) fn is_redacted(&self) -> bool {
+		self.unsigned
+			.as_ref()
+			.and_then(|unsigned| serde_json::from_str::<BTreeMap<String, Box<RawJsonValue>>>(unsigned.get()).ok())
+			.is_some_and(|unsigned| unsigned.contains_key("redacted_because"))
+	}
+
 	pub(crate) fn remove_transaction_id(&mut self) -> crate::Result<()> {
 		if let Some(unsigned) = &self.unsigned {
 			let mut unsigned: BTreeMap<String, Box<RawJsonValue>> = serde_json::from_str(unsigned.get())
@@ -328,25 +336,31 @@ impl PduEvent {
 			unsigned.remove("transaction_id");
 		}
 
-		if let Some(room_id) = pdu_json
+		let room_version_id = pdu_json
 			.get("room_id")
 			.and_then(|val| RoomId::parse(val.as_str()?).ok())
-		{
-			if let Ok(room_version_id) = services().rooms.state.get_room_version(&room_id) {
-				// room v3 and above removed the "event_id" field from remote PDU format
-				match room_version_id {
-					RoomVersionId::V1 | RoomVersionId::V2 => {},
-					_ => {
-						pdu_json.remove("event_id");
-					},
-				};
-			} else {
-				pdu_json.remove("event_id");
-			}
-		} else {
+			.and_then(|room_id| services().rooms.state.get_room_version(&room_id).ok());
+
+		// room v3 and above removed the "event_id" field from remote PDU format
+		if !matches!(room_version_id, Some(RoomVersionId::V1 | RoomVersionId::V2)) {
 			pdu_json.remove("event_id");
 		}
 
+		// events of erased users are only handed out in their redacted form
+		if let Some(room_version_id) = &room_version_id {
+			let erased = pdu_json
+				.get("sender")
+				.and_then(|val| UserId::parse(val.as_str()?).ok())
+				.is_some_and(|sender| user_is_local(&sender) && services().users.is_erased(&sender).unwrap_or(false));
+
+			if erased {
+				match ruma::canonical_json::redact(pdu_json.clone(), room_version_id, None) {
+					Ok(redacted) => pdu_json = redacted,
+					Err(e) => warn!("Failed to redact event of erased user: {e}"),
+				}
+			}
+		}
+
 		// TODO: another option would be to convert it to a canonical string to validate
 		// size and return a Result<Raw<...>>
 		// serde_json::from_str::<Raw<_>>(
@@ -1,19 +1,23 @@
 mod data;
 
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 pub(crate) use data::Data;
 use futures_util::{stream::FuturesUnordered, StreamExt};
 use ruma::{
-	events::presence::{PresenceEvent, PresenceEventContent},
+	events::{
+		presence::{PresenceEvent, PresenceEventContent},
+		RoomAccountDataEventType,
+	},
 	presence::PresenceState,
-	OwnedUserId, UInt, UserId,
+	OwnedRoomId, OwnedUserId, RoomId, ServerName, UInt, UserId,
 };
 use serde::{Deserialize, Serialize};
 use tokio::{sync::Mutex, time::sleep};
 use tracing::{debug, error};
 
 use crate::{
+	service::{appservice::RegistrationInfo, sending::AppserviceEdu},
 	services,
 	utils::{self, user_id::user_is_local},
 	Config, Error, Result,
@@ -77,6 +81,36 @@ impl Presence {
 	}
 }
 
+/// Account data event type users keep their presence settings in
+pub(crate) const PRESENCE_SETTINGS_EVENT_TYPE: &str = "org.conduwuit.presence";
+
+/// Who a user shares their presence with
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PresenceVisibility {
+	#[default]
+	Everyone,
+	/// Only the users the user has direct chats with
+	Contacts,
+	Nobody,
+}
+
+/// The content of a user's `org.conduwuit.presence` account data event
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct PresenceSettings {
+	#[serde(default)]
+	pub(crate) visibility: PresenceVisibility,
+	/// Users whose presence the user wants in sync even if they only share
+	/// large rooms
+	#[serde(default)]
+	pub(crate) subscriptions: HashSet<OwnedUserId>,
+}
+
+#[derive(Deserialize)]
+struct PresenceSettingsEvent {
+	content: PresenceSettings,
+}
+
 pub(crate) struct Service {
 	pub(crate) db: &'static dyn Data,
 	pub(crate) timer_sender: loole::Sender<(OwnedUserId, Duration)>,
@@ -158,6 +192,17 @@ impl Service {
 		self.db
 			.set_presence(user_id, presence_state, currently_active, last_active_ago, status_msg)?;
 
+		// The user's visibility setting is checked against each appservice before
+		// the EDU is queued for it
+		if let Some(event) = self.get_presence(user_id)? {
+			services()
+				.sending
+				.send_edu_appservices(&AppserviceEdu::Presence {
+					user_id: user_id.to_owned(),
+					event: serde_json::to_value(event).expect("presence event can be serialized"),
+				})?;
+		}
+
 		if self.timeout_remote_users || user_is_local(user_id) {
 			let timeout = match presence_state {
 				PresenceState::Online => services().globals.config.presence_idle_timeout_s,
@@ -175,6 +220,113 @@ impl Service {
 		Ok(())
 	}
 
+	/// The presence settings of a local user
+	pub(crate) fn settings(&self, user_id: &UserId) -> Result<PresenceSettings> {
+		Ok(services()
+			.account_data
+			.get(None, user_id, RoomAccountDataEventType::from(PRESENCE_SETTINGS_EVENT_TYPE))?
+			.and_then(|event| serde_json::from_str::<PresenceSettingsEvent>(event.get()).ok())
+			.map(|event| event.content)
+			.unwrap_or_default())
+	}
+
+	/// Whether the user's presence may be shared with `observer` according to
+	/// the user's visibility setting. Remote users' servers decide this for
+	/// them.
+	pub(crate) fn visible_to(&self, user_id: &UserId, observer: &UserId) -> Result<bool> {
+		if user_id == observer || !user_is_local(user_id) {
+			return Ok(true);
+		}
+
+		Ok(match self.settings(user_id)?.visibility {
+			PresenceVisibility::Everyone => true,
+			PresenceVisibility::Contacts => services()
+				.account_data
+				.direct_chats(user_id)?
+				.contains_key(observer),
+			PresenceVisibility::Nobody => false,
+		})
+	}
+
+	/// Whether the user's presence may be shared with the appservice according
+	/// to the user's visibility setting. With `contacts` the appservice has to
+	/// own one of the user's direct chat partners.
+	pub(crate) fn visible_to_appservice(&self, user_id: &UserId, info: &RegistrationInfo) -> Result<bool> {
+		if !user_is_local(user_id) || info.is_user_match(user_id) {
+			return Ok(true);
+		}
+
+		Ok(match self.settings(user_id)?.visibility {
+			PresenceVisibility::Everyone => true,
+			PresenceVisibility::Contacts => services()
+				.account_data
+				.direct_chats(user_id)?
+				.keys()
+				.any(|contact| info.is_user_match(contact)),
+			PresenceVisibility::Nobody => false,
+		})
+	}
+
+	/// Whether the syncing user gets the presence of the user: they have to
+	/// share a direct chat or a room with at most `presence_max_room_members`
+	/// members, or the syncing user has to have subscribed to the user.
+	pub(crate) fn wanted_by(
+		&self, user_id: &UserId, observer: &UserId, observer_settings: &PresenceSettings,
+	) -> Result<bool> {
+		if user_id == observer {
+			return Ok(true);
+		}
+
+		if !self.visible_to(user_id, observer)? {
+			return Ok(false);
+		}
+
+		let state_cache = &services().rooms.state_cache;
+		if observer_settings.subscriptions.contains(user_id) {
+			return state_cache.user_sees_user(observer, user_id);
+		}
+
+		let direct_rooms = services().account_data.direct_room_ids(observer)?;
+		for room_id in state_cache.rooms_joined(observer).filter_map(Result::ok) {
+			if state_cache.is_joined(user_id, &room_id)? && is_presence_room(&room_id, &direct_rooms)? {
+				return Ok(true);
+			}
+		}
+
+		Ok(false)
+	}
+
+	/// Whether the local user's presence is sent to the server: they have to
+	/// share a direct chat or a room with at most `presence_max_room_members`
+	/// members, and the user's visibility setting has to allow it.
+	pub(crate) fn server_gets_presence(&self, server: &ServerName, user_id: &UserId) -> Result<bool> {
+		let state_cache = &services().rooms.state_cache;
+
+		match self.settings(user_id)?.visibility {
+			PresenceVisibility::Nobody => Ok(false),
+			PresenceVisibility::Contacts => Ok(services()
+				.account_data
+				.direct_chats(user_id)?
+				.keys()
+				.any(|contact| {
+					contact.server_name() == server
+						&& state_cache
+							.user_sees_user(user_id, contact)
+							.unwrap_or(false)
+				})),
+			PresenceVisibility::Everyone => {
+				let direct_rooms = services().account_data.direct_room_ids(user_id)?;
+				for room_id in state_cache.server_rooms(server).filter_map(Result::ok) {
+					if state_cache.is_joined(user_id, &room_id)? && is_presence_room(&room_id, &direct_rooms)? {
+						return Ok(true);
+					}
+				}
+
+				Ok(false)
+			},
+		}
+	}
+
 	/// Removes the presence record for the given user from the database.
 	///
 	/// TODO: Why is this not used?
@@ -214,6 +366,20 @@ impl Service {
 	}
 }
 
+/// Whether presence is shared through the room, which is the case for direct
+/// chats and rooms with at most `presence_max_room_members` members
+fn is_presence_room(room_id: &RoomId, direct_rooms: &HashSet<OwnedRoomId>) -> Result<bool> {
+	if direct_rooms.contains(room_id) {
+		return Ok(true);
+	}
+
+	Ok(services()
+		.rooms
+		.state_cache
+		.room_joined_count(room_id)?
+		.is_some_and(|count| count <= services().globals.config.presence_max_room_members))
+}
+
 async fn presence_timer(user_id: OwnedUserId, timeout: Duration) -> OwnedUserId {
 	sleep(timeout).await;
 
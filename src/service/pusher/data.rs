@@ -1,8 +1,9 @@
 use ruma::{
 	api::client::push::{set_pusher, Pusher},
-	UserId,
+	OwnedUserId, UserId,
 };
 
+use super::{EmailDigest, LoggedNotification};
 use crate::Result;
 
 pub(crate) trait Data: Send + Sync {
@@ -13,4 +14,26 @@ pub(crate) trait Data: Send + Sync {
 	fn get_pushers(&self, sender: &UserId) -> Result<Vec<Pusher>>;
 
 	fn get_pushkeys<'a>(&'a self, sender: &UserId) -> Box<dyn Iterator<Item = Result<String>> + 'a>;
+
+	/// Adds the notification to the user's log, `count` is the PDU count of
+	/// the event
+	fn log_notification(&self, user_id: &UserId, count: u64, notification: &LoggedNotification) -> Result<()>;
+
+	/// The user's notifications with a count below `until`, newest first
+	fn notifications_until<'a>(
+		&'a self, user_id: &UserId, until: u64,
+	) -> Box<dyn Iterator<Item = Result<(u64, LoggedNotification)>> + 'a>;
+
+	/// Removes all notifications logged before `ts`, returns how many were
+	/// removed
+	fn prune_notifications(&self, ts: u64) -> Result<usize>;
+
+	fn email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<Option<EmailDigest>>;
+
+	fn set_email_digest(&self, user_id: &UserId, pushkey: &str, digest: &EmailDigest) -> Result<()>;
+
+	fn remove_email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<()>;
+
+	/// The digest state of all email pushers with their user and pushkey
+	fn email_digests<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(OwnedUserId, String, EmailDigest)>> + 'a>;
 }
@@ -0,0 +1,328 @@
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	fmt::Write,
+	time::Duration,
+};
+
+use ruma::{api::client::push::PusherKind, events::TimelineEventType, ServerName, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use super::Service;
+use crate::{services, utils, Result};
+
+/// How often we look for email digests that are due
+const EMAIL_DIGEST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Progress of the email notifications of an email pusher
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct EmailDigest {
+	/// When the pending digest is due to be sent, if there is one
+	pub(crate) due_at: Option<u64>,
+	/// PDU count of the newest notification a digest was considered for
+	pub(crate) last_count: u64,
+}
+
+/// A message listed in a digest email
+pub(crate) struct DigestEntry {
+	pub(crate) room: String,
+	pub(crate) sender: String,
+	pub(crate) text: String,
+}
+
+impl Service {
+	/// Periodically sends the email digests that are due
+	pub(crate) fn start_email_handler(&self) {
+		tokio::spawn(async {
+			let mut interval = tokio::time::interval(EMAIL_DIGEST_INTERVAL);
+			loop {
+				interval.tick().await;
+				if let Err(e) = services().pusher.send_due_email_digests().await {
+					error!("Failed to send email digests: {e}");
+				}
+			}
+		});
+	}
+
+	/// Starts the digest state of a new email pusher, so that it only covers
+	/// notifications from now on
+	pub(crate) fn init_email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<()> {
+		if self.db.email_digest(user_id, pushkey)?.is_none() {
+			self.db.set_email_digest(
+				user_id,
+				pushkey,
+				&EmailDigest {
+					due_at: None,
+					last_count: services().globals.current_count()?,
+				},
+			)?;
+		}
+
+		Ok(())
+	}
+
+	/// Schedules a digest for the email pusher after `email_notification_delay`
+	/// unless one is already pending
+	pub(crate) fn schedule_email_digest(&self, user_id: &UserId, pushkey: &str) -> Result<()> {
+		let mut digest = self.db.email_digest(user_id, pushkey)?.unwrap_or_default();
+
+		if digest.due_at.is_none() {
+			let delay = services().globals.config.email_notification_delay;
+			digest.due_at = Some(utils::millis_since_unix_epoch().saturating_add(delay.saturating_mul(1000)));
+			self.db.set_email_digest(user_id, pushkey, &digest)?;
+		}
+
+		Ok(())
+	}
+
+	async fn send_due_email_digests(&self) -> Result<()> {
+		let now = utils::millis_since_unix_epoch();
+		let due: Vec<_> = self
+			.db
+			.email_digests()
+			.filter_map(Result::ok)
+			.filter(|(_, _, digest)| digest.due_at.is_some_and(|due_at| due_at <= now))
+			.collect();
+
+		for (user_id, pushkey, mut digest) in due {
+			if !self
+				.get_pusher(&user_id, &pushkey)?
+				.is_some_and(|pusher| matches!(pusher.kind, PusherKind::Email(_)))
+			{
+				self.db.remove_email_digest(&user_id, &pushkey)?;
+				continue;
+			}
+
+			if let Err(e) = self
+				.send_email_digest(&user_id, &pushkey, &mut digest)
+				.await
+			{
+				warn!("Failed to send email digest to {pushkey} for {user_id}: {e}");
+
+				// Try again later instead of on every tick
+				let delay = services().globals.config.email_notification_delay;
+				digest.due_at = Some(now.saturating_add(delay.saturating_mul(1000)));
+			}
+
+			self.db.set_email_digest(&user_id, &pushkey, &digest)?;
+		}
+
+		Ok(())
+	}
+
+	/// Emails the user's unread highlights and direct messages that are newer
+	/// than the last digest
+	async fn send_email_digest(&self, user_id: &UserId, pushkey: &str, digest: &mut EmailDigest) -> Result<()> {
+		let Some(mailer) = services().globals.mailer.as_ref() else {
+			digest.due_at = None;
+			return Ok(());
+		};
+
+		let direct_rooms = services().account_data.direct_room_ids(user_id)?;
+		let mut last_read = HashMap::new();
+		let mut newest = digest.last_count;
+		let mut entries = Vec::new();
+
+		for (count, notification) in self
+			.notifications(user_id, None)
+			.filter_map(Result::ok)
+			.take_while(|(count, _)| *count > digest.last_count)
+		{
+			newest = newest.max(count);
+
+			if !notification.highlight && !direct_rooms.contains(&notification.room_id) {
+				continue;
+			}
+
+			let room_last_read = match last_read.entry(notification.room_id.clone()) {
+				Entry::Occupied(entry) => *entry.get(),
+				Entry::Vacant(entry) => *entry.insert(
+					services()
+						.rooms
+						.read_receipt
+						.last_read_count(&notification.room_id, user_id)?,
+				),
+			};
+			if count <= room_last_read {
+				continue;
+			}
+
+			let Some(pdu) = services().rooms.timeline.get_pdu(&notification.event_id)? else {
+				continue;
+			};
+
+			let text = match pdu.kind {
+				TimelineEventType::RoomEncrypted => "sent an encrypted message".to_owned(),
+				_ => serde_json::from_str::<serde_json::Value>(pdu.content.get())
+					.ok()
+					.and_then(|content| content.get("body")?.as_str().map(ToOwned::to_owned))
+					.unwrap_or_else(|| format!("sent an event of type {}", pdu.kind)),
+			};
+
+			entries.push(DigestEntry {
+				room: services()
+					.rooms
+					.state_accessor
+					.get_name(&pdu.room_id)?
+					.unwrap_or_else(|| pdu.room_id.to_string()),
+				sender: services()
+					.users
+					.displayname(&pdu.sender)?
+					.unwrap_or_else(|| pdu.sender.to_string()),
+				text,
+			});
+		}
+
+		if !entries.is_empty() {
+			// The notification log is newest first
+			entries.reverse();
+
+			let (subject, body) = render_digest(services().globals.server_name(), &entries);
+			mailer.send(pushkey, &subject, body).await?;
+			debug!("Sent email digest of {} messages to {pushkey} for {user_id}", entries.len());
+		}
+
+		digest.last_count = newest;
+		digest.due_at = None;
+
+		Ok(())
+	}
+}
+
+/// The subject and plain text body of a digest email
+pub(crate) fn render_digest(server_name: &ServerName, entries: &[DigestEntry]) -> (String, String) {
+	let subject = match entries.len() {
+		1 => format!("1 unread message on {server_name}"),
+		n => format!("{n} unread messages on {server_name}"),
+	};
+
+	let mut body = format!("You have unread messages on {server_name}:\n\n");
+	for entry in entries {
+		let _ = writeln!(body, "[{}] {}: {}", entry.room, entry.sender, entry.text);
+	}
+	let _ = write!(
+		body,
+		"\nYou are receiving this email because email notifications are enabled for your account on {server_name}. \
+		 Remove the email pusher in your client to stop them.\n"
+	);
+
+	(subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{BufRead, BufReader, Write},
+		net::TcpListener,
+		sync::mpsc,
+		thread,
+		time::Duration,
+	};
+
+	use ruma::server_name;
+
+	use super::{render_digest, DigestEntry};
+	use crate::{
+		config::{SmtpConfig, SmtpSecurity},
+		service::globals::mailer::Mailer,
+	};
+
+	/// A plaintext SMTP server accepting a single connection, which hands out
+	/// the data of every message it receives
+	fn smtp_sink() -> (u16, mpsc::Receiver<String>) {
+		let listener = TcpListener::bind("127.0.0.1:0").expect("can bind to a local port");
+		let port = listener
+			.local_addr()
+			.expect("listener has an address")
+			.port();
+		let (sender, receiver) = mpsc::channel();
+
+		thread::spawn(move || {
+			let (stream, _) = listener.accept().expect("sink gets a connection");
+			let mut reader = BufReader::new(stream.try_clone().expect("stream can be cloned"));
+			let mut writer = stream;
+			let mut reply = |line: &str| {
+				writer
+					.write_all(format!("{line}\r\n").as_bytes())
+					.expect("reply can be written");
+			};
+
+			reply("220 localhost ESMTP sink");
+			let mut line = String::new();
+			loop {
+				line.clear();
+				if reader.read_line(&mut line).unwrap_or(0) == 0 {
+					break;
+				}
+
+				let command = line.trim_end().to_ascii_uppercase();
+				if command.starts_with("EHLO") || command.starts_with("HELO") {
+					reply("250 localhost");
+				} else if command == "DATA" {
+					reply("354 End data with <CR><LF>.<CR><LF>");
+					let mut data = String::new();
+					loop {
+						line.clear();
+						if reader.read_line(&mut line).unwrap_or(0) == 0 || line == ".\r\n" {
+							break;
+						}
+						data.push_str(&line);
+					}
+					reply("250 OK");
+					_ = sender.send(data);
+				} else if command == "QUIT" {
+					reply("221 Bye");
+					break;
+				} else {
+					reply("250 OK");
+				}
+			}
+		});
+
+		(port, receiver)
+	}
+
+	#[tokio::test]
+	async fn digest_is_sent_over_smtp() {
+		let (port, received) = smtp_sink();
+		let mailer = Mailer::new(&SmtpConfig {
+			from: "conduwuit <noreply@example.com>".to_owned(),
+			host: Some("127.0.0.1".to_owned()),
+			port: Some(port),
+			security: SmtpSecurity::None,
+			username: None,
+			password: None,
+			file_sink_path: None,
+		})
+		.expect("mailer can be built");
+
+		let (subject, body) = render_digest(
+			server_name!("example.com"),
+			&[
+				DigestEntry {
+					room: "Lobby".to_owned(),
+					sender: "Alice".to_owned(),
+					text: "are you there?".to_owned(),
+				},
+				DigestEntry {
+					room: "!direct:example.com".to_owned(),
+					sender: "@bob:example.com".to_owned(),
+					text: "sent an encrypted message".to_owned(),
+				},
+			],
+		);
+		assert_eq!(subject, "2 unread messages on example.com");
+
+		mailer
+			.send("user@example.com", &subject, body)
+			.await
+			.expect("sink accepts the email");
+
+		let message = received
+			.recv_timeout(Duration::from_secs(5))
+			.expect("sink received the email");
+		assert!(message.contains("Subject: 2 unread messages on example.com"));
+		assert!(message.contains("[Lobby] Alice: are you there?"));
+		assert!(message.contains("[!direct:example.com] @bob:example.com: sent an encrypted message"));
+	}
+}
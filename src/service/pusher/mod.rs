@@ -1,8 +1,10 @@
 mod data;
-use std::{fmt::Debug, mem};
+mod email;
+use std::{collections::HashMap, fmt::Debug, mem, sync::Mutex as StdMutex, time::Instant};
 
 use bytes::BytesMut;
 pub(crate) use data::Data;
+pub(crate) use email::EmailDigest;
 use ipaddress::IPAddress;
 use ruma::{
 	api::{
@@ -18,18 +20,48 @@ use ruma::{
 	},
 	push::{Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, PushFormat, Ruleset, Tweak},
 	serde::Raw,
-	uint, RoomId, UInt, UserId,
+	uint, OwnedEventId, OwnedRoomId, RoomId, UInt, UserId,
 };
-use tracing::{info, trace, warn};
-
-use crate::{debug_info, services, Error, PduEvent, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, trace, warn};
+
+use crate::{debug_info, service::sending::backoff_duration, services, utils, Error, PduEvent, Result};
+
+/// An entry of a user's notification log, which backs `GET /notifications`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct LoggedNotification {
+	pub(crate) room_id: OwnedRoomId,
+	pub(crate) event_id: OwnedEventId,
+	pub(crate) actions: Vec<Action>,
+	pub(crate) highlight: bool,
+	/// When the notification was logged
+	pub(crate) ts: u64,
+}
 
 pub(crate) struct Service {
 	pub(crate) db: &'static dyn Data,
+
+	pub(crate) gateway_backoff: GatewayBackoff,
+}
+
+/// Consecutive failures and the time of the last one of push gateways we are
+/// backing off from, by URL
+#[derive(Default)]
+pub(crate) struct GatewayBackoff {
+	failures: StdMutex<HashMap<String, (u32, Instant)>>,
 }
 
 impl Service {
 	pub(crate) fn set_pusher(&self, sender: &UserId, pusher: set_pusher::v3::PusherAction) -> Result<()> {
+		if let set_pusher::v3::PusherAction::Post(data) = &pusher {
+			if matches!(data.pusher.kind, PusherKind::Email(_)) {
+				self.init_email_digest(sender, &data.pusher.ids.pushkey)?;
+			} else {
+				self.db
+					.remove_email_digest(sender, &data.pusher.ids.pushkey)?;
+			}
+		}
+
 		self.db.set_pusher(sender, pusher)
 	}
 
@@ -43,6 +75,48 @@ impl Service {
 		self.db.get_pushkeys(sender)
 	}
 
+	/// Logs the notification the event caused for the user, `count` is the PDU
+	/// count of the event
+	pub(crate) fn log_notification(
+		&self, user_id: &UserId, pdu: &PduEvent, count: u64, actions: &[Action], highlight: bool,
+	) -> Result<()> {
+		self.db.log_notification(
+			user_id,
+			count,
+			&LoggedNotification {
+				room_id: pdu.room_id.clone(),
+				event_id: (*pdu.event_id).to_owned(),
+				actions: actions.to_vec(),
+				highlight,
+				ts: utils::millis_since_unix_epoch(),
+			},
+		)
+	}
+
+	/// The user's notifications before the event with the count `until`, or
+	/// all of them, newest first
+	pub(crate) fn notifications<'a>(
+		&'a self, user_id: &UserId, until: Option<u64>,
+	) -> impl Iterator<Item = Result<(u64, LoggedNotification)>> + 'a {
+		self.db
+			.notifications_until(user_id, until.unwrap_or(u64::MAX))
+	}
+
+	/// Removes notifications older than `notification_log_ttl`
+	pub(crate) fn prune_notifications(&self) -> Result<()> {
+		let ttl = services().globals.config.notification_log_ttl;
+		let cutoff = utils::millis_since_unix_epoch().saturating_sub(ttl.saturating_mul(1000));
+
+		let pruned = self.db.prune_notifications(cutoff)?;
+		if pruned > 0 {
+			debug!("Pruned {pruned} notifications from the notification log");
+		}
+
+		Ok(())
+	}
+
+	/// Sends the request to the push gateway unless we are backing off from it
+	/// after repeated failures
 	#[tracing::instrument(skip(self, dest, request))]
 	pub(crate) async fn send_request<T>(&self, dest: &str, request: T) -> Result<T::IncomingResponse>
 	where
@@ -52,88 +126,17 @@ impl Service {
 
 		trace!("Push gateway destination: {dest}");
 
-		const VERSIONS: [MatrixVersion; 1] = [MatrixVersion::V1_0];
-
-		let http_request = request
-			.try_into_http_request::<BytesMut>(&dest, SendAccessToken::IfRequired(""), &VERSIONS)
-			.map_err(|e| {
-				warn!("Failed to find destination {dest} for push gateway: {e}");
-				Error::BadServerResponse("Invalid push gateway destination")
-			})?
-			.map(BytesMut::freeze);
-
-		let reqwest_request = reqwest::Request::try_from(http_request)?;
-
-		if let Some(url_host) = reqwest_request.url().host_str() {
-			trace!("Checking request URL for IP");
-			if let Ok(ip) = IPAddress::parse(url_host) {
-				if !services().globals.valid_cidr_range(&ip) {
-					return Err(Error::BadServerResponse("Not allowed to send requests to this IP"));
-				}
-			}
-		}
-
-		let response = services()
-			.globals
-			.client
-			.pusher
-			.execute(reqwest_request)
-			.await;
-
-		match response {
-			Ok(mut response) => {
-				// reqwest::Response -> http::Response conversion
-
-				trace!("Checking response destination's IP");
-				if let Some(remote_addr) = response.remote_addr() {
-					if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
-						if !services().globals.valid_cidr_range(&ip) {
-							return Err(Error::BadServerResponse("Not allowed to send requests to this IP"));
-						}
-					}
-				}
-
-				let status = response.status();
-				let mut http_response_builder = http::Response::builder()
-					.status(status)
-					.version(response.version());
-				mem::swap(
-					response.headers_mut(),
-					http_response_builder
-						.headers_mut()
-						.expect("http::response::Builder is usable"),
-				);
-
-				let body = response.bytes().await?; // TODO: handle timeout
-
-				if !status.is_success() {
-					info!("Push gateway {dest} returned unsuccessful HTTP response ({status})");
-					debug_info!("Push gateway response body: {:?}", crate::utils::string_from_bytes(&body));
-
-					return Err(Error::BadServerResponse("Push gateway returned unsuccessful response"));
-				}
-
-				let response = T::IncomingResponse::try_from_http_response(
-					http_response_builder
-						.body(body)
-						.expect("reqwest body is valid http body"),
-				);
-				response.map_err(|e| {
-					warn!("Push gateway {dest} returned invalid response bytes: {e}");
-					Error::BadServerResponse("Push gateway returned bad/invalid response")
-				})
-			},
-			Err(e) => {
-				warn!("Could not send request to pusher {dest}: {e}");
-				Err(e.into())
-			},
-		}
+		self.gateway_backoff
+			.send(&services().globals.client.pusher, &dest, request, &|ip| {
+				services().globals.valid_cidr_range(ip)
+			})
+			.await
 	}
 
-	#[tracing::instrument(skip(self, user, unread, pusher, ruleset, pdu))]
-	pub(crate) async fn send_push_notice(
-		&self, user: &UserId, unread: UInt, pusher: &Pusher, ruleset: Ruleset, pdu: &PduEvent,
-	) -> Result<()> {
+	/// The tweaks of the notification the event causes for the user, or None
+	/// if the event doesn't notify
+	#[tracing::instrument(skip(self, user, ruleset, pdu))]
+	pub(crate) fn notice_tweaks(&self, user: &UserId, ruleset: &Ruleset, pdu: &PduEvent) -> Result<Option<Vec<Tweak>>> {
 		let mut notify = None;
 		let mut tweaks = Vec::new();
 
@@ -148,7 +151,7 @@ impl Service {
 			.transpose()?
 			.unwrap_or_default();
 
-		for action in self.get_actions(user, &ruleset, &power_levels, &pdu.to_sync_room_event(), &pdu.room_id)? {
+		for action in self.get_actions(user, ruleset, &power_levels, &pdu.to_sync_room_event(), &pdu.room_id)? {
 			let n = match action {
 				Action::Notify => true,
 				Action::SetTweak(tweak) => {
@@ -167,12 +170,7 @@ impl Service {
 			notify = Some(n);
 		}
 
-		if notify == Some(true) {
-			self.send_notice(unread, pusher, tweaks, pdu).await?;
-		}
-		// Else the event triggered no actions
-
-		Ok(())
+		Ok((notify == Some(true)).then_some(tweaks))
 	}
 
 	#[tracing::instrument(skip(self, user, ruleset, pdu))]
@@ -206,9 +204,12 @@ impl Service {
 		Ok(ruleset.get_actions(pdu, &ctx))
 	}
 
-	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
-	async fn send_notice(&self, unread: UInt, pusher: &Pusher, tweaks: Vec<Tweak>, event: &PduEvent) -> Result<()> {
-		// TODO: email
+	/// Sends the notification for the event to the pusher. Pushers whose
+	/// pushkey the gateway rejects are removed.
+	#[tracing::instrument(skip(self, user, unread, pusher, tweaks, event))]
+	pub(crate) async fn send_notice(
+		&self, user: &UserId, unread: UInt, pusher: &Pusher, tweaks: Vec<Tweak>, event: &PduEvent,
+	) -> Result<()> {
 		match &pusher.kind {
 			PusherKind::Http(http) => {
 				// TODO:
@@ -244,10 +245,7 @@ impl Service {
 					notifi.prio = NotificationPriority::High;
 				}
 
-				if event_id_only {
-					self.send_request(&http.url, send_event_notification::v1::Request::new(notifi))
-						.await?;
-				} else {
+				if !event_id_only {
 					notifi.sender = Some(event.sender.clone());
 					notifi.event_type = Some(event.kind.clone());
 					notifi.content = serde_json::value::to_raw_value(&event.content).ok();
@@ -259,16 +257,392 @@ impl Service {
 					notifi.sender_display_name = services().users.displayname(&event.sender)?;
 
 					notifi.room_name = services().rooms.state_accessor.get_name(&event.room_id)?;
+				}
+
+				let response = self
+					.send_request(&http.url, send_event_notification::v1::Request::new(notifi))
+					.await?;
 
-					self.send_request(&http.url, send_event_notification::v1::Request::new(notifi))
-						.await?;
+				if pushkey_rejected(&response, &pusher.ids.pushkey) {
+					info!("Push gateway {} rejected pushkey of {user}, removing the pusher", http.url);
+					self.set_pusher(user, set_pusher::v3::PusherAction::Delete(pusher.ids.clone()))?;
 				}
 
 				Ok(())
 			},
-			// TODO: Handle email
-			//PusherKind::Email(_) => Ok(()),
+			// Email pushers get digests instead, see email.rs
 			_ => Ok(()),
 		}
 	}
 }
+
+impl GatewayBackoff {
+	/// Sends the request to the push gateway at `dest` unless we are backing
+	/// off from it after repeated failures, and records whether it failed
+	async fn send<T>(
+		&self, client: &reqwest::Client, dest: &str, request: T, allowed_ip: &(dyn Fn(&IPAddress) -> bool + Sync),
+	) -> Result<T::IncomingResponse>
+	where
+		T: OutgoingRequest + Debug,
+	{
+		if self.is_backed_off(dest) {
+			return Err(Error::BadServerResponse(
+				"Not sending request to push gateway that is backed off after repeated failures.",
+			));
+		}
+
+		let response = send_gateway_request(client, dest, request, allowed_ip).await;
+
+		let mut failures = self.failures.lock().expect("locked for writing");
+		if response.is_ok() {
+			failures.remove(dest);
+		} else {
+			let (count, last_failure) = failures
+				.entry(dest.to_owned())
+				.or_insert((0, Instant::now()));
+			*count = count.saturating_add(1);
+			*last_failure = Instant::now();
+		}
+
+		response
+	}
+
+	/// Whether the push gateway failed recently enough to not be tried again
+	/// yet
+	fn is_backed_off(&self, dest: &str) -> bool {
+		self.failures
+			.lock()
+			.expect("locked for reading")
+			.get(dest)
+			.is_some_and(|(count, last_failure)| last_failure.elapsed() < backoff_duration(*count))
+	}
+}
+
+/// Whether the push gateway rejected the pushkey, the pusher has to be
+/// removed then
+fn pushkey_rejected(response: &send_event_notification::v1::Response, pushkey: &str) -> bool {
+	response.rejected.iter().any(|rejected| rejected == pushkey)
+}
+
+/// Merges the tweaks of an older notice into those of a newer one that
+/// replaces it: a highlight of either is kept, and the older notice's sound
+/// is kept if the newer one is silent
+fn merge_tweaks(tweaks: &mut Vec<Tweak>, older: Vec<Tweak>) {
+	for tweak in older {
+		match tweak {
+			Tweak::Highlight(true) => {
+				tweaks.retain(|t| !matches!(t, Tweak::Highlight(_)));
+				tweaks.push(Tweak::Highlight(true));
+			},
+			Tweak::Highlight(false) => {},
+			Tweak::Sound(sound) => {
+				if !sound.is_empty()
+					&& !tweaks
+						.iter()
+						.any(|t| matches!(t, Tweak::Sound(s) if !s.is_empty()))
+				{
+					tweaks.retain(|t| !matches!(t, Tweak::Sound(_)));
+					tweaks.push(Tweak::Sound(sound));
+				}
+			},
+			tweak => {
+				if !tweaks
+					.iter()
+					.any(|t| mem::discriminant(t) == mem::discriminant(&tweak))
+				{
+					tweaks.push(tweak);
+				}
+			},
+		}
+	}
+}
+
+/// Coalesces the notices of a pusher to the newest one of each room, which
+/// carries the tweaks of the older ones of that room
+pub(crate) fn coalesce_notices(
+	notices: impl IntoIterator<Item = (PduEvent, Vec<Tweak>)>,
+) -> Vec<(PduEvent, Vec<Tweak>)> {
+	let mut coalesced: Vec<(PduEvent, Vec<Tweak>)> = Vec::new();
+	for (pdu, mut tweaks) in notices {
+		if let Some(index) = coalesced
+			.iter()
+			.position(|(older, _)| older.room_id == pdu.room_id)
+		{
+			let (_, older_tweaks) = coalesced.remove(index);
+			merge_tweaks(&mut tweaks, older_tweaks);
+		}
+
+		coalesced.push((pdu, tweaks));
+	}
+
+	coalesced
+}
+
+/// Sends the request to the push gateway at `dest`, refusing to talk to IPs
+/// for which `allowed_ip` returns false
+async fn send_gateway_request<T>(
+	client: &reqwest::Client, dest: &str, request: T, allowed_ip: &(dyn Fn(&IPAddress) -> bool + Sync),
+) -> Result<T::IncomingResponse>
+where
+	T: OutgoingRequest + Debug,
+{
+	const VERSIONS: [MatrixVersion; 1] = [MatrixVersion::V1_0];
+
+	let http_request = request
+		.try_into_http_request::<BytesMut>(dest, SendAccessToken::IfRequired(""), &VERSIONS)
+		.map_err(|e| {
+			warn!("Failed to find destination {dest} for push gateway: {e}");
+			Error::BadServerResponse("Invalid push gateway destination")
+		})?
+		.map(BytesMut::freeze);
+
+	let reqwest_request = reqwest::Request::try_from(http_request)?;
+
+	if let Some(url_host) = reqwest_request.url().host_str() {
+		trace!("Checking request URL for IP");
+		if let Ok(ip) = IPAddress::parse(url_host) {
+			if !allowed_ip(&ip) {
+				return Err(Error::BadServerResponse("Not allowed to send requests to this IP"));
+			}
+		}
+	}
+
+	let response = client.execute(reqwest_request).await;
+
+	match response {
+		Ok(mut response) => {
+			// reqwest::Response -> http::Response conversion
+
+			trace!("Checking response destination's IP");
+			if let Some(remote_addr) = response.remote_addr() {
+				if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
+					if !allowed_ip(&ip) {
+						return Err(Error::BadServerResponse("Not allowed to send requests to this IP"));
+					}
+				}
+			}
+
+			let status = response.status();
+			let mut http_response_builder = http::Response::builder()
+				.status(status)
+				.version(response.version());
+			mem::swap(
+				response.headers_mut(),
+				http_response_builder
+					.headers_mut()
+					.expect("http::response::Builder is usable"),
+			);
+
+			let body = response.bytes().await?; // TODO: handle timeout
+
+			if !status.is_success() {
+				info!("Push gateway {dest} returned unsuccessful HTTP response ({status})");
+				debug_info!("Push gateway response body: {:?}", crate::utils::string_from_bytes(&body));
+
+				return Err(Error::BadServerResponse("Push gateway returned unsuccessful response"));
+			}
+
+			let response = T::IncomingResponse::try_from_http_response(
+				http_response_builder
+					.body(body)
+					.expect("reqwest body is valid http body"),
+			);
+			response.map_err(|e| {
+				warn!("Push gateway {dest} returned invalid response bytes: {e}");
+				Error::BadServerResponse("Push gateway returned bad/invalid response")
+			})
+		},
+		Err(e) => {
+			warn!("Could not send request to pusher {dest}: {e}");
+			Err(e.into())
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{Read, Write},
+		net::TcpListener,
+		sync::mpsc,
+		thread,
+		time::Duration,
+	};
+
+	use ruma::{
+		api::push_gateway::send_event_notification::v1::{Device, Notification, Request},
+		push::Tweak,
+	};
+
+	use super::{coalesce_notices, pushkey_rejected, send_gateway_request, GatewayBackoff};
+	use crate::PduEvent;
+
+	/// Answers a single request with the given status line and JSON body,
+	/// returning the URL of the gateway and the body of the request it got
+	fn mock_gateway(status: &'static str, body: &'static str) -> (String, mpsc::Receiver<String>) {
+		let listener = TcpListener::bind("127.0.0.1:0").expect("can bind to a local port");
+		let url = format!("http://{}", listener.local_addr().expect("listener has an address"));
+		let (sender, receiver) = mpsc::channel();
+
+		thread::spawn(move || {
+			let (mut stream, _) = listener.accept().expect("gateway gets a request");
+
+			// read the whole request before answering it
+			let mut request = Vec::new();
+			let mut buf = [0_u8; 4096];
+			loop {
+				let read = stream.read(&mut buf).expect("request can be read");
+				request.extend_from_slice(&buf[..read]);
+				let text = String::from_utf8_lossy(&request);
+				if let Some(headers_end) = text.find("\r\n\r\n") {
+					let content_length = text[..headers_end]
+						.lines()
+						.find_map(|line| {
+							let (name, value) = line.split_once(':')?;
+							name.eq_ignore_ascii_case("content-length")
+								.then(|| value.trim().parse::<usize>().ok())?
+						})
+						.unwrap_or(0);
+					if request.len() >= headers_end + 4 + content_length {
+						_ = sender.send(text[headers_end + 4..].to_owned());
+						break;
+					}
+				}
+				if read == 0 {
+					break;
+				}
+			}
+
+			write!(
+				stream,
+				"HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: \
+				 close\r\n\r\n{body}",
+				body.len()
+			)
+			.expect("response can be written");
+		});
+
+		(url, receiver)
+	}
+
+	fn pdu(event_id: &str, room_id: &str) -> PduEvent {
+		serde_json::from_value(serde_json::json!({
+			"event_id": event_id,
+			"room_id": room_id,
+			"sender": "@alice:example.com",
+			"origin_server_ts": 1,
+			"type": "m.room.message",
+			"content": { "msgtype": "m.text", "body": "hi" },
+			"prev_events": [],
+			"depth": 1,
+			"auth_events": [],
+			"hashes": { "sha256": "" },
+		}))
+		.expect("PDU is valid")
+	}
+
+	fn notification_request(tweaks: Vec<Tweak>) -> Request {
+		let mut device = Device::new("org.example.app".to_owned(), "pushkey".to_owned());
+		device.tweaks = tweaks;
+		Request::new(Notification::new(vec![device]))
+	}
+
+	#[tokio::test]
+	async fn gateway_rejected_pushkeys_are_returned() {
+		let (url, _) = mock_gateway("200 OK", r#"{"rejected":["pushkey"]}"#);
+
+		let response = send_gateway_request(&reqwest::Client::new(), &url, notification_request(Vec::new()), &|_| true)
+			.await
+			.expect("gateway answered successfully");
+
+		assert_eq!(response.rejected, vec!["pushkey".to_owned()]);
+	}
+
+	#[tokio::test]
+	async fn gateway_errors_are_failures() {
+		let (url, _) = mock_gateway("500 Internal Server Error", r#"{"errcode":"M_UNKNOWN"}"#);
+
+		assert!(
+			send_gateway_request(&reqwest::Client::new(), &url, notification_request(Vec::new()), &|_| true)
+				.await
+				.is_err()
+		);
+	}
+
+	#[tokio::test]
+	async fn coalesced_notices_keep_highlight_and_sound_per_room() {
+		let (url, requests) = mock_gateway("200 OK", r#"{"rejected":[]}"#);
+
+		// a highlighted mention with a sound, a message in another room and a plain
+		// message after the mention
+		let notices = coalesce_notices([
+			(
+				pdu("$mention", "!a:example.com"),
+				vec![Tweak::Highlight(true), Tweak::Sound("default".to_owned())],
+			),
+			(pdu("$other", "!b:example.com"), vec![Tweak::Highlight(false)]),
+			(pdu("$plain", "!a:example.com"), vec![Tweak::Highlight(false)]),
+		]);
+
+		assert_eq!(notices.len(), 2);
+		let (other, other_tweaks) = &notices[0];
+		assert_eq!(other.event_id.as_str(), "$other");
+		assert!(matches!(other_tweaks.as_slice(), [Tweak::Highlight(false)]));
+		let (newest, tweaks) = notices[1].clone();
+		assert_eq!(newest.event_id.as_str(), "$plain");
+
+		GatewayBackoff::default()
+			.send(&reqwest::Client::new(), &url, notification_request(tweaks), &|_| true)
+			.await
+			.expect("gateway answered successfully");
+
+		let body: serde_json::Value = serde_json::from_str(
+			&requests
+				.recv_timeout(Duration::from_secs(5))
+				.expect("gateway got the notice"),
+		)
+		.expect("notice is JSON");
+		let tweaks: Vec<Tweak> =
+			serde_json::from_value(body["notification"]["devices"][0]["tweaks"].clone()).expect("notice has tweaks");
+
+		assert_eq!(tweaks.len(), 2);
+		assert!(tweaks.iter().any(|t| matches!(t, Tweak::Highlight(true))));
+		assert!(tweaks
+			.iter()
+			.any(|t| matches!(t, Tweak::Sound(sound) if sound == "default")));
+		assert!(requests.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn rejected_pushkey_is_detected() {
+		let (url, _) = mock_gateway("200 OK", r#"{"rejected":["pushkey"]}"#);
+
+		let response = GatewayBackoff::default()
+			.send(&reqwest::Client::new(), &url, notification_request(Vec::new()), &|_| true)
+			.await
+			.expect("gateway answered successfully");
+
+		assert!(pushkey_rejected(&response, "pushkey"));
+		assert!(!pushkey_rejected(&response, "other-pushkey"));
+	}
+
+	#[tokio::test]
+	async fn backoff_is_per_gateway() {
+		let backoff = GatewayBackoff::default();
+		let client = reqwest::Client::new();
+		let (failing, _) = mock_gateway("500 Internal Server Error", r#"{"errcode":"M_UNKNOWN"}"#);
+		let (healthy, _) = mock_gateway("200 OK", r#"{"rejected":[]}"#);
+
+		assert!(backoff
+			.send(&client, &failing, notification_request(Vec::new()), &|_| true)
+			.await
+			.is_err());
+		assert!(backoff.is_backed_off(&failing));
+
+		backoff
+			.send(&client, &healthy, notification_request(Vec::new()), &|_| true)
+			.await
+			.expect("other gateways are still tried");
+		assert!(!backoff.is_backed_off(&healthy));
+	}
+}
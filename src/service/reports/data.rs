@@ -0,0 +1,12 @@
+use super::Report;
+use crate::Result;
+
+pub(crate) trait Data: Send + Sync {
+	/// Inserts or replaces the report with the report's ID
+	fn put_report(&self, report: &Report) -> Result<()>;
+
+	fn get_report(&self, id: u64) -> Result<Option<Report>>;
+
+	/// All reports, newest first
+	fn all_reports<'a>(&'a self) -> Box<dyn Iterator<Item = Result<Report>> + 'a>;
+}
@@ -0,0 +1,161 @@
+mod data;
+
+use std::fmt;
+
+pub(crate) use data::Data;
+use ruma::{
+	events::room::message::RoomMessageEventContent, Int, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{services, utils, utils::HtmlEscape, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReportStatus {
+	Open,
+	Resolved,
+	Dismissed,
+}
+
+impl fmt::Display for ReportStatus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Open => "open",
+			Self::Resolved => "resolved",
+			Self::Dismissed => "dismissed",
+		})
+	}
+}
+
+/// A report of a room or of an event in it by a local user
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Report {
+	pub(crate) id: u64,
+	pub(crate) reporter: OwnedUserId,
+	pub(crate) room_id: OwnedRoomId,
+	/// Not set for reports of the whole room
+	pub(crate) event_id: Option<OwnedEventId>,
+	pub(crate) event_sender: Option<OwnedUserId>,
+	pub(crate) reason: Option<String>,
+	pub(crate) score: Option<Int>,
+	pub(crate) created: u64,
+	pub(crate) status: ReportStatus,
+	pub(crate) handled_by: Option<OwnedUserId>,
+	pub(crate) handled_at: Option<u64>,
+	pub(crate) note: Option<String>,
+}
+
+pub(crate) struct Service {
+	pub(crate) db: &'static dyn Data,
+}
+
+impl Service {
+	/// Stores a new open report and notifies the admin room about it if
+	/// `report_notifications` is enabled
+	pub(crate) async fn create(
+		&self, reporter: &UserId, room_id: &RoomId, event: Option<(OwnedEventId, OwnedUserId)>, reason: Option<String>,
+		score: Option<Int>,
+	) -> Result<Report> {
+		let (event_id, event_sender) = event.unzip();
+		let report = Report {
+			id: services().globals.next_count()?,
+			reporter: reporter.to_owned(),
+			room_id: room_id.to_owned(),
+			event_id,
+			event_sender,
+			reason,
+			score,
+			created: utils::millis_since_unix_epoch(),
+			status: ReportStatus::Open,
+			handled_by: None,
+			handled_at: None,
+			note: None,
+		};
+		self.db.put_report(&report)?;
+
+		if services().globals.config.report_notifications {
+			services()
+				.admin
+				.send_message(report_notification(&report))
+				.await;
+		}
+
+		Ok(report)
+	}
+
+	pub(crate) fn get(&self, id: u64) -> Result<Option<Report>> { self.db.get_report(id) }
+
+	/// Reports with the given status, or all reports, newest first
+	pub(crate) fn list<'a>(&'a self, status: Option<ReportStatus>) -> impl Iterator<Item = Result<Report>> + 'a {
+		self.db
+			.all_reports()
+			.filter(move |report| match (report, status) {
+				(Ok(report), Some(status)) => report.status == status,
+				_ => true,
+			})
+	}
+
+	/// Changes the status of the report, returns `None` if it doesn't exist
+	pub(crate) fn set_status(
+		&self, id: u64, status: ReportStatus, handled_by: &UserId, note: Option<String>,
+	) -> Result<Option<Report>> {
+		let Some(mut report) = self.get(id)? else {
+			return Ok(None);
+		};
+
+		report.status = status;
+		report.handled_by = Some(handled_by.to_owned());
+		report.handled_at = Some(utils::millis_since_unix_epoch());
+		report.note = note;
+		self.db.put_report(&report)?;
+
+		Ok(Some(report))
+	}
+}
+
+/// The admin room message announcing a new report, with an @room ping for
+/// urgency
+fn report_notification(report: &Report) -> RoomMessageEventContent {
+	let score = report.score.unwrap_or_else(|| Int::from(0));
+	let reason = report.reason.as_deref().unwrap_or("");
+	match (&report.event_id, &report.event_sender) {
+		(Some(event_id), Some(event_sender)) => RoomMessageEventContent::text_html(
+			format!(
+				"@room Report {} received from: {}\n\nEvent ID: {}\nRoom ID: {}\nSent By: {}\n\nReport Score: \
+				 {}\nReport Reason: {}",
+				report.id, report.reporter, event_id, report.room_id, event_sender, score, reason
+			),
+			format!(
+				"<details><summary>@room Report {6} received from: <a href=\"https://matrix.to/#/{0}\">{0}\
+                </a></summary><ul><li>Event Info<ul><li>Event ID: <code>{1}</code>\
+                <a href=\"https://matrix.to/#/{2}/{1}\">🔗</a></li><li>Room ID: <code>{2}</code>\
+                </li><li>Sent By: <a href=\"https://matrix.to/#/{3}\">{3}</a></li></ul></li><li>\
+                Report Info<ul><li>Report Score: {4}</li><li>Report Reason: {5}</li></ul></li>\
+                </ul></details>",
+				report.reporter,
+				event_id,
+				report.room_id,
+				event_sender,
+				score,
+				HtmlEscape(reason),
+				report.id,
+			),
+		),
+		_ => RoomMessageEventContent::text_html(
+			format!(
+				"@room Report {} received from: {}\n\nRoom ID: {}\n\nReport Reason: {}",
+				report.id, report.reporter, report.room_id, reason
+			),
+			format!(
+				"<details><summary>@room Report {3} received from: <a href=\"https://matrix.to/#/{0}\">{0}\
+                </a></summary><ul><li>Room ID: <code>{1}</code> <a href=\"https://matrix.to/#/{1}\">🔗</a></li>\
+                <li>Report Reason: {2}</li></ul></details>",
+				report.reporter,
+				report.room_id,
+				HtmlEscape(reason),
+				report.id,
+			),
+		),
+	}
+}
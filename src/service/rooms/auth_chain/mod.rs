@@ -57,6 +57,7 @@ impl Service {
 		let mut hits = 0;
 		let mut misses = 0;
 		let mut full_auth_chain = Vec::new();
+		let mut complete = true;
 		for chunk in buckets {
 			if chunk.is_empty() {
 				continue;
@@ -76,6 +77,7 @@ impl Service {
 			let mut hits2 = 0;
 			let mut misses2 = 0;
 			let mut chunk_cache = Vec::new();
+			let mut chunk_complete = true;
 			for (sevent_id, event_id) in chunk {
 				if let Some(cached) = services()
 					.rooms
@@ -85,11 +87,15 @@ impl Service {
 					chunk_cache.extend(cached.iter().copied());
 					hits2 += 1;
 				} else {
-					let auth_chain = self.get_auth_chain_inner(room_id, event_id)?;
-					services()
-						.rooms
-						.auth_chain
-						.cache_auth_chain(vec![sevent_id], &auth_chain)?;
+					let (auth_chain, event_complete) = self.get_auth_chain_inner(room_id, event_id)?;
+					if event_complete {
+						services()
+							.rooms
+							.auth_chain
+							.cache_auth_chain(vec![sevent_id], &auth_chain)?;
+					} else {
+						chunk_complete = false;
+					}
 					chunk_cache.extend(auth_chain.iter());
 					misses2 += 1;
 					debug!(
@@ -104,10 +110,14 @@ impl Service {
 
 			chunk_cache.sort_unstable();
 			chunk_cache.dedup();
-			services()
-				.rooms
-				.auth_chain
-				.cache_auth_chain_vec(chunk_key, &chunk_cache)?;
+			if chunk_complete {
+				services()
+					.rooms
+					.auth_chain
+					.cache_auth_chain_vec(chunk_key, &chunk_cache)?;
+			} else {
+				complete = false;
+			}
 			full_auth_chain.extend(chunk_cache.iter());
 			misses += 1;
 			debug!(
@@ -125,6 +135,7 @@ impl Service {
 			chain_length = ?full_auth_chain.len(),
 			hits = ?hits,
 			misses = ?misses,
+			complete = ?complete,
 			elapsed = ?started.elapsed(),
 			"done",
 		);
@@ -132,10 +143,13 @@ impl Service {
 		Ok(full_auth_chain)
 	}
 
+	/// Walks the auth events of the event. The returned bool is false if some
+	/// of them are missing, the chain must not be persisted then.
 	#[tracing::instrument(skip(self, event_id))]
-	fn get_auth_chain_inner(&self, room_id: &RoomId, event_id: &EventId) -> Result<HashSet<u64>> {
+	fn get_auth_chain_inner(&self, room_id: &RoomId, event_id: &EventId) -> Result<(HashSet<u64>, bool)> {
 		let mut todo = vec![Arc::from(event_id)];
 		let mut found = HashSet::new();
+		let mut complete = true;
 
 		while let Some(event_id) = todo.pop() {
 			match services().rooms.timeline.get_pdu(&event_id) {
@@ -150,20 +164,54 @@ impl Service {
 							.get_or_create_shorteventid(auth_event)?;
 
 						if found.insert(sauthevent) {
-							todo.push(auth_event.clone());
+							// An indexed auth chain already contains everything further up
+							if let Some(cached) = self.get_cached_eventid_authchain(&[sauthevent])? {
+								found.extend(cached.iter().copied());
+							} else {
+								todo.push(auth_event.clone());
+							}
 						}
 					}
 				},
 				Ok(None) => {
 					warn!(?event_id, "Could not find pdu mentioned in auth events");
+					complete = false;
 				},
 				Err(error) => {
 					error!(?event_id, ?error, "Could not load event in auth chain");
+					complete = false;
 				},
 			}
 		}
 
-		Ok(found)
+		Ok((found, complete))
+	}
+
+	/// Computes and persists the auth chain of a newly stored event. The auth
+	/// events of a new event are usually indexed already, so this only has to
+	/// look one level deep instead of walking the whole chain. Incomplete
+	/// chains are computed again once the missing events are there.
+	#[tracing::instrument(skip(self))]
+	pub(crate) fn index_event(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+		let shorteventid = services()
+			.rooms
+			.short
+			.get_or_create_shorteventid(event_id)?;
+
+		if self
+			.get_cached_eventid_authchain(&[shorteventid])?
+			.is_some()
+		{
+			return Ok(());
+		}
+
+		let (auth_chain, complete) = self.get_auth_chain_inner(room_id, event_id)?;
+		if !complete {
+			debug!(?event_id, "Not indexing incomplete auth chain");
+			return Ok(());
+		}
+
+		self.cache_auth_chain(vec![shorteventid], &auth_chain)
 	}
 
 	pub(crate) fn get_cached_eventid_authchain(&self, key: &[u64]) -> Result<Option<Arc<[u64]>>> {
@@ -18,7 +18,7 @@ use ruma::{
 	int,
 	serde::Base64,
 	state_res::{self, RoomVersion, StateMap},
-	uint, CanonicalJsonValue, EventId, MilliSecondsSinceUnixEpoch, RoomId, RoomVersionId, ServerName,
+	uint, CanonicalJsonValue, EventId, MilliSecondsSinceUnixEpoch, RoomId, RoomVersionId, ServerName, UserId,
 };
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, trace, warn};
@@ -26,11 +26,16 @@ use tracing::{debug, error, info, trace, warn};
 use super::state_compressor::CompressedStateEvent;
 use crate::{
 	debug_error, debug_info,
-	service::{pdu, Arc, BTreeMap, HashMap, Result},
+	service::{
+		event_policy::{PolicyCheck, Verdict},
+		pdu, Arc, BTreeMap, HashMap, Result,
+	},
 	services, Error, PduEvent,
 };
 
+mod partial_state;
 mod signing_keys;
+pub(crate) mod state_resolution;
 pub(crate) struct Service;
 
 // We use some AsyncRecursiveType hacks here so we can call async funtion
@@ -96,6 +101,23 @@ impl Service {
 		// 1.3 Check room ACL
 		services().rooms.event_handler.acl_check(origin, room_id)?;
 
+		// 1.4 Check policy lists for the origin and the sender's server
+		let sender_server = value
+			.get("sender")
+			.and_then(CanonicalJsonValue::as_str)
+			.and_then(|sender| <&UserId>::try_from(sender).ok())
+			.map(UserId::server_name);
+		for server in std::iter::once(origin).chain(sender_server) {
+			if let Some(rule) = services().rooms.policy.server_rule(server).await? {
+				debug_info!(
+					"Dropping PDU {event_id} from {origin}, {server} is banned by policy room {}: {}",
+					rule.policy_room,
+					rule.reason
+				);
+				return Err(Error::BadRequest(ErrorKind::forbidden(), "Server is banned by a policy list."));
+			}
+		}
+
 		// Fetch create event
 		let create_event = services()
 			.rooms
@@ -359,34 +381,7 @@ impl Service {
 			// 6. Reject "due to auth events" if the event doesn't pass auth based on the
 			//    auth events
 			debug!("Checking based on auth events");
-			// Build map of auth events
-			let mut auth_events = HashMap::new();
-			for id in &incoming_pdu.auth_events {
-				let Some(auth_event) = services().rooms.timeline.get_pdu(id)? else {
-					warn!("Could not find auth event {}", id);
-					continue;
-				};
-
-				self.check_room_id(room_id, &auth_event)?;
-
-				match auth_events.entry((
-					auth_event.kind.to_string().into(),
-					auth_event
-						.state_key
-						.clone()
-						.expect("all auth events have state keys"),
-				)) {
-					hash_map::Entry::Vacant(v) => {
-						v.insert(auth_event);
-					},
-					hash_map::Entry::Occupied(_) => {
-						return Err(Error::BadRequest(
-							ErrorKind::InvalidParam,
-							"Auth event's type and state_key combination exists multiple times.",
-						));
-					},
-				}
-			}
+			let auth_events = self.auth_events_map(room_id, &incoming_pdu)?;
 
 			// The original create event must be in the auth events
 			if !matches!(
@@ -419,6 +414,10 @@ impl Service {
 				.rooms
 				.outlier
 				.add_pdu_outlier(&incoming_pdu.event_id, &val)?;
+			services()
+				.rooms
+				.auth_chain
+				.index_event(room_id, &incoming_pdu.event_id)?;
 
 			trace!("Added pdu as outlier.");
 
@@ -494,7 +493,12 @@ impl Service {
 		)
 		.map_err(|_e| Error::BadRequest(ErrorKind::forbidden(), "Auth check failed."))?;
 
-		if !check_result {
+		// Rooms we joined with partial state are missing most memberships, so their
+		// state can't reject an event that passed auth based on its auth events. The
+		// event is checked again once the full state arrives.
+		let partial_state = services().rooms.metadata.is_partial_state(room_id)?;
+
+		if !check_result && !partial_state {
 			return Err(Error::BadRequest(
 				ErrorKind::forbidden(),
 				"Event has failed auth check with state at the event.",
@@ -512,10 +516,34 @@ impl Service {
 
 		// Soft fail check before doing state res
 		debug!("Performing soft-fail check");
-		let soft_fail = !state_res::event_auth::auth_check(&room_version, &incoming_pdu, None::<PduEvent>, |k, s| {
-			auth_events.get(&(k.clone(), s.to_owned()))
-		})
-		.map_err(|_e| Error::BadRequest(ErrorKind::forbidden(), "Auth check failed."))?;
+		let mut soft_fail =
+			!state_res::event_auth::auth_check(&room_version, &incoming_pdu, None::<PduEvent>, |k, s| {
+				auth_events.get(&(k.clone(), s.to_owned()))
+			})
+			.map_err(|_e| Error::BadRequest(ErrorKind::forbidden(), "Auth check failed."))?
+				&& !partial_state;
+
+		// Let the event policy hooks reject or soft fail the event
+		let check = PolicyCheck::Event {
+			event: &incoming_pdu,
+			local: false,
+		};
+		match services().event_policy.check(&check).await? {
+			Verdict::Allow => {},
+			Verdict::SoftFail => {
+				debug_info!("Event policy soft failed {}", incoming_pdu.event_id);
+				soft_fail = true;
+			},
+			Verdict::Deny {
+				reason,
+			} => {
+				debug_info!("Event policy rejected {}: {reason:?}", incoming_pdu.event_id);
+				return Err(Error::BadRequest(
+					ErrorKind::forbidden(),
+					"Rejected by this server's event policy.",
+				));
+			},
+		}
 
 		// 13. Use state resolution to find new room state
 
@@ -646,6 +674,11 @@ impl Service {
 			)
 			.await?;
 
+		if partial_state {
+			self.record_partial_state_event(room_id, &room_version, &incoming_pdu.event_id, &state_lock)
+				.await?;
+		}
+
 		// Event has passed all auth/stateres checks
 		drop(state_lock);
 		debug_info!(
@@ -674,18 +707,6 @@ impl Service {
 
 		let fork_states = [current_state_ids, incoming_state];
 
-		let mut auth_chain_sets = Vec::new();
-		for state in &fork_states {
-			auth_chain_sets.push(
-				services()
-					.rooms
-					.auth_chain
-					.event_ids_iter(room_id, state.iter().map(|(_, id)| id.clone()).collect())
-					.await?
-					.collect(),
-			);
-		}
-
 		debug!("Loading fork states");
 		let fork_states: Vec<_> = fork_states
 			.into_iter()
@@ -703,28 +724,9 @@ impl Service {
 			})
 			.collect();
 
-		let lock = services().globals.stateres_mutex.lock();
-
-		debug!("Resolving state");
-		let state_resolve = state_res::resolve(room_version_id, &fork_states, auth_chain_sets, |id| {
-			let res = services().rooms.timeline.get_pdu(id);
-			if let Err(e) = &res {
-				error!("Failed to fetch event: {}", e);
-			}
-			res.ok().flatten()
-		});
-
-		let state = match state_resolve {
-			Ok(new_state) => new_state,
-			Err(e) => {
-				error!("State resolution failed: {}", e);
-				return Err(Error::bad_database(
-					"State resolution failed, either an event could not be found or deserialization",
-				));
-			},
-		};
-
-		drop(lock);
+		let state = self
+			.resolve_fork_states(room_id, room_version_id, &fork_states)
+			.await?;
 
 		debug!("State resolution done. Compressing state");
 		let new_room_state = state
@@ -825,8 +827,6 @@ impl Service {
 		}
 
 		let mut fork_states = Vec::with_capacity(extremity_sstatehashes.len());
-		let mut auth_chain_sets = Vec::with_capacity(extremity_sstatehashes.len());
-
 		for (sstatehash, prev_event) in extremity_sstatehashes {
 			let mut leaf_state: HashMap<_, _> = services()
 				.rooms
@@ -844,40 +844,22 @@ impl Service {
 			}
 
 			let mut state = StateMap::with_capacity(leaf_state.len());
-			let mut starting_events = Vec::with_capacity(leaf_state.len());
-
 			for (k, id) in leaf_state {
 				if let Ok((ty, st_key)) = services().rooms.short.get_statekey_from_short(k) {
 					// FIXME: Undo .to_string().into() when StateMap
 					//        is updated to use StateEventType
-					state.insert((ty.to_string().into(), st_key), id.clone());
+					state.insert((ty.to_string().into(), st_key), id);
 				} else {
 					warn!("Failed to get_statekey_from_short.");
 				}
-				starting_events.push(id);
 			}
 
-			auth_chain_sets.push(
-				services()
-					.rooms
-					.auth_chain
-					.event_ids_iter(room_id, starting_events)
-					.await?
-					.collect(),
-			);
-
 			fork_states.push(state);
 		}
 
-		let lock = services().globals.stateres_mutex.lock();
-		let result = state_res::resolve(room_version_id, &fork_states, auth_chain_sets, |id| {
-			let res = services().rooms.timeline.get_pdu(id);
-			if let Err(e) = &res {
-				error!("Failed to fetch event: {}", e);
-			}
-			res.ok().flatten()
-		});
-		drop(lock);
+		let result = self
+			.resolve_fork_states(room_id, room_version_id, &fork_states)
+			.await;
 
 		Ok(match result {
 			Ok(new_state) => Some(
@@ -893,10 +875,7 @@ impl Service {
 					.collect::<Result<_>>()?,
 			),
 			Err(e) => {
-				warn!(
-					"State resolution on prev events failed, either an event could not be found or deserialization: {}",
-					e
-				);
+				warn!("State resolution on prev events failed: {}", e);
 				None
 			},
 		})
@@ -1316,6 +1295,42 @@ impl Service {
 		}
 	}
 
+	/// Builds the map of the auth events an event refers to, by their type and
+	/// state key
+	fn auth_events_map(
+		&self, room_id: &RoomId, pdu: &PduEvent,
+	) -> Result<HashMap<(StateEventType, String), Arc<PduEvent>>> {
+		let mut auth_events = HashMap::new();
+		for id in &pdu.auth_events {
+			let Some(auth_event) = services().rooms.timeline.get_pdu(id)? else {
+				warn!("Could not find auth event {}", id);
+				continue;
+			};
+
+			self.check_room_id(room_id, &auth_event)?;
+
+			match auth_events.entry((
+				auth_event.kind.to_string().into(),
+				auth_event
+					.state_key
+					.clone()
+					.expect("all auth events have state keys"),
+			)) {
+				hash_map::Entry::Vacant(v) => {
+					v.insert(auth_event);
+				},
+				hash_map::Entry::Occupied(_) => {
+					return Err(Error::BadRequest(
+						ErrorKind::InvalidParam,
+						"Auth event's type and state_key combination exists multiple times.",
+					));
+				},
+			}
+		}
+
+		Ok(auth_events)
+	}
+
 	fn check_room_id(&self, room_id: &RoomId, pdu: &PduEvent) -> Result<()> {
 		if pdu.room_id != room_id {
 			warn!("Found event from room {} in room {}", pdu.room_id, room_id);
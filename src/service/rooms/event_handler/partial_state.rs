@@ -0,0 +1,380 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use ruma::{
+	api::federation::event::get_room_state,
+	state_res::{self, RoomVersion},
+	CanonicalJsonObject, EventId, OwnedEventId, RoomId, RoomVersionId,
+};
+use tokio::sync::{MutexGuard, RwLock};
+use tracing::{debug, info, warn};
+
+use super::Service;
+use crate::{api::client_server::validate_and_add_event_id, services, Error, PduEvent, Result};
+
+/// Longest time between two attempts to fetch the full state of a room
+const MAX_RESYNC_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Longest time a request waits for the full state of a room
+const FULL_STATE_WAIT: Duration = Duration::from_secs(10);
+
+impl Service {
+	/// Fetches the full state of a room we joined with partial state in the
+	/// background, retrying until it succeeds.
+	pub(crate) fn start_partial_state_resync(&self, room_id: &RoomId) {
+		let room_id = room_id.to_owned();
+		tokio::spawn(async move {
+			let mut delay = Duration::from_secs(10);
+			loop {
+				match services()
+					.rooms
+					.event_handler
+					.resync_partial_state(&room_id)
+					.await
+				{
+					Ok(()) => break,
+					Err(e) => {
+						warn!(
+							"Failed to fetch the full state of {room_id}, retrying in {}s: {e}",
+							delay.as_secs()
+						);
+						tokio::time::sleep(delay).await;
+						delay = (delay * 2).min(MAX_RESYNC_DELAY);
+					},
+				}
+			}
+		});
+	}
+
+	/// Resumes fetching the full state of the rooms that were still partial
+	/// state on shutdown.
+	pub(crate) fn resume_partial_state_resyncs(&self) {
+		for room_id in services()
+			.rooms
+			.metadata
+			.partial_state_rooms()
+			.filter_map(Result::ok)
+		{
+			debug!("Resuming full state resync of {room_id}");
+			self.start_partial_state_resync(&room_id);
+		}
+	}
+
+	/// Waits a bit for the full state of the room, for endpoints that need all
+	/// members of a room. If it does not arrive in time, the caller goes on
+	/// with the members we know of.
+	pub(crate) async fn wait_for_full_state(&self, room_id: &RoomId) -> Result<()> {
+		let deadline = Instant::now() + FULL_STATE_WAIT;
+		while services().rooms.metadata.is_partial_state(room_id)? {
+			if Instant::now() >= deadline {
+				debug!("Full state of {room_id} is not there yet, using the members we know");
+				break;
+			}
+
+			tokio::time::sleep(Duration::from_secs(1)).await;
+		}
+
+		Ok(())
+	}
+
+	#[tracing::instrument(skip(self))]
+	async fn resync_partial_state(&self, room_id: &RoomId) -> Result<()> {
+		let Some(partial_state) = services().rooms.metadata.partial_state(room_id)? else {
+			return Ok(());
+		};
+
+		// everyone left again before we got the full state
+		if !services()
+			.rooms
+			.state_cache
+			.server_in_room(services().globals.server_name(), room_id)?
+		{
+			services().rooms.metadata.set_partial_state(room_id, None)?;
+			return Ok(());
+		}
+
+		let room_version_id = services().rooms.state.get_room_version(room_id)?;
+		let mut last_error = None;
+		for server in &partial_state.servers {
+			info!("Asking {server} for the full state of {room_id}");
+			let response = match services()
+				.sending
+				.send_federation_request(
+					server,
+					get_room_state::v1::Request {
+						room_id: room_id.to_owned(),
+						event_id: partial_state.join_event_id.clone(),
+					},
+				)
+				.await
+			{
+				Ok(response) => response,
+				Err(e) => {
+					debug!("{server} could not give us the full state of {room_id}: {e}");
+					last_error = Some(e);
+					continue;
+				},
+			};
+
+			self.apply_full_state(room_id, &room_version_id, &response)
+				.await?;
+
+			info!("Fetched the full state of {room_id}");
+
+			return Ok(());
+		}
+
+		Err(last_error.unwrap_or(Error::BadServerResponse("No server in the room to ask for its full state.")))
+	}
+
+	/// Adds the state events we did not know about yet to the current state of
+	/// the room and marks the room as having its full state. Events with bad
+	/// signatures or failing auth are left out, and state that changed since
+	/// our join is kept unless it came from an event that fails the checks
+	/// against the full state.
+	async fn apply_full_state(
+		&self, room_id: &RoomId, room_version_id: &RoomVersionId, response: &get_room_state::v1::Response,
+	) -> Result<()> {
+		let values = response
+			.pdus
+			.iter()
+			.chain(&response.auth_chain)
+			.filter_map(|pdu| serde_json::from_str::<CanonicalJsonObject>(pdu.get()).ok())
+			.collect::<Vec<_>>();
+
+		let pub_key_map = RwLock::new(BTreeMap::new());
+		self.fetch_required_signing_keys(&values, &pub_key_map)
+			.await?;
+
+		let room_version = self.to_room_version(room_version_id);
+
+		// Auth events have a lower depth than the events they authorize, so checking
+		// in order of depth finds them stored already
+		let mut auth_chain = Vec::new();
+		for pdu in &response.auth_chain {
+			let Ok((event_id, value)) = validate_and_add_event_id(pdu, room_version_id, &pub_key_map).await else {
+				continue;
+			};
+			let Ok(pdu) = PduEvent::from_id_val(&event_id, value.clone()) else {
+				continue;
+			};
+			auth_chain.push((pdu, value));
+		}
+		auth_chain.sort_by_key(|(pdu, _)| pdu.depth);
+
+		for (pdu, value) in auth_chain {
+			if self.passes_auth(room_id, &room_version, &pdu) {
+				services()
+					.rooms
+					.outlier
+					.add_pdu_outlier(&pdu.event_id, &value)?;
+			}
+		}
+
+		let mut full_state = HashMap::new();
+		for pdu in &response.pdus {
+			let Ok((event_id, value)) = validate_and_add_event_id(pdu, room_version_id, &pub_key_map).await else {
+				continue;
+			};
+
+			let pdu = PduEvent::from_id_val(&event_id, value.clone()).map_err(|e| {
+				warn!("Invalid PDU in state response: {e} {value:?}");
+				Error::BadServerResponse("Invalid PDU in state response.")
+			})?;
+
+			if !self.passes_auth(room_id, &room_version, &pdu) {
+				continue;
+			}
+
+			services()
+				.rooms
+				.outlier
+				.add_pdu_outlier(&event_id, &value)?;
+
+			if let Some(state_key) = &pdu.state_key {
+				let shortstatekey = services()
+					.rooms
+					.short
+					.get_or_create_shortstatekey(&pdu.kind.to_string().into(), state_key)?;
+				full_state.insert(shortstatekey, pdu.event_id);
+			}
+		}
+
+		let mutex_state = Arc::clone(
+			services()
+				.globals
+				.roomid_mutex_state
+				.write()
+				.await
+				.entry(room_id.to_owned())
+				.or_default(),
+		);
+		let state_lock = mutex_state.lock().await;
+
+		let accepted_events = services()
+			.rooms
+			.metadata
+			.partial_state(room_id)?
+			.map(|partial_state| partial_state.accepted_events)
+			.unwrap_or_default();
+		let failed = self
+			.recheck_accepted_events(room_id, &room_version, &accepted_events, &full_state)
+			.await?;
+
+		self.update_current_state(room_id, &failed, full_state, &state_lock)
+			.await?;
+		services().rooms.metadata.set_partial_state(room_id, None)?;
+
+		Ok(())
+	}
+
+	/// Remembers an event that was accepted while the room had partial state,
+	/// so it is checked again once the full state arrives. If the full state
+	/// arrived while the event was handled, it is checked right away.
+	pub(super) async fn record_partial_state_event(
+		&self, room_id: &RoomId, room_version: &RoomVersion, event_id: &EventId, state_lock: &MutexGuard<'_, ()>,
+	) -> Result<()> {
+		if let Some(mut partial_state) = services().rooms.metadata.partial_state(room_id)? {
+			partial_state.accepted_events.push(event_id.to_owned());
+			return services()
+				.rooms
+				.metadata
+				.set_partial_state(room_id, Some(&partial_state));
+		}
+
+		let failed = self
+			.recheck_accepted_events(room_id, room_version, &[event_id.to_owned()], &HashMap::new())
+			.await?;
+		if !failed.is_empty() {
+			self.update_current_state(room_id, &failed, HashMap::new(), state_lock)
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// Checks the events accepted while the room had partial state again, this
+	/// time against the state at each event completed with the full state. The
+	/// events failing the check are soft failed and returned.
+	async fn recheck_accepted_events(
+		&self, room_id: &RoomId, room_version: &RoomVersion, event_ids: &[OwnedEventId],
+		full_state: &HashMap<u64, Arc<EventId>>,
+	) -> Result<HashSet<Arc<EventId>>> {
+		let mut failed = HashSet::new();
+		for event_id in event_ids {
+			let Some(pdu) = services().rooms.timeline.get_pdu(event_id)? else {
+				continue;
+			};
+
+			let state_at_event = match services()
+				.rooms
+				.state_accessor
+				.pdu_shortstatehash(event_id)?
+			{
+				Some(shortstatehash) => {
+					services()
+						.rooms
+						.state_accessor
+						.state_full_ids(shortstatehash)
+						.await?
+				},
+				None => HashMap::new(),
+			};
+
+			let passes = state_res::event_auth::auth_check(room_version, &pdu, None::<PduEvent>, |k, s| {
+				let shortstatekey = services()
+					.rooms
+					.short
+					.get_shortstatekey(&k.to_string().into(), s)
+					.ok()
+					.flatten()?;
+				state_at_event
+					.get(&shortstatekey)
+					.filter(|id| !failed.contains(*id))
+					.or_else(|| full_state.get(&shortstatekey))
+					.and_then(|id| services().rooms.timeline.get_pdu(id).ok().flatten())
+			})
+			.unwrap_or(false);
+
+			if !passes {
+				warn!("Soft failing {event_id} in {room_id}, it fails the auth check against the full state");
+				services()
+					.rooms
+					.pdu_metadata
+					.mark_event_soft_failed(event_id)?;
+				failed.insert(pdu.event_id.clone());
+			}
+		}
+
+		Ok(failed)
+	}
+
+	/// Removes the state of the failed events from the current state of the
+	/// room and adds the state of `full_state` it does not have yet
+	async fn update_current_state(
+		&self, room_id: &RoomId, failed: &HashSet<Arc<EventId>>, full_state: HashMap<u64, Arc<EventId>>,
+		state_lock: &MutexGuard<'_, ()>,
+	) -> Result<()> {
+		let mut state = match services().rooms.state.get_room_shortstatehash(room_id)? {
+			Some(shortstatehash) => {
+				services()
+					.rooms
+					.state_accessor
+					.state_full_ids(shortstatehash)
+					.await?
+			},
+			None => HashMap::new(),
+		};
+
+		state.retain(|_, event_id| !failed.contains(event_id));
+		for (shortstatekey, event_id) in full_state {
+			state.entry(shortstatekey).or_insert(event_id);
+		}
+
+		let (shortstatehash, new, removed) = services().rooms.state_compressor.save_state(
+			room_id,
+			Arc::new(
+				state
+					.into_iter()
+					.map(|(k, id)| {
+						services()
+							.rooms
+							.state_compressor
+							.compress_state_event(k, &id)
+					})
+					.collect::<Result<_>>()?,
+			),
+		)?;
+
+		services()
+			.rooms
+			.state
+			.force_state(room_id, shortstatehash, new, removed, state_lock)
+			.await
+	}
+
+	/// Whether an event of a state response belongs to the room and passes auth
+	/// based on the auth events it refers to
+	fn passes_auth(&self, room_id: &RoomId, room_version: &RoomVersion, pdu: &PduEvent) -> bool {
+		let Ok(auth_events) = self
+			.check_room_id(room_id, pdu)
+			.and_then(|()| self.auth_events_map(room_id, pdu))
+		else {
+			return false;
+		};
+
+		let passes = state_res::event_auth::auth_check(room_version, pdu, None::<PduEvent>, |k, s| {
+			auth_events.get(&(k.to_string().into(), s.to_owned()))
+		})
+		.unwrap_or(false);
+
+		if !passes {
+			warn!("Ignoring {} in the state of {room_id}, it fails the auth check", pdu.event_id);
+		}
+
+		passes
+	}
+}
@@ -250,6 +250,9 @@ impl super::Service {
 			if let (Ok(get_keys_response), origin) = result {
 				debug!("Result is from {origin}");
 				if let Ok(key) = get_keys_response.server_key.deserialize() {
+					services()
+						.globals
+						.add_signed_server_keys(&origin, &get_keys_response.server_key)?;
 					let result: BTreeMap<_, _> = services()
 						.globals
 						.add_signing_key(&origin, key)?
@@ -412,13 +415,16 @@ impl super::Service {
 			}
 
 			debug!("Asking {origin} for their signing keys over federation");
-			if let Some(server_key) = services()
+			if let Some((server_key, raw_server_key)) = services()
 				.sending
 				.send_federation_request(origin, get_server_keys::v2::Request::new())
 				.await
 				.ok()
-				.and_then(|resp| resp.server_key.deserialize().ok())
+				.and_then(|resp| Some((resp.server_key.deserialize().ok()?, resp.server_key)))
 			{
+				services()
+					.globals
+					.add_signed_server_keys(origin, &raw_server_key)?;
 				services()
 					.globals
 					.add_signing_key(origin, server_key.clone())?;
@@ -444,13 +450,16 @@ impl super::Service {
 			info!("query_trusted_key_servers_first is set to false, querying {origin} first");
 
 			debug!("Asking {origin} for their signing keys over federation");
-			if let Some(server_key) = services()
+			if let Some((server_key, raw_server_key)) = services()
 				.sending
 				.send_federation_request(origin, get_server_keys::v2::Request::new())
 				.await
 				.ok()
-				.and_then(|resp| resp.server_key.deserialize().ok())
+				.and_then(|resp| Some((resp.server_key.deserialize().ok()?, resp.server_key)))
 			{
+				services()
+					.globals
+					.add_signed_server_keys(origin, &raw_server_key)?;
 				services()
 					.globals
 					.add_signing_key(origin, server_key.clone())?;
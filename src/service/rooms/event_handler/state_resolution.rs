@@ -0,0 +1,185 @@
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+
+use ruma::{
+	state_res::{self, StateMap},
+	EventId, RoomId, RoomVersionId,
+};
+use tracing::{debug, error};
+
+use crate::{services, Error, Result};
+
+impl super::Service {
+	/// Resolves the given fork states. Only the conflicted subset of the state
+	/// is actually handed to state resolution: if the forks agree there is
+	/// nothing to resolve, and otherwise only the part of the auth chains which
+	/// can influence the auth difference is loaded.
+	#[tracing::instrument(skip_all, fields(forks = fork_states.len()))]
+	pub(crate) async fn resolve_fork_states(
+		&self, room_id: &RoomId, room_version_id: &RoomVersionId, fork_states: &[StateMap<Arc<EventId>>],
+	) -> Result<StateMap<Arc<EventId>>> {
+		let (unconflicted, conflicted) = separate(fork_states);
+		if conflicted.is_empty() {
+			debug!("Fork states agree, skipping state resolution");
+			return Ok(unconflicted);
+		}
+
+		debug!(
+			unconflicted = unconflicted.len(),
+			conflicted = conflicted.len(),
+			"Loading auth chains of conflicted state"
+		);
+
+		let auth_chain = &services().rooms.auth_chain;
+		let unconflicted_chain = auth_chain
+			.get_auth_chain(room_id, &unconflicted.values().map(|id| &**id).collect::<Vec<_>>())
+			.await?;
+
+		let mut conflicted_chains = Vec::with_capacity(fork_states.len());
+		for state in fork_states {
+			let starting_events: Vec<&EventId> = state
+				.iter()
+				.filter(|(key, _)| conflicted.contains_key(*key))
+				.map(|(_, id)| &**id)
+				.collect();
+
+			conflicted_chains.push(
+				auth_chain
+					.get_auth_chain(room_id, &starting_events)
+					.await?
+					.into_iter()
+					.collect(),
+			);
+		}
+
+		let auth_chain_sets = conflicted_auth_chain_sets(conflicted_chains, &unconflicted_chain)
+			.into_iter()
+			.map(|set| {
+				set.into_iter()
+					.filter_map(|short| services().rooms.short.get_eventid_from_short(short).ok())
+					.collect::<HashSet<_>>()
+			})
+			.collect();
+
+		let lock = services().globals.stateres_mutex.lock();
+
+		debug!("Resolving state");
+		let result = state_res::resolve(room_version_id, fork_states, auth_chain_sets, |id| {
+			let res = services().rooms.timeline.get_pdu(id);
+			if let Err(e) = &res {
+				error!("Failed to fetch event: {}", e);
+			}
+			res.ok().flatten()
+		});
+
+		drop(lock);
+
+		result.map_err(|e| {
+			error!("State resolution failed: {}", e);
+			Error::bad_database("State resolution failed, either an event could not be found or deserialization")
+		})
+	}
+}
+
+/// Splits the fork states into the state all forks agree on and the state keys
+/// which are missing from some fork or differ between forks.
+pub(crate) fn separate<Id>(fork_states: &[StateMap<Id>]) -> (StateMap<Id>, StateMap<Vec<Id>>)
+where
+	Id: Clone + Eq,
+{
+	let mut unconflicted = StateMap::new();
+	let mut conflicted: StateMap<Vec<Id>> = StateMap::new();
+
+	let keys: HashSet<_> = fork_states.iter().flat_map(HashMap::keys).collect();
+	for key in keys {
+		let ids: Vec<Option<&Id>> = fork_states.iter().map(|state| state.get(key)).collect();
+		match ids.first() {
+			Some(Some(first)) if ids.iter().all(|id| *id == Some(*first)) => {
+				unconflicted.insert(key.clone(), (*first).clone());
+			},
+			_ => {
+				let mut ids: Vec<Id> = ids.into_iter().flatten().cloned().collect();
+				ids.dedup();
+				conflicted.insert(key.clone(), ids);
+			},
+		}
+	}
+
+	(unconflicted, conflicted)
+}
+
+/// Turns the auth chains of each fork's conflicted state into sets with the
+/// same auth difference as the auth chains of the full fork states.
+///
+/// The full auth chain of a fork is its conflicted chain plus the chain of the
+/// unconflicted state, which all forks share, so the auth difference is the
+/// union of the conflicted chains minus their intersection minus the
+/// unconflicted chain. Adding the part of the unconflicted chain that occurs in
+/// any conflicted chain to every set yields exactly that while the sets stay
+/// small. `unconflicted_chain` has to be sorted.
+pub(crate) fn conflicted_auth_chain_sets(
+	conflicted_chains: Vec<HashSet<u64>>, unconflicted_chain: &[u64],
+) -> Vec<HashSet<u64>> {
+	let shared: HashSet<u64> = conflicted_chains
+		.iter()
+		.flatten()
+		.filter(|short| unconflicted_chain.binary_search(short).is_ok())
+		.copied()
+		.collect();
+
+	conflicted_chains
+		.into_iter()
+		.map(|mut chain| {
+			chain.extend(&shared);
+			chain
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use ruma::events::StateEventType;
+
+	use super::{conflicted_auth_chain_sets, separate};
+
+	fn auth_difference(sets: &[HashSet<u64>]) -> HashSet<u64> {
+		let union: HashSet<u64> = sets.iter().flatten().copied().collect();
+		union
+			.into_iter()
+			.filter(|id| !sets.iter().all(|set| set.contains(id)))
+			.collect()
+	}
+
+	#[test]
+	fn separate_state() {
+		let key = |state_key: &str| (StateEventType::RoomMember, state_key.to_owned());
+		let fork_a = [(key("a"), 1), (key("b"), 2), (key("c"), 3)].into();
+		let fork_b = [(key("a"), 1), (key("b"), 4)].into();
+
+		let (unconflicted, conflicted) = separate(&[fork_a, fork_b]);
+		assert_eq!(unconflicted, [(key("a"), 1)].into());
+		assert_eq!(conflicted, [(key("b"), vec![2, 4]), (key("c"), vec![3])].into());
+	}
+
+	#[test]
+	fn conflicted_auth_difference() {
+		// 1 and 2 are shared by the unconflicted state, 2 is also reachable from
+		// the conflicted events of the first fork only
+		let unconflicted_chain = [1, 2, 7];
+		let conflicted_chains = vec![HashSet::from([2, 3, 4]), HashSet::from([3, 5])];
+
+		let full: Vec<HashSet<u64>> = conflicted_chains
+			.iter()
+			.map(|chain| chain.iter().chain(&unconflicted_chain).copied().collect())
+			.collect();
+
+		let sets = conflicted_auth_chain_sets(conflicted_chains, &unconflicted_chain);
+		assert_eq!(auth_difference(&sets), auth_difference(&full));
+		assert_eq!(auth_difference(&sets), HashSet::from([4, 5]));
+		assert!(sets.iter().all(|set| !set.contains(&7)));
+	}
+}
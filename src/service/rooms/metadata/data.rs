@@ -1,5 +1,6 @@
 use ruma::{OwnedRoomId, RoomId};
 
+use super::PartialState;
 use crate::Result;
 
 pub(crate) trait Data: Send + Sync {
@@ -10,4 +11,7 @@ pub(crate) trait Data: Send + Sync {
 	fn is_banned(&self, room_id: &RoomId) -> Result<bool>;
 	fn ban_room(&self, room_id: &RoomId, banned: bool) -> Result<()>;
 	fn list_banned_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a>;
+	fn partial_state(&self, room_id: &RoomId) -> Result<Option<PartialState>>;
+	fn set_partial_state(&self, room_id: &RoomId, partial_state: Option<&PartialState>) -> Result<()>;
+	fn partial_state_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a>;
 }
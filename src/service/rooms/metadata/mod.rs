@@ -1,10 +1,25 @@
 mod data;
 
 pub(crate) use data::Data;
-use ruma::{OwnedRoomId, RoomId};
+use ruma::{OwnedEventId, OwnedRoomId, OwnedServerName, RoomId};
+use serde::{Deserialize, Serialize};
 
 use crate::Result;
 
+/// A room we joined without the member events of its state (MSC3706), whose
+/// full state is still being fetched in the background.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct PartialState {
+	/// Our join event, the full state is requested at it
+	pub(crate) join_event_id: OwnedEventId,
+	/// The servers in the room according to the resident server
+	pub(crate) servers: Vec<OwnedServerName>,
+	/// Events accepted without checking them against the full state, they are
+	/// checked again once it arrives
+	#[serde(default)]
+	pub(crate) accepted_events: Vec<OwnedEventId>,
+}
+
 pub(crate) struct Service {
 	pub(crate) db: &'static dyn Data,
 }
@@ -29,4 +44,23 @@ impl Service {
 	pub(crate) fn list_banned_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a> {
 		self.db.list_banned_rooms()
 	}
+
+	/// Returns the partial state information if we don't know the full state
+	/// of the room yet.
+	pub(crate) fn partial_state(&self, room_id: &RoomId) -> Result<Option<PartialState>> {
+		self.db.partial_state(room_id)
+	}
+
+	pub(crate) fn is_partial_state(&self, room_id: &RoomId) -> Result<bool> {
+		Ok(self.partial_state(room_id)?.is_some())
+	}
+
+	/// Marks the room as partial state, or as having its full state with None
+	pub(crate) fn set_partial_state(&self, room_id: &RoomId, partial_state: Option<&PartialState>) -> Result<()> {
+		self.db.set_partial_state(room_id, partial_state)
+	}
+
+	pub(crate) fn partial_state_rooms<'a>(&'a self) -> Box<dyn Iterator<Item = Result<OwnedRoomId>> + 'a> {
+		self.db.partial_state_rooms()
+	}
 }
@@ -6,6 +6,7 @@ pub(crate) mod lazy_loading;
 pub(crate) mod metadata;
 pub(crate) mod outlier;
 pub(crate) mod pdu_metadata;
+pub(crate) mod policy;
 pub(crate) mod read_receipt;
 pub(crate) mod search;
 pub(crate) mod short;
@@ -49,6 +50,7 @@ pub(crate) struct Service {
 	pub(crate) metadata: metadata::Service,
 	pub(crate) outlier: outlier::Service,
 	pub(crate) pdu_metadata: pdu_metadata::Service,
+	pub(crate) policy: policy::Service,
 	pub(crate) read_receipt: read_receipt::Service,
 	pub(crate) search: search::Service,
 	pub(crate) short: short::Service,
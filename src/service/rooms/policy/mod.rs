@@ -0,0 +1,195 @@
+use std::sync::{Arc, RwLock};
+
+use ruma::{OwnedRoomId, RoomId, ServerName, UserId};
+use serde::Deserialize;
+use tracing::{debug, error, info};
+
+use crate::{api::client_server::join_room_by_id_helper, services, utils, utils::server_name::server_is_ours, Result};
+
+/// Recommendations which mean the entity should be banned. The second one is
+/// used by older versions of Mjolnir.
+const BAN_RECOMMENDATIONS: &[&str] = &["m.ban", "org.matrix.mjolnir.ban"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PolicyKind {
+	User,
+	Server,
+	Room,
+}
+
+impl PolicyKind {
+	/// Also accepts the event types used before MSC2313 was merged
+	fn from_event_type(kind: &str) -> Option<Self> {
+		match kind {
+			"m.policy.rule.user" | "m.room.rule.user" | "org.matrix.mjolnir.rule.user" => Some(Self::User),
+			"m.policy.rule.server" | "m.room.rule.server" | "org.matrix.mjolnir.rule.server" => Some(Self::Server),
+			"m.policy.rule.room" | "m.room.rule.room" | "org.matrix.mjolnir.rule.room" => Some(Self::Room),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PolicyRule {
+	pub(crate) kind: PolicyKind,
+	/// Glob matched against the user ID, server name or room ID
+	pub(crate) entity: String,
+	pub(crate) reason: String,
+	pub(crate) policy_room: OwnedRoomId,
+}
+
+#[derive(Deserialize)]
+struct PolicyRuleContent {
+	entity: String,
+	recommendation: String,
+	#[serde(default)]
+	reason: String,
+}
+
+pub(crate) struct Service {
+	/// Ban rules of all policy rooms, rebuilt after a policy room changes
+	pub(crate) rules: RwLock<Option<Arc<[PolicyRule]>>>,
+}
+
+impl Service {
+	/// Joins the server user to the configured policy rooms it is not in yet.
+	pub(crate) fn start_subscriptions(&self) {
+		if services().globals.config.policy_rooms.is_empty() {
+			return;
+		}
+
+		tokio::spawn(async move {
+			let server_user = UserId::parse(format!("@conduit:{}", services().globals.server_name()))
+				.expect("server's username is valid");
+
+			for room_id in &services().globals.config.policy_rooms {
+				if let Ok(true) = services()
+					.rooms
+					.state_cache
+					.is_joined(&server_user, room_id)
+				{
+					continue;
+				}
+
+				let servers: Vec<_> = room_id
+					.server_name()
+					.map(ToOwned::to_owned)
+					.into_iter()
+					.collect();
+				match join_room_by_id_helper(
+					Some(&server_user),
+					room_id,
+					Some("Subscribing to policy list".to_owned()),
+					&servers,
+					None,
+				)
+				.await
+				{
+					Ok(_) => info!("Subscribed to policy room {room_id}"),
+					Err(e) => error!("Failed to join policy room {room_id}: {e}"),
+				}
+			}
+
+			services().rooms.policy.invalidate();
+		});
+	}
+
+	pub(crate) fn is_policy_room(&self, room_id: &RoomId) -> bool {
+		services()
+			.globals
+			.config
+			.policy_rooms
+			.iter()
+			.any(|policy_room| policy_room == room_id)
+	}
+
+	/// Whether the state event is a policy rule which should update our rules
+	pub(crate) fn is_policy_event(&self, room_id: &RoomId, kind: &str) -> bool {
+		PolicyKind::from_event_type(kind).is_some() && self.is_policy_room(room_id)
+	}
+
+	/// Drops the cached rules, they are reloaded from the policy rooms on the
+	/// next check
+	pub(crate) fn invalidate(&self) {
+		debug!("Policy rules changed");
+		*self.rules.write().expect("locked") = None;
+	}
+
+	/// All ban rules of the configured policy rooms
+	pub(crate) async fn rules(&self) -> Result<Arc<[PolicyRule]>> {
+		let cached = self.rules.read().expect("locked").clone();
+		if let Some(rules) = cached {
+			return Ok(rules);
+		}
+
+		let mut rules = Vec::new();
+		for room_id in &services().globals.config.policy_rooms {
+			for ((kind, _), pdu) in services()
+				.rooms
+				.state_accessor
+				.room_state_full(room_id)
+				.await?
+			{
+				let Some(kind) = PolicyKind::from_event_type(&kind.to_string()) else {
+					continue;
+				};
+
+				// Removed rules are replaced with an empty event
+				let Ok(content) = serde_json::from_str::<PolicyRuleContent>(pdu.content.get()) else {
+					continue;
+				};
+
+				if BAN_RECOMMENDATIONS.contains(&content.recommendation.as_str()) {
+					rules.push(PolicyRule {
+						kind,
+						entity: content.entity,
+						reason: content.reason,
+						policy_room: room_id.clone(),
+					});
+				}
+			}
+		}
+
+		debug!("Loaded {} policy rules", rules.len());
+		let rules: Arc<[PolicyRule]> = rules.into();
+		*self.rules.write().expect("locked") = Some(Arc::clone(&rules));
+
+		Ok(rules)
+	}
+
+	async fn matching_rule(&self, kind: PolicyKind, value: &str) -> Result<Option<PolicyRule>> {
+		Ok(self
+			.rules()
+			.await?
+			.iter()
+			.find(|rule| rule.kind == kind && utils::glob_matches(&rule.entity, value))
+			.cloned())
+	}
+
+	/// Returns the rule banning the server, if any. Our own server is never
+	/// considered banned.
+	pub(crate) async fn server_rule(&self, server_name: &ServerName) -> Result<Option<PolicyRule>> {
+		if server_is_ours(server_name) {
+			return Ok(None);
+		}
+
+		self.matching_rule(PolicyKind::Server, server_name.as_str())
+			.await
+	}
+
+	/// Returns the rule banning the user or their server, if any
+	pub(crate) async fn user_rule(&self, user_id: &UserId) -> Result<Option<PolicyRule>> {
+		if let Some(rule) = self
+			.matching_rule(PolicyKind::User, user_id.as_str())
+			.await?
+		{
+			return Ok(Some(rule));
+		}
+
+		self.server_rule(user_id.server_name()).await
+	}
+
+	pub(crate) async fn room_rule(&self, room_id: &RoomId) -> Result<Option<PolicyRule>> {
+		self.matching_rule(PolicyKind::Room, room_id.as_str()).await
+	}
+}
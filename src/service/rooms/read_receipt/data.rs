@@ -10,7 +10,7 @@ type AnySyncEphemeralRoomEventIter<'a> =
 	Box<dyn Iterator<Item = Result<(OwnedUserId, u64, Raw<AnySyncEphemeralRoomEvent>)>> + 'a>;
 
 pub(crate) trait Data: Send + Sync {
-	/// Replaces the previous read receipt.
+	/// Replaces the previous read receipt of the user in the same thread.
 	fn readreceipt_update(&self, user_id: &UserId, room_id: &RoomId, event: ReceiptEvent) -> Result<()>;
 
 	/// Returns an iterator over the most recent read_receipts in a room that
@@ -21,9 +21,6 @@ pub(crate) trait Data: Send + Sync {
 	fn private_read_set(&self, room_id: &RoomId, user_id: &UserId, count: u64) -> Result<()>;
 
 	/// Returns the private read marker.
-	///
-	/// TODO: use this?
-	#[allow(dead_code)]
 	fn private_read_get(&self, room_id: &RoomId, user_id: &UserId) -> Result<Option<u64>>;
 
 	/// Returns the count of the last typing update in this room.
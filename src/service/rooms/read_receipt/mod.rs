@@ -1,19 +1,33 @@
 mod data;
 
 pub(crate) use data::Data;
-use ruma::{events::receipt::ReceiptEvent, serde::Raw, OwnedUserId, RoomId, UserId};
+use ruma::{
+	events::{receipt::ReceiptEvent, AnySyncEphemeralRoomEvent},
+	serde::Raw,
+	OwnedUserId, RoomId, UserId,
+};
 
-use crate::{services, Result};
+use crate::{
+	service::{rooms::timeline::PduCount, sending::AppserviceEdu},
+	services, Result,
+};
 
 pub(crate) struct Service {
 	pub(crate) db: &'static dyn Data,
 }
 
 impl Service {
-	/// Replaces the previous read receipt.
+	/// Replaces the previous read receipt of the user in the same thread.
 	pub(crate) fn readreceipt_update(&self, user_id: &UserId, room_id: &RoomId, event: ReceiptEvent) -> Result<()> {
+		let serialized = serde_json::to_value(&event).expect("receipt event can be serialized");
 		self.db.readreceipt_update(user_id, room_id, event)?;
 		services().sending.flush_room(room_id)?;
+		services()
+			.sending
+			.send_edu_appservices(&AppserviceEdu::Room {
+				room_id: room_id.to_owned(),
+				event: serialized,
+			})?;
 
 		Ok(())
 	}
@@ -39,6 +53,30 @@ impl Service {
 		self.db.private_read_get(room_id, user_id)
 	}
 
+	/// The PDU count of the latest event the user has read in the room, from
+	/// their private read marker or any of their read receipts
+	pub(crate) fn last_read_count(&self, room_id: &RoomId, user_id: &UserId) -> Result<u64> {
+		let mut last_read = self.private_read_get(room_id, user_id)?.unwrap_or(0);
+
+		for receipt in self.readreceipts_since(room_id, 0) {
+			let (receipt_user, _, event) = receipt?;
+			if receipt_user != user_id {
+				continue;
+			}
+
+			let Ok(AnySyncEphemeralRoomEvent::Receipt(event)) = event.deserialize() else {
+				continue;
+			};
+			for event_id in event.content.0.keys() {
+				if let Some(PduCount::Normal(count)) = services().rooms.timeline.get_pdu_count(event_id)? {
+					last_read = last_read.max(count);
+				}
+			}
+		}
+
+		Ok(last_read)
+	}
+
 	/// Returns the count of the last typing update in this room.
 	pub(crate) fn last_privateread_update(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
 		self.db.last_privateread_update(user_id, room_id)
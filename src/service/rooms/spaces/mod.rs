@@ -388,12 +388,13 @@ impl Service {
 			.as_ref()
 		{
 			return Ok(if let Some(cached) = cached {
-				if is_accessable_child(
-					current_room,
-					&cached.summary.join_rule,
-					&identifier,
-					&cached.summary.allowed_room_ids,
-				)? {
+				if cached.summary.world_readable
+					|| is_accessable_child(
+						current_room,
+						&cached.summary.join_rule,
+						&identifier,
+						&cached.summary.allowed_room_ids,
+					)? {
 					Some(SummaryAccessibility::Accessible(Box::new(cached.summary.clone())))
 				} else {
 					Some(SummaryAccessibility::Inaccessible)
@@ -425,7 +426,7 @@ impl Service {
 	}
 
 	async fn get_summary_and_children_federation(
-		&self, current_room: &OwnedRoomId, suggested_only: bool, user_id: &UserId, via: &[OwnedServerName],
+		&self, current_room: &OwnedRoomId, suggested_only: bool, identifier: &Identifier<'_>, via: &[OwnedServerName],
 	) -> Result<Option<SummaryAccessibility>> {
 		debug_info!("servers via for federation hierarchy: {via:?}");
 
@@ -492,12 +493,13 @@ impl Service {
 						);
 					}
 				}
-				if is_accessable_child(
-					current_room,
-					&response.room.join_rule,
-					&Identifier::UserId(user_id),
-					&response.room.allowed_room_ids,
-				)? {
+				if response.room.world_readable
+					|| is_accessable_child(
+						current_room,
+						&response.room.join_rule,
+						identifier,
+						&response.room.allowed_room_ids,
+					)? {
 					return Ok(Some(SummaryAccessibility::Accessible(Box::new(summary.clone()))));
 				}
 
@@ -521,11 +523,45 @@ impl Service {
 		{
 			Ok(Some(response))
 		} else {
-			self.get_summary_and_children_federation(current_room, suggested_only, user_id, via)
+			self.get_summary_and_children_federation(current_room, suggested_only, &Identifier::UserId(user_id), via)
 				.await
 		}
 	}
 
+	/// Gets the summary of a room for previews (MSC3266), from the room's state
+	/// if we know it and over federation through `via` otherwise. Returns
+	/// `None` if the room was not found or the user may not see it.
+	pub(crate) async fn get_room_preview(
+		&self, room_id: &OwnedRoomId, user_id: Option<&UserId>, via: &[OwnedServerName],
+	) -> Result<Option<SpaceHierarchyParentSummary>> {
+		let identifier = user_id.map_or(Identifier::None, Identifier::UserId);
+
+		let summary = if services()
+			.rooms
+			.state
+			.get_room_shortstatehash(room_id)?
+			.is_some()
+		{
+			// Built from the current state, as the cached summaries of the space
+			// hierarchy are not updated when e.g. the join rules change
+			get_stripped_space_child_events(room_id)
+				.await?
+				.and_then(|children| Self::get_room_summary(room_id, children, &identifier).ok())
+				.map(|summary| SummaryAccessibility::Accessible(Box::new(summary)))
+		} else if user_id.is_some() {
+			// Only users of this server can make us ask other servers
+			self.get_summary_and_children_federation(room_id, false, &identifier, via)
+				.await?
+		} else {
+			None
+		};
+
+		Ok(match summary {
+			Some(SummaryAccessibility::Accessible(summary)) => Some(*summary),
+			_ => None,
+		})
+	}
+
 	fn get_room_summary(
 		current_room: &OwnedRoomId, children_state: Vec<Raw<HierarchySpaceChildEvent>>, identifier: &Identifier<'_>,
 	) -> Result<SpaceHierarchyParentSummary, Error> {
@@ -548,7 +584,9 @@ impl Service {
 
 		let allowed_room_ids = allowed_room_ids(join_rule.clone());
 
-		if !is_accessable_child(current_room, &join_rule.clone().into(), identifier, &allowed_room_ids)? {
+		if !world_readable(room_id)?
+			&& !is_accessable_child(current_room, &join_rule.clone().into(), identifier, &allowed_room_ids)?
+		{
 			debug!("User is not allowed to see room {room_id}");
 			// This error will be caught later
 			return Err(Error::BadRequest(ErrorKind::forbidden(), "User is not allowed to see the room"));
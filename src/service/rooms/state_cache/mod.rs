@@ -202,6 +202,14 @@ impl Service {
 			_ => {},
 		}
 
+		services().user_directory.update_membership(
+			room_id,
+			user_id,
+			&membership,
+			membership_event.displayname,
+			membership_event.avatar_url,
+		)?;
+
 		if update_joined_count {
 			self.update_joined_count(room_id)?;
 		}
@@ -62,7 +62,10 @@ pub(crate) trait Data: Send + Sync {
 		&'a self, user_id: &UserId, room_id: &RoomId, from: PduCount,
 	) -> Result<Box<dyn Iterator<Item = Result<(PduCount, PduEvent)>> + 'a>>;
 
+	/// Increments the counts of the thread with the given root, or of the main
+	/// timeline if the event isn't in a thread
 	fn increment_notification_counts(
-		&self, room_id: &RoomId, notifies: Vec<OwnedUserId>, highlights: Vec<OwnedUserId>,
+		&self, room_id: &RoomId, thread_root: Option<&EventId>, notifies: Vec<OwnedUserId>,
+		highlights: Vec<OwnedUserId>,
 	) -> Result<()>;
 }
@@ -13,9 +13,11 @@ use ruma::{
 	canonical_json::to_canonical_value,
 	events::{
 		push_rules::PushRulesEvent,
+		receipt::ReceiptThread,
 		room::{
 			create::RoomCreateEventContent,
 			encrypted::Relation,
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
 			member::{MembershipState, RoomMemberEventContent},
 			power_levels::RoomPowerLevelsEventContent,
 			redaction::RoomRedactionEventContent,
@@ -39,6 +41,7 @@ use crate::{
 	service::{
 		self,
 		appservice::NamespaceRegex,
+		event_policy::{PolicyCheck, Verdict},
 		pdu::{EventHash, PduBuilder},
 	},
 	services,
@@ -301,6 +304,14 @@ impl Service {
 		);
 		let insert_lock = mutex_insert.lock().await;
 
+		// Events in a thread only count towards the unread counts of the thread
+		let thread_root = serde_json::from_str::<ExtractRelatesTo>(pdu.content.get())
+			.ok()
+			.and_then(|content| match content.relates_to {
+				Relation::Thread(thread) => Some(thread.event_id),
+				_ => None,
+			});
+
 		let count1 = services().globals.next_count()?;
 		// Mark as read first so the sending client doesn't get a notification even if
 		// appending fails
@@ -308,10 +319,13 @@ impl Service {
 			.rooms
 			.read_receipt
 			.private_read_set(&pdu.room_id, &pdu.sender, count1)?;
-		services()
-			.rooms
-			.user
-			.reset_notification_counts(&pdu.sender, &pdu.room_id)?;
+		services().rooms.user.reset_notification_counts(
+			&pdu.sender,
+			&pdu.room_id,
+			&thread_root
+				.clone()
+				.map_or(ReceiptThread::Unthreaded, ReceiptThread::Thread),
+		)?;
 
 		let count2 = services().globals.next_count()?;
 		let mut pdu_id = shortroomid.to_be_bytes().to_vec();
@@ -322,6 +336,20 @@ impl Service {
 
 		drop(insert_lock);
 
+		services()
+			.rooms
+			.auth_chain
+			.index_event(&pdu.room_id, &pdu.event_id)?;
+
+		if pdu.state_key.is_some()
+			&& services()
+				.rooms
+				.policy
+				.is_policy_event(&pdu.room_id, &pdu.kind.to_string())
+		{
+			services().rooms.policy.invalidate();
+		}
+
 		// See if the event matches any known pushers
 		let power_levels: RoomPowerLevelsEventContent = services()
 			.rooms
@@ -377,11 +405,11 @@ impl Service {
 			let mut highlight = false;
 			let mut notify = false;
 
-			for action in
+			let actions =
 				services()
 					.pusher
-					.get_actions(user, &rules_for_user, &power_levels, &sync_pdu, &pdu.room_id)?
-			{
+					.get_actions(user, &rules_for_user, &power_levels, &sync_pdu, &pdu.room_id)?;
+			for action in actions {
 				match action {
 					Action::Notify => notify = true,
 					Action::SetTweak(Tweak::Highlight(true)) => {
@@ -393,6 +421,9 @@ impl Service {
 
 			if notify {
 				notifies.push(user.clone());
+				services()
+					.pusher
+					.log_notification(user, pdu, count2, actions, highlight)?;
 			}
 
 			if highlight {
@@ -405,7 +436,7 @@ impl Service {
 		}
 
 		self.db
-			.increment_notification_counts(&pdu.room_id, notifies, highlights)?;
+			.increment_notification_counts(&pdu.room_id, thread_root.as_deref(), notifies, highlights)?;
 
 		match pdu.kind {
 			TimelineEventType::RoomRedaction => {
@@ -444,6 +475,16 @@ impl Service {
 					},
 				};
 			},
+			TimelineEventType::RoomJoinRules => {
+				if pdu.state_key.is_some() {
+					// The room state is only updated after the event was appended
+					let public = serde_json::from_str::<RoomJoinRulesEventContent>(pdu.content.get())
+						.map_or(false, |content| content.join_rule == JoinRule::Public);
+					services()
+						.user_directory
+						.update_room_visibility(&pdu.room_id, public)?;
+				}
+			},
 			TimelineEventType::SpaceChild => {
 				if let Some(_state_key) = &pdu.state_key {
 					services()
@@ -771,7 +812,7 @@ impl Service {
 
 		match ruma::signatures::hash_and_sign_event(
 			services().globals.server_name().as_str(),
-			services().globals.keypair(),
+			&*services().globals.keypair(),
 			&mut pdu_json,
 			&room_version_id,
 		) {
@@ -820,6 +861,24 @@ impl Service {
 	) -> Result<Arc<EventId>> {
 		let (pdu, pdu_json) = self.create_hash_and_sign_event(pdu_builder, sender, room_id, state_lock)?;
 
+		// suspended users (MSC3823) may only redact their events and leave rooms
+		if server_is_ours(sender.server_name()) && services().users.is_suspended(sender)? {
+			let allowed = match pdu.event_type() {
+				TimelineEventType::RoomRedaction => true,
+				TimelineEventType::RoomMember => {
+					pdu.state_key() == Some(sender.as_str())
+						&& serde_json::from_str::<RoomMemberEventContent>(pdu.content.get())
+							.map_err(|_| Error::bad_database("Invalid content in pdu."))?
+							.membership == MembershipState::Leave
+				},
+				_ => false,
+			};
+
+			if !allowed {
+				return Err(Error::user_suspended());
+			}
+		}
+
 		if let Some(admin_room) = service::admin::Service::get_admin_room().await? {
 			if admin_room == room_id {
 				match pdu.event_type() {
@@ -895,6 +954,38 @@ impl Service {
 			}
 		}
 
+		// The server user is exempt so the admin room keeps working if the policy
+		// hook is unavailable
+		if sender.as_str() != format!("@conduit:{}", services().globals.server_name()) {
+			let check = PolicyCheck::Event {
+				event: &pdu,
+				local: true,
+			};
+			match services().event_policy.check(&check).await? {
+				Verdict::Allow => {},
+				Verdict::SoftFail => {
+					// Only stored as an outlier, so it's in nobody's timeline, not even the
+					// sender's. The request still succeeds with the event ID, so the sender
+					// isn't told that the policy hid it.
+					info!("Event policy soft failed local event {}", pdu.event_id);
+					services()
+						.rooms
+						.outlier
+						.add_pdu_outlier(&pdu.event_id, &pdu_json)?;
+					return Ok(pdu.event_id);
+				},
+				Verdict::Deny {
+					reason,
+				} => {
+					info!("Event policy denied local event {}: {reason:?}", pdu.event_id);
+					return Err(Error::BadRequest(
+						ErrorKind::forbidden(),
+						"Rejected by this server's event policy.",
+					));
+				},
+			}
+		}
+
 		// We append to state before appending the pdu, so we don't have a moment in
 		// time with the pdu without it's state. This is okay because append_pdu can't
 		// fail.
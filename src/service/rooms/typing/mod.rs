@@ -2,13 +2,15 @@ use std::collections::BTreeMap;
 
 use ruma::{
 	api::federation::transactions::edu::{Edu, TypingContent},
-	events::SyncEphemeralRoomEvent,
+	events::{typing::TypingEventContent, EphemeralRoomEvent, SyncEphemeralRoomEvent},
 	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use tokio::sync::{broadcast, RwLock};
 
 use crate::{
-	debug_info, services,
+	debug_info,
+	service::sending::AppserviceEdu,
+	services,
 	utils::{self, user_id::user_is_local},
 	Result,
 };
@@ -39,6 +41,9 @@ impl Service {
 			.insert(room_id.to_owned(), services().globals.next_count()?);
 		_ = self.typing_update_sender.send(room_id.to_owned());
 
+		// update appservices
+		self.appservice_send(room_id, self.typings_all(room_id).await?.content)?;
+
 		// update federation
 		if user_is_local(user_id) {
 			self.federation_send(room_id, user_id, true)?;
@@ -63,6 +68,9 @@ impl Service {
 			.insert(room_id.to_owned(), services().globals.next_count()?);
 		_ = self.typing_update_sender.send(room_id.to_owned());
 
+		// update appservices
+		self.appservice_send(room_id, self.typings_all(room_id).await?.content)?;
+
 		// update federation
 		if user_is_local(user_id) {
 			self.federation_send(room_id, user_id, false)?;
@@ -116,6 +124,14 @@ impl Service {
 				.insert(room_id.to_owned(), services().globals.next_count()?);
 			_ = self.typing_update_sender.send(room_id.to_owned());
 
+			// update appservices
+			self.appservice_send(
+				room_id,
+				TypingEventContent {
+					user_ids: room.keys().cloned().collect(),
+				},
+			)?;
+
 			// update federation
 			for user in removable {
 				if user_is_local(&user) {
@@ -156,6 +172,20 @@ impl Service {
 		})
 	}
 
+	fn appservice_send(&self, room_id: &RoomId, content: TypingEventContent) -> Result<()> {
+		let event = EphemeralRoomEvent {
+			content,
+			room_id: room_id.to_owned(),
+		};
+
+		services()
+			.sending
+			.send_edu_appservices(&AppserviceEdu::Room {
+				room_id: room_id.to_owned(),
+				event: serde_json::to_value(event).expect("typing event can be serialized"),
+			})
+	}
+
 	fn federation_send(&self, room_id: &RoomId, user_id: &UserId, typing: bool) -> Result<()> {
 		debug_assert!(user_is_local(user_id), "tried to broadcast typing status of remote user",);
 		if !services().globals.config.allow_outgoing_typing {
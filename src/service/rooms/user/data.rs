@@ -1,14 +1,26 @@
-use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use ruma::{EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
 
 use crate::Result;
 
 pub(crate) trait Data: Send + Sync {
+	/// Resets the counts of the main timeline and of all threads
 	fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<()>;
 
+	fn reset_main_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<()>;
+
+	fn reset_thread_notification_counts(&self, user_id: &UserId, room_id: &RoomId, thread_root: &EventId)
+		-> Result<()>;
+
+	/// Notifications in the main timeline, i.e. outside of threads
 	fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64>;
 
+	/// Highlights in the main timeline, i.e. outside of threads
 	fn highlight_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64>;
 
+	/// Notification and highlight counts of every thread with unread
+	/// notifications
+	fn thread_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<Vec<(OwnedEventId, u64, u64)>>;
+
 	// Returns the count at which the last reset_notification_counts was called
 	fn last_notification_read(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64>;
 
@@ -1,7 +1,12 @@
 mod data;
 
+use std::collections::BTreeMap;
+
 pub(crate) use data::Data;
-use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use ruma::{
+	api::client::sync::sync_events::UnreadNotificationsCount, events::receipt::ReceiptThread, OwnedEventId,
+	OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
+};
 
 use crate::Result;
 
@@ -10,16 +15,91 @@ pub(crate) struct Service {
 }
 
 impl Service {
-	pub(crate) fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
-		self.db.reset_notification_counts(user_id, room_id)
+	/// Resets the counts the receipt covers: an unthreaded receipt covers the
+	/// whole room, other receipts only the main timeline or one thread
+	pub(crate) fn reset_notification_counts(
+		&self, user_id: &UserId, room_id: &RoomId, thread: &ReceiptThread,
+	) -> Result<()> {
+		match thread {
+			ReceiptThread::Main => self.db.reset_main_notification_counts(user_id, room_id),
+			ReceiptThread::Thread(thread_root) => {
+				self.db
+					.reset_thread_notification_counts(user_id, room_id, thread_root)
+			},
+			_ => self.db.reset_notification_counts(user_id, room_id),
+		}
 	}
 
+	/// Notifications in the whole room, including threads
 	pub(crate) fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
-		self.db.notification_count(user_id, room_id)
+		let threads: u64 = self
+			.db
+			.thread_notification_counts(user_id, room_id)?
+			.iter()
+			.map(|(_, notifications, _)| notifications)
+			.sum();
+
+		Ok(self
+			.db
+			.notification_count(user_id, room_id)?
+			.saturating_add(threads))
 	}
 
+	/// Highlights in the whole room, including threads
 	pub(crate) fn highlight_count(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
-		self.db.highlight_count(user_id, room_id)
+		let threads: u64 = self
+			.db
+			.thread_notification_counts(user_id, room_id)?
+			.iter()
+			.map(|(_, _, highlights)| highlights)
+			.sum();
+
+		Ok(self
+			.db
+			.highlight_count(user_id, room_id)?
+			.saturating_add(threads))
+	}
+
+	/// The unread counts for `/sync`. If `threaded` is set the room counts only
+	/// cover the main timeline and the threads are counted separately,
+	/// otherwise the room counts include the threads.
+	pub(crate) fn unread_notifications(
+		&self, user_id: &UserId, room_id: &RoomId, threaded: bool,
+	) -> Result<(UnreadNotificationsCount, BTreeMap<OwnedEventId, UnreadNotificationsCount>)> {
+		let count = |n: u64| -> Option<UInt> { Some(n.try_into().expect("notification count can't go that high")) };
+
+		if !threaded {
+			return Ok((
+				UnreadNotificationsCount {
+					highlight_count: count(self.highlight_count(user_id, room_id)?),
+					notification_count: count(self.notification_count(user_id, room_id)?),
+				},
+				BTreeMap::new(),
+			));
+		}
+
+		let threads = self
+			.db
+			.thread_notification_counts(user_id, room_id)?
+			.into_iter()
+			.map(|(thread_root, notifications, highlights)| {
+				(
+					thread_root,
+					UnreadNotificationsCount {
+						highlight_count: count(highlights),
+						notification_count: count(notifications),
+					},
+				)
+			})
+			.collect();
+
+		Ok((
+			UnreadNotificationsCount {
+				highlight_count: count(self.db.highlight_count(user_id, room_id)?),
+				notification_count: count(self.db.notification_count(user_id, room_id)?),
+			},
+			threads,
+		))
 	}
 
 	pub(crate) fn last_notification_read(&self, user_id: &UserId, room_id: &RoomId) -> Result<u64> {
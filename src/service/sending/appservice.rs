@@ -81,7 +81,7 @@ where
 		);
 		debug_error!("Appservice response bytes: {:?}", utils::string_from_bytes(&body));
 
-		return Err(Error::BadServerResponse("Appservice returned unsuccessful HTTP response"));
+		return Err(Error::AppserviceResponse(status, String::from_utf8_lossy(&body).into_owned()));
 	}
 
 	let response = T::IncomingResponse::try_from_http_response(
@@ -95,3 +95,58 @@ where
 		Error::BadServerResponse("Appservice returned bad/invalid response")
 	})
 }
+
+/// `PUT /_matrix/app/v1/transactions/{txnId}` with the unstable fields of
+/// MSC2409 and MSC3202, which ruma's `push_events` doesn't have
+pub(crate) mod push_events {
+	use std::collections::BTreeMap;
+
+	use ruma::{
+		api::{client::sync::sync_events::DeviceLists, request, response, Metadata},
+		events::AnyTimelineEvent,
+		metadata,
+		serde::Raw,
+		DeviceKeyAlgorithm, OwnedDeviceId, OwnedTransactionId, OwnedUserId, UInt,
+	};
+
+	const METADATA: Metadata = metadata! {
+		method: PUT,
+		rate_limited: false,
+		authentication: AccessToken,
+		history: {
+			1.0 => "/_matrix/app/v1/transactions/:txn_id",
+		}
+	};
+
+	#[request]
+	pub struct Request {
+		#[ruma_api(path)]
+		pub txn_id: OwnedTransactionId,
+
+		pub events: Vec<Raw<AnyTimelineEvent>>,
+
+		/// Typing, receipt and presence events (MSC2409)
+		#[serde(rename = "de.sorunome.msc2409.ephemeral", default, skip_serializing_if = "Vec::is_empty")]
+		pub ephemeral: Vec<serde_json::Value>,
+
+		/// To-device messages for users of the appservice (MSC2409)
+		#[serde(rename = "de.sorunome.msc2409.to_device", default, skip_serializing_if = "Vec::is_empty")]
+		pub to_device: Vec<serde_json::Value>,
+
+		/// Users whose devices changed (MSC3202)
+		#[serde(rename = "org.matrix.msc3202.device_lists", default, skip_serializing_if = "DeviceLists::is_empty")]
+		pub device_lists: DeviceLists,
+
+		/// Unclaimed one-time keys of devices of the appservice (MSC3202)
+		#[serde(
+			rename = "org.matrix.msc3202.device_one_time_keys_count",
+			default,
+			skip_serializing_if = "BTreeMap::is_empty"
+		)]
+		pub device_one_time_keys_count:
+			BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, BTreeMap<DeviceKeyAlgorithm, UInt>>>,
+	}
+
+	#[response]
+	pub struct Response {}
+}
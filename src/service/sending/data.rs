@@ -1,6 +1,6 @@
-use ruma::ServerName;
+use ruma::{OwnedServerName, ServerName};
 
-use super::{Destination, SendingEvent};
+use super::{Destination, DestinationHealth, SendingEvent};
 use crate::Result;
 
 type OutgoingSendingIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Destination, SendingEvent)>> + 'a>;
@@ -22,4 +22,13 @@ pub(crate) trait Data: Send + Sync {
 	fn mark_as_active(&self, events: &[(SendingEvent, Vec<u8>)]) -> Result<()>;
 	fn set_latest_educount(&self, server_name: &ServerName, educount: u64) -> Result<()>;
 	fn get_latest_educount(&self, server_name: &ServerName) -> Result<u64>;
+	fn destination_health(&self, server_name: &ServerName) -> Result<Option<DestinationHealth>>;
+	fn set_destination_health(&self, server_name: &ServerName, health: &DestinationHealth) -> Result<()>;
+	fn remove_destination_health(&self, server_name: &ServerName) -> Result<()>;
+	fn all_destination_health<'a>(
+		&'a self,
+	) -> Box<dyn Iterator<Item = Result<(OwnedServerName, DestinationHealth)>> + 'a>;
+	fn appservice_health(&self, appservice_id: &str) -> Result<Option<DestinationHealth>>;
+	fn set_appservice_health(&self, appservice_id: &str, health: &DestinationHealth) -> Result<()>;
+	fn remove_appservice_health(&self, appservice_id: &str) -> Result<()>;
 }
@@ -0,0 +1,151 @@
+use std::{cmp, time::Duration};
+
+use ruma::{OwnedServerName, ServerName};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use super::{Destination, Service};
+use crate::{services, utils, Error, Result};
+
+/// What we know about the reachability of a federation destination or an
+/// appservice. This is persisted so that a restart does not retry every dead
+/// server at once.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct DestinationHealth {
+	/// Consecutive failed transactions since the last success
+	pub(crate) failures: u32,
+	pub(crate) last_success: Option<u64>,
+	pub(crate) last_failure: Option<u64>,
+	/// We don't send anything before this time unless the backoff is reset.
+	/// Appservices are only backed off in memory, so this is never set for
+	/// them.
+	pub(crate) next_retry: Option<u64>,
+	pub(crate) last_error: Option<String>,
+}
+
+impl DestinationHealth {
+	pub(crate) fn is_backed_off(&self, now: u64) -> bool { self.next_retry.is_some_and(|next_retry| next_retry > now) }
+}
+
+/// Exponential backoff for the given number of consecutive failures, bounded
+/// by `sender_retry_backoff_limit`.
+pub(crate) fn backoff_duration(tries: u32) -> Duration {
+	let max_duration = Duration::from_secs(services().globals.config.sender_retry_backoff_limit);
+	let min_duration = Duration::from_secs(services().globals.config.sender_timeout);
+	cmp::min(min_duration.saturating_mul(tries.saturating_mul(tries)), max_duration)
+}
+
+impl Service {
+	pub(crate) fn destination_health(&self, server_name: &ServerName) -> Result<Option<DestinationHealth>> {
+		self.db.destination_health(server_name)
+	}
+
+	/// All destinations we have health information about
+	pub(crate) fn destinations<'a>(
+		&'a self,
+	) -> impl Iterator<Item = Result<(OwnedServerName, DestinationHealth)>> + 'a {
+		self.db.all_destination_health()
+	}
+
+	/// Whether requests to the destination should not be attempted right now
+	pub(crate) fn is_backed_off(&self, server_name: &ServerName) -> Result<bool> {
+		Ok(self
+			.destination_health(server_name)?
+			.is_some_and(|health| health.is_backed_off(utils::millis_since_unix_epoch())))
+	}
+
+	pub(crate) fn record_success(&self, server_name: &ServerName) -> Result<()> {
+		let mut health = self.destination_health(server_name)?.unwrap_or_default();
+		if health.failures > 0 {
+			info!(
+				"Destination {server_name} is reachable again after {} failures",
+				health.failures
+			);
+		}
+
+		health.failures = 0;
+		health.next_retry = None;
+		health.last_success = Some(utils::millis_since_unix_epoch());
+		self.db.set_destination_health(server_name, &health)
+	}
+
+	pub(crate) fn record_failure(&self, server_name: &ServerName, error: &Error) -> Result<()> {
+		let now = utils::millis_since_unix_epoch();
+		let mut health = self.destination_health(server_name)?.unwrap_or_default();
+		health.failures = health.failures.saturating_add(1);
+		health.last_failure = Some(now);
+		health.last_error = Some(error.to_string());
+
+		let backoff = u64::try_from(backoff_duration(health.failures).as_millis()).unwrap_or(u64::MAX);
+		health.next_retry = Some(now.saturating_add(backoff));
+		debug!(
+			"Backing off from {server_name} for {}s after {} failures",
+			backoff / 1000,
+			health.failures
+		);
+
+		self.db.set_destination_health(server_name, &health)
+	}
+
+	/// Allows retrying a backed off destination right away, e.g. because it
+	/// just sent us a request so it is evidently up. Queued transactions for it
+	/// are flushed. Returns whether the destination was backed off.
+	pub(crate) fn reset_backoff(&self, server_name: &ServerName) -> Result<bool> {
+		let Some(mut health) = self.destination_health(server_name)? else {
+			return Ok(false);
+		};
+
+		if !health.is_backed_off(utils::millis_since_unix_epoch()) {
+			return Ok(false);
+		}
+
+		debug!("Resetting backoff for {server_name}");
+		health.next_retry = None;
+		self.db.set_destination_health(server_name, &health)?;
+		self.flush_servers(std::iter::once(server_name.to_owned()))?;
+
+		Ok(true)
+	}
+
+	/// Forgets all health information about the destination, resetting its
+	/// failure count as well.
+	pub(crate) fn forget_destination(&self, server_name: &ServerName) -> Result<()> {
+		self.db.remove_destination_health(server_name)?;
+		self.flush_servers(std::iter::once(server_name.to_owned()))
+	}
+
+	pub(crate) fn appservice_health(&self, appservice_id: &str) -> Result<Option<DestinationHealth>> {
+		self.db.appservice_health(appservice_id)
+	}
+
+	pub(crate) fn record_appservice_success(&self, appservice_id: &str) -> Result<()> {
+		let mut health = self.appservice_health(appservice_id)?.unwrap_or_default();
+		if health.failures > 0 {
+			info!(
+				"Appservice {appservice_id} is reachable again after {} failures",
+				health.failures
+			);
+		}
+
+		health.failures = 0;
+		health.last_success = Some(utils::millis_since_unix_epoch());
+		self.db.set_appservice_health(appservice_id, &health)
+	}
+
+	pub(crate) fn record_appservice_failure(&self, appservice_id: &str, error: &Error) -> Result<()> {
+		let mut health = self.appservice_health(appservice_id)?.unwrap_or_default();
+		health.failures = health.failures.saturating_add(1);
+		health.last_failure = Some(utils::millis_since_unix_epoch());
+		health.last_error = Some(error.to_string());
+		self.db.set_appservice_health(appservice_id, &health)
+	}
+
+	/// Number of events waiting to be sent to the destination, including the
+	/// ones of the transaction in flight
+	pub(crate) fn pending_requests(&self, dest: &Destination) -> usize {
+		self.db
+			.active_requests_for(dest)
+			.count()
+			.saturating_add(self.db.queued_requests(dest).count())
+	}
+}
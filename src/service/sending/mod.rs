@@ -1,31 +1,47 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+	collections::{hash_map::DefaultHasher, HashSet},
+	fmt::Debug,
+	hash::{Hash, Hasher},
+	sync::Arc,
+};
 
 pub(crate) use data::Data;
 use ruma::{
 	api::{appservice::Registration, OutgoingRequest},
-	OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+	OwnedDeviceId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 };
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tracing::warn;
 
-use crate::{services, utils::server_name::server_is_ours, Config, Error, Result};
+use crate::{
+	service::appservice::RegistrationInfo, services, utils::server_name::server_is_ours, Config, Error, Result,
+};
 
 mod appservice;
 mod data;
+mod health;
 pub(crate) mod send;
 pub(crate) mod sender;
+pub(crate) use health::{backoff_duration, DestinationHealth};
 pub(crate) use send::FedDest;
 
 pub(crate) struct Service {
 	pub(crate) db: &'static dyn Data,
 
-	/// The state for a given state hash.
-	sender: loole::Sender<Msg>,
-	receiver: Mutex<loole::Receiver<Msg>>,
+	/// Every destination is handled by one of these, chosen by its hash
+	shards: Vec<Shard>,
+	max_inflight: usize,
 	startup_netburst: bool,
 	startup_netburst_keep: i64,
 }
 
+/// The queue of one sender worker
+struct Shard {
+	sender: loole::Sender<Msg>,
+	receiver: Mutex<loole::Receiver<Msg>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Msg {
 	dest: Destination,
@@ -48,13 +64,118 @@ pub(crate) enum SendingEvent {
 	Flush,        // none
 }
 
+/// Ephemeral data queued for appservices as `SendingEvent::Edu`, only for the
+/// appservices interested in it
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum AppserviceEdu {
+	/// Typing or receipt event of a room
+	Room {
+		room_id: OwnedRoomId,
+		event: serde_json::Value,
+	},
+	/// Presence event of a user
+	Presence {
+		user_id: OwnedUserId,
+		event: serde_json::Value,
+	},
+	/// To-device message for a local device
+	ToDevice {
+		user_id: OwnedUserId,
+		device_id: OwnedDeviceId,
+		event: serde_json::Value,
+	},
+	/// The device list of the user changed (MSC3202)
+	DeviceListUpdate {
+		user_id: OwnedUserId,
+	},
+	/// A one-time key of a local device was claimed (MSC3202)
+	OneTimeKeysCount {
+		user_id: OwnedUserId,
+		device_id: OwnedDeviceId,
+	},
+}
+
+impl AppserviceEdu {
+	/// Whether the appservice asks for this kind of EDU in its registration
+	/// options and is interested in the room or user of it
+	fn is_wanted_by(&self, info: &RegistrationInfo) -> Result<bool> {
+		Ok(match self {
+			Self::Room {
+				room_id,
+				..
+			} => {
+				info.extensions.receive_ephemeral
+					&& (info.rooms.is_match(room_id.as_str())
+						|| services()
+							.rooms
+							.state_cache
+							.appservice_in_room(room_id, info)?)
+			},
+			Self::Presence {
+				user_id,
+				..
+			} => {
+				info.extensions.receive_ephemeral
+					&& appservice_sees_user(info, user_id)?
+					&& services().presence.visible_to_appservice(user_id, info)?
+			},
+			Self::ToDevice {
+				user_id,
+				..
+			} => info.extensions.receive_ephemeral && info.is_user_match(user_id),
+			Self::DeviceListUpdate {
+				user_id,
+			} => info.extensions.msc3202 && appservice_sees_user(info, user_id)?,
+			Self::OneTimeKeysCount {
+				user_id,
+				..
+			} => info.extensions.msc3202 && info.is_user_match(user_id),
+		})
+	}
+}
+
+/// Whether the user is in the namespaces of the appservice or shares a room
+/// with it
+fn appservice_sees_user(info: &RegistrationInfo, user_id: &UserId) -> Result<bool> {
+	if info.is_user_match(user_id) {
+		return Ok(true);
+	}
+
+	for room_id in services()
+		.rooms
+		.state_cache
+		.rooms_joined(user_id)
+		.filter_map(Result::ok)
+	{
+		if services()
+			.rooms
+			.state_cache
+			.appservice_in_room(&room_id, info)?
+		{
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
 impl Service {
 	pub(crate) fn build(db: &'static dyn Data, config: &Config) -> Arc<Self> {
-		let (sender, receiver) = loole::unbounded();
+		let shards = (0..config.sender_shards)
+			.map(|_| {
+				let (sender, receiver) = loole::unbounded();
+				Shard {
+					sender,
+					receiver: Mutex::new(receiver),
+				}
+			})
+			.collect();
+
 		Arc::new(Self {
 			db,
-			sender,
-			receiver: Mutex::new(receiver),
+			shards,
+			max_inflight: config.sender_shard_max_inflight,
 			startup_netburst: config.startup_netburst,
 			startup_netburst_keep: config.startup_netburst_keep,
 		})
@@ -88,12 +209,7 @@ impl Service {
 
 	#[tracing::instrument(skip(self, room_id, pdu_id))]
 	pub(crate) fn send_pdu_room(&self, room_id: &RoomId, pdu_id: &[u8]) -> Result<()> {
-		let servers = services()
-			.rooms
-			.state_cache
-			.room_servers(room_id)
-			.filter_map(Result::ok)
-			.filter(|server_name| !server_is_ours(server_name));
+		let servers = room_servers(room_id)?;
 
 		self.send_pdu_servers(servers, pdu_id)
 	}
@@ -139,12 +255,7 @@ impl Service {
 
 	#[tracing::instrument(skip(self, room_id, serialized))]
 	pub(crate) fn send_edu_room(&self, room_id: &RoomId, serialized: Vec<u8>) -> Result<()> {
-		let servers = services()
-			.rooms
-			.state_cache
-			.room_servers(room_id)
-			.filter_map(Result::ok)
-			.filter(|server_name| !server_is_ours(server_name));
+		let servers = room_servers(room_id)?;
 
 		self.send_edu_servers(servers, serialized)
 	}
@@ -176,14 +287,48 @@ impl Service {
 		Ok(())
 	}
 
+	/// Queues the EDU for every appservice that wants it
+	#[tracing::instrument(skip(self, edu))]
+	pub(crate) fn send_edu_appservices(&self, edu: &AppserviceEdu) -> Result<()> {
+		let mut ids = Vec::new();
+		for info in services().appservice.read_sync().values() {
+			if edu.is_wanted_by(info)? {
+				ids.push(info.registration.id.clone());
+			}
+		}
+
+		if ids.is_empty() {
+			return Ok(());
+		}
+
+		let serialized = serde_json::to_vec(edu).expect("AppserviceEdu can be serialized");
+		let requests = ids
+			.into_iter()
+			.map(|id| (Destination::Appservice(id), SendingEvent::Edu(serialized.clone())))
+			.collect::<Vec<_>>();
+
+		let _cork = services().globals.db.cork()?;
+		let keys = self.db.queue_requests(
+			&requests
+				.iter()
+				.map(|(o, e)| (o, e.clone()))
+				.collect::<Vec<_>>(),
+		)?;
+
+		for ((dest, event), queue_id) in requests.into_iter().zip(keys) {
+			self.dispatch(Msg {
+				dest,
+				event,
+				queue_id,
+			})?;
+		}
+
+		Ok(())
+	}
+
 	#[tracing::instrument(skip(self, room_id))]
 	pub(crate) fn flush_room(&self, room_id: &RoomId) -> Result<()> {
-		let servers = services()
-			.rooms
-			.state_cache
-			.room_servers(room_id)
-			.filter_map(Result::ok)
-			.filter(|server_name| !server_is_ours(server_name));
+		let servers = room_servers(room_id)?;
 
 		self.flush_servers(servers)
 	}
@@ -207,6 +352,13 @@ impl Service {
 	where
 		T: OutgoingRequest + Debug,
 	{
+		// transactions are retried by the sender, anything else fails right away
+		if self.is_backed_off(dest)? {
+			return Err(Error::BadServerResponse(
+				"Not sending request to destination that is backed off after repeated failures.",
+			));
+		}
+
 		let client = &services().globals.client.federation;
 		send::send(client, dest, request).await
 	}
@@ -228,6 +380,7 @@ impl Service {
 	/// Used for instance after we remove an appservice registration
 	#[tracing::instrument(skip(self))]
 	pub(crate) fn cleanup_events(&self, appservice_id: String) -> Result<()> {
+		self.db.remove_appservice_health(&appservice_id)?;
 		self.db
 			.delete_all_requests_for(&Destination::Appservice(appservice_id))?;
 
@@ -235,12 +388,38 @@ impl Service {
 	}
 
 	fn dispatch(&self, msg: Msg) -> Result<()> {
-		debug_assert!(!self.sender.is_full(), "channel full");
-		debug_assert!(!self.sender.is_closed(), "channel closed");
-		self.sender.send(msg).map_err(|e| Error::Err(e.to_string()))
+		let sender = &self.shards[self.shard_id(&msg.dest)].sender;
+		debug_assert!(!sender.is_full(), "channel full");
+		debug_assert!(!sender.is_closed(), "channel closed");
+		sender.send(msg).map_err(|e| Error::Err(e.to_string()))
+	}
+
+	/// The shard handling all requests to the destination
+	fn shard_id(&self, dest: &Destination) -> usize {
+		let mut hasher = DefaultHasher::new();
+		dest.hash(&mut hasher);
+		usize::try_from(hasher.finish() % self.shards.len() as u64).expect("shard id fits into usize")
 	}
 }
 
+/// The servers in the room besides us. While we only have partial state of the
+/// room, the servers the resident server told us about are included.
+fn room_servers(room_id: &RoomId) -> Result<impl Iterator<Item = OwnedServerName>> {
+	let mut servers = services()
+		.rooms
+		.state_cache
+		.room_servers(room_id)
+		.filter_map(Result::ok)
+		.filter(|server_name| !server_is_ours(server_name))
+		.collect::<HashSet<_>>();
+
+	if let Some(partial_state) = services().rooms.metadata.partial_state(room_id)? {
+		servers.extend(partial_state.servers);
+	}
+
+	Ok(servers.into_iter())
+}
+
 impl Destination {
 	#[tracing::instrument(skip(self))]
 	pub(crate) fn get_prefix(&self) -> Vec<u8> {
@@ -3,17 +3,20 @@ use std::{
 	collections::{BTreeMap, HashMap, HashSet},
 	fmt::Debug,
 	sync::Arc,
-	time::{Duration, Instant},
+	time::Instant,
 };
 
 use base64::{engine::general_purpose, Engine as _};
 use federation::transactions::send_transaction_message;
 use futures_util::{future::BoxFuture, stream::FuturesUnordered, StreamExt};
 use ruma::{
-	api::federation::{
-		self,
-		transactions::edu::{
-			DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent, ReceiptData, ReceiptMap,
+	api::{
+		client::{push::PusherKind, sync::sync_events::DeviceLists},
+		federation::{
+			self,
+			transactions::edu::{
+				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent, ReceiptData, ReceiptMap,
+			},
 		},
 	},
 	device_id,
@@ -22,11 +25,11 @@ use ruma::{
 };
 use tracing::{debug, error, warn};
 
-use super::{appservice, send, Destination, Msg, SendingEvent, Service};
+use super::{appservice, backoff_duration, send, AppserviceEdu, Destination, Msg, SendingEvent, Service};
 use crate::{
-	service::presence::Presence,
+	service::{presence::Presence, pusher::coalesce_notices},
 	services,
-	utils::{calculate_hash, user_id::user_is_local},
+	utils::{self, calculate_hash, user_id::user_is_local},
 	Error, PduEvent, Result,
 };
 
@@ -48,23 +51,26 @@ const SELECT_EDU_LIMIT: usize = 16;
 
 impl Service {
 	pub(crate) fn start_handler(self: &Arc<Self>) {
-		let self2 = Arc::clone(self);
-		tokio::spawn(async move {
-			self2.handler().await;
-		});
+		for shard_id in 0..self.shards.len() {
+			let self2 = Arc::clone(self);
+			tokio::spawn(async move {
+				self2.handler(shard_id).await;
+			});
+		}
 	}
 
-	#[tracing::instrument(skip_all, name = "sender")]
-	async fn handler(&self) {
-		let receiver = self.receiver.lock().await;
+	#[tracing::instrument(skip(self), name = "sender")]
+	async fn handler(&self, shard_id: usize) {
+		let receiver = self.shards[shard_id].receiver.lock().await;
 		debug_assert!(!receiver.is_closed(), "channel error");
 
 		let mut futures: SendingFutures<'_> = FuturesUnordered::new();
 		let mut statuses: CurTransactionStatus = CurTransactionStatus::new();
-		self.initial_transactions(&mut futures, &mut statuses);
+		self.initial_transactions(shard_id, &mut futures, &mut statuses);
 		loop {
 			tokio::select! {
-				Ok(request) = receiver.recv_async() => {
+				// requests wait in the queue while too many transactions are in flight
+				Ok(request) = receiver.recv_async(), if futures.len() < self.max_inflight => {
 					self.handle_request(request, &mut futures, &mut statuses);
 				},
 				Some(response) = futures.next() => {
@@ -87,6 +93,20 @@ impl Service {
 		&self, dest: Destination, _futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus, e: &Error,
 	) {
 		debug!(dest = ?dest, "{e:?}");
+		match &dest {
+			Destination::Normal(server_name) => {
+				if let Err(e) = self.record_failure(server_name, e) {
+					error!("Failed to record failure for {server_name}: {e}");
+				}
+			},
+			Destination::Appservice(id) => {
+				if let Err(e) = self.record_appservice_failure(id, e) {
+					error!("Failed to record failure for appservice {id}: {e}");
+				}
+			},
+			Destination::Push(..) => {},
+		}
+
 		statuses.entry(dest).and_modify(|e| {
 			*e = match e {
 				TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
@@ -100,6 +120,20 @@ impl Service {
 		&self, dest: &Destination, futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus,
 	) {
 		let _cork = services().globals.db.cork();
+		match dest {
+			Destination::Normal(server_name) => {
+				if let Err(e) = self.record_success(server_name) {
+					error!("Failed to record success for {server_name}: {e}");
+				}
+			},
+			Destination::Appservice(id) => {
+				if let Err(e) = self.record_appservice_success(id) {
+					error!("Failed to record success for appservice {id}: {e}");
+				}
+			},
+			Destination::Push(..) => {},
+		}
+
 		self.db
 			.delete_all_active_requests_for(dest)
 			.expect("all active requests deleted");
@@ -135,10 +169,16 @@ impl Service {
 		}
 	}
 
-	fn initial_transactions(&self, futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus) {
+	fn initial_transactions(
+		&self, shard_id: usize, futures: &mut SendingFutures<'_>, statuses: &mut CurTransactionStatus,
+	) {
 		let keep = usize::try_from(self.startup_netburst_keep).unwrap_or(usize::MAX);
 		let mut txns = HashMap::<Destination, Vec<SendingEvent>>::new();
 		for (key, dest, event) in self.db.active_requests().filter_map(Result::ok) {
+			if self.shard_id(&dest) != shard_id {
+				continue;
+			}
+
 			let entry = txns.entry(dest.clone()).or_default();
 			if self.startup_netburst_keep >= 0 && entry.len() >= keep {
 				warn!("Dropping unsent event {:?} {:?}", dest, String::from_utf8_lossy(&key));
@@ -151,6 +191,13 @@ impl Service {
 		}
 
 		for (dest, events) in txns {
+			// don't hammer servers that were already failing before the restart, their
+			// transactions are retried once the persisted backoff has passed
+			if let Some(failures) = self.persisted_failures(&dest) {
+				statuses.insert(dest, TransactionStatus::Failed(failures, Instant::now()));
+				continue;
+			}
+
 			if self.startup_netburst && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
 				futures.push(Box::pin(send_events(dest.clone(), events)));
@@ -182,7 +229,11 @@ impl Service {
 				.filter_map(Result::ok)
 				.for_each(|(_, e)| events.push(e));
 
-			return Ok(Some(events));
+			// the backoff may have been persisted before a restart without a transaction
+			// in flight, then the new events make up the retry
+			if !events.is_empty() {
+				return Ok(Some(events));
+			}
 		}
 
 		// Compose the next transaction
@@ -207,17 +258,27 @@ impl Service {
 
 	#[tracing::instrument(skip(self, dest, statuses))]
 	fn select_events_current(&self, dest: Destination, statuses: &mut CurTransactionStatus) -> Result<(bool, bool)> {
+		// The backoff of federation destinations is persisted, so it survives restarts
+		// and can be reset early when the server contacts us
+		let persisted_backoff = match &dest {
+			Destination::Normal(server_name) => Some(self.is_backed_off(server_name)?),
+			Destination::Appservice(_) | Destination::Push(..) => None,
+		};
+
+		if persisted_backoff == Some(true) && !statuses.contains_key(&dest) {
+			let failures = self.persisted_failures(&dest).unwrap_or(1);
+			statuses.insert(dest, TransactionStatus::Failed(failures, Instant::now()));
+			return Ok((false, false));
+		}
+
 		let (mut allow, mut retry) = (true, false);
 		statuses
 			.entry(dest)
 			.and_modify(|e| match e {
 				TransactionStatus::Failed(tries, time) => {
 					// Fail if a request has failed recently (exponential backoff)
-					let max_duration = Duration::from_secs(services().globals.config.sender_retry_backoff_limit);
-					let min_duration = Duration::from_secs(services().globals.config.sender_timeout);
-					let min_elapsed_duration = min_duration * (*tries) * (*tries);
-					let min_elapsed_duration = cmp::min(min_elapsed_duration, max_duration);
-					if time.elapsed() < min_elapsed_duration {
+					let backed_off = persisted_backoff.unwrap_or_else(|| time.elapsed() < backoff_duration(*tries));
+					if backed_off {
 						allow = false;
 					} else {
 						retry = true;
@@ -233,6 +294,20 @@ impl Service {
 		Ok((allow, retry))
 	}
 
+	/// Number of consecutive failures of a federation destination that is
+	/// currently backed off according to the database
+	fn persisted_failures(&self, dest: &Destination) -> Option<u32> {
+		let Destination::Normal(server_name) = dest else {
+			return None;
+		};
+
+		self.destination_health(server_name)
+			.ok()
+			.flatten()
+			.filter(|health| health.is_backed_off(utils::millis_since_unix_epoch()))
+			.map(|health| health.failures)
+	}
+
 	#[tracing::instrument(skip(self, server_name))]
 	fn select_edus(&self, server_name: &ServerName) -> Result<(Vec<Vec<u8>>, u64)> {
 		// u64: count of last edu
@@ -298,9 +373,8 @@ fn select_edus_presence(
 		}
 
 		if !services()
-			.rooms
-			.state_cache
-			.server_sees_user(server_name, &user_id)?
+			.presence
+			.server_gets_presence(server_name, &user_id)?
 		{
 			continue;
 		}
@@ -328,7 +402,8 @@ fn select_edus_presence(
 	Ok(true)
 }
 
-/// Look for read receipts in this room
+/// Look for read receipts in this room. Users have a receipt per thread, each
+/// is sent in its own EDU as a `ReceiptMap` only holds one receipt per user.
 #[tracing::instrument(skip(room_id, since, max_edu_count, events))]
 fn select_edus_receipts(
 	room_id: &RoomId, since: u64, max_edu_count: &mut u64, events: &mut Vec<Vec<u8>>,
@@ -406,7 +481,24 @@ async fn send_events(dest: Destination, events: Vec<SendingEvent>) -> SendingRes
 
 #[tracing::instrument(skip(dest, events))]
 async fn send_events_dest_appservice(dest: &Destination, id: &String, events: Vec<SendingEvent>) -> SendingResult {
+	let info = services()
+		.appservice
+		.read()
+		.await
+		.get(id)
+		.cloned()
+		.ok_or_else(|| {
+			(
+				dest.clone(),
+				Error::bad_database("[Appservice] Could not load registration from db."),
+			)
+		})?;
+
 	let mut pdu_jsons = Vec::new();
+	let mut ephemeral = Vec::new();
+	let mut to_device = Vec::new();
+	let mut device_lists = DeviceLists::new();
+	let mut one_time_keys = HashSet::new();
 
 	for event in &events {
 		match event {
@@ -426,27 +518,84 @@ async fn send_events_dest_appservice(dest: &Destination, id: &String, events: Ve
 						.to_room_event(),
 				);
 			},
-			SendingEvent::Edu(_) | SendingEvent::Flush => {
-				// Appservices don't need EDUs (?) and flush only;
-				// no new content
+			SendingEvent::Edu(edu) => {
+				let Ok(edu) = serde_json::from_slice::<AppserviceEdu>(edu) else {
+					warn!("[Appservice] Dropping invalid EDU queued for {id}");
+					continue;
+				};
+
+				// the interest of the appservice was checked when the EDU was queued
+				match edu {
+					AppserviceEdu::Room {
+						event,
+						..
+					}
+					| AppserviceEdu::Presence {
+						event,
+						..
+					} => ephemeral.push(event),
+					AppserviceEdu::ToDevice {
+						user_id,
+						device_id,
+						event,
+					} => {
+						to_device.push(event);
+						if info.extensions.msc3202 {
+							one_time_keys.insert((user_id, device_id));
+						}
+					},
+					AppserviceEdu::DeviceListUpdate {
+						user_id,
+					} => {
+						if !device_lists.changed.contains(&user_id) {
+							device_lists.changed.push(user_id);
+						}
+					},
+					AppserviceEdu::OneTimeKeysCount {
+						user_id,
+						device_id,
+					} => {
+						one_time_keys.insert((user_id, device_id));
+					},
+				}
+			},
+			SendingEvent::Flush => {
+				// flush only; no new content
 			},
 		}
 	}
 
-	debug_assert!(!pdu_jsons.is_empty(), "sending empty transaction");
+	// counts are looked up now so the appservice gets the latest ones
+	let mut device_one_time_keys_count = BTreeMap::<_, BTreeMap<_, _>>::new();
+	for (user_id, device_id) in one_time_keys {
+		let count = services()
+			.users
+			.count_one_time_keys(&user_id, &device_id)
+			.map_err(|e| (dest.clone(), e))?;
+		device_one_time_keys_count
+			.entry(user_id)
+			.or_default()
+			.insert(device_id, count);
+	}
+
+	// everything queued may have been filtered out
+	if pdu_jsons.is_empty()
+		&& ephemeral.is_empty()
+		&& to_device.is_empty()
+		&& device_lists.is_empty()
+		&& device_one_time_keys_count.is_empty()
+	{
+		return Ok(dest.clone());
+	}
+
 	match appservice::send_request(
-		services()
-			.appservice
-			.get_registration(id)
-			.await
-			.ok_or_else(|| {
-				(
-					dest.clone(),
-					Error::bad_database("[Appservice] Could not load registration from db."),
-				)
-			})?,
-		ruma::api::appservice::event::push_events::v1::Request {
+		info.registration,
+		appservice::push_events::Request {
 			events: pdu_jsons,
+			ephemeral,
+			to_device,
+			device_lists,
+			device_one_time_keys_count,
 			txn_id: (&*general_purpose::URL_SAFE_NO_PAD.encode(calculate_hash(
 				&events
 					.iter()
@@ -496,6 +645,23 @@ async fn send_events_dest_push(
 		}
 	}
 
+	let Some(pusher) = services()
+		.pusher
+		.get_pusher(userid, pushkey)
+		.map_err(|e| (dest.clone(), e))?
+	else {
+		// The pusher was removed while its events were queued
+		return Ok(dest.clone());
+	};
+
+	let rules_for_user = services()
+		.account_data
+		.get(None, userid, GlobalAccountDataEventType::PushRules.to_string().into())
+		.unwrap_or_default()
+		.and_then(|event| serde_json::from_str::<PushRulesEvent>(event.get()).ok())
+		.map_or_else(|| push::Ruleset::server_default(userid), |ev: PushRulesEvent| ev.content.global);
+
+	let mut notices = Vec::new();
 	for pdu in pdus {
 		// Redacted events are not notification targets (we don't send push for them)
 		if let Some(unsigned) = &pdu.unsigned {
@@ -506,21 +672,32 @@ async fn send_events_dest_push(
 			}
 		}
 
-		let Some(pusher) = services()
+		if let Some(tweaks) = services()
 			.pusher
-			.get_pusher(userid, pushkey)
+			.notice_tweaks(userid, &rules_for_user, &pdu)
 			.map_err(|e| (dest.clone(), e))?
-		else {
-			continue;
-		};
+		{
+			notices.push((pdu, tweaks));
+		}
+	}
 
-		let rules_for_user = services()
-			.account_data
-			.get(None, userid, GlobalAccountDataEventType::PushRules.to_string().into())
-			.unwrap_or_default()
-			.and_then(|event| serde_json::from_str::<PushRulesEvent>(event.get()).ok())
-			.map_or_else(|| push::Ruleset::server_default(userid), |ev: PushRulesEvent| ev.content.global);
+	// Only the newest notifying event of each room is pushed, carrying the tweaks
+	// of the older ones of that room so a highlight or sound is not lost
+	let notices = coalesce_notices(notices);
+	if notices.is_empty() {
+		return Ok(dest.clone());
+	}
+
+	if matches!(pusher.kind, PusherKind::Email(_)) {
+		services()
+			.pusher
+			.schedule_email_digest(userid, pushkey)
+			.map_err(|e| (dest.clone(), e))?;
 
+		return Ok(dest.clone());
+	}
+
+	for (pdu, tweaks) in notices {
 		let unread: UInt = services()
 			.rooms
 			.user
@@ -529,12 +706,12 @@ async fn send_events_dest_push(
 			.try_into()
 			.expect("notification count can't go that high");
 
-		let _response = services()
+		// A failure leaves the events queued, so they are retried with backoff
+		services()
 			.pusher
-			.send_push_notice(userid, unread, &pusher, rules_for_user, &pdu)
+			.send_notice(userid, unread, &pusher, tweaks, &pdu)
 			.await
-			.map(|_response| dest.clone())
-			.map_err(|e| (dest.clone(), e));
+			.map_err(|e| (dest.clone(), e))?;
 	}
 
 	Ok(dest.clone())
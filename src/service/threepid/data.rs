@@ -0,0 +1,31 @@
+use ruma::{
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	OwnedUserId, UserId,
+};
+
+use super::EmailSession;
+use crate::Result;
+
+pub(crate) trait Data: Send + Sync {
+	/// Returns the validation session started with this client secret for
+	/// this email address.
+	fn email_session(&self, client_secret: &str, email: &str) -> Result<Option<EmailSession>>;
+
+	/// Looks up a validation session by its session id and client secret.
+	fn find_email_session(&self, sid: &str, client_secret: &str) -> Result<Option<EmailSession>>;
+
+	/// Creates or replaces a validation session.
+	fn set_email_session(&self, session: &EmailSession) -> Result<()>;
+
+	/// Binds a 3PID to the user. The 3PID must not be bound to another user.
+	fn add_threepid(&self, user_id: &UserId, threepid: &ThirdPartyIdentifier) -> Result<()>;
+
+	/// Unbinds a 3PID from the user, returning whether it was bound.
+	fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<bool>;
+
+	/// Returns all 3PIDs bound to the user.
+	fn threepids<'a>(&'a self, user_id: &UserId) -> Box<dyn Iterator<Item = Result<ThirdPartyIdentifier>> + 'a>;
+
+	/// Returns the user this 3PID is bound to.
+	fn find_user_by_threepid(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>>;
+}
@@ -0,0 +1,223 @@
+mod data;
+
+pub(crate) use data::Data;
+use ruma::{
+	api::client::error::ErrorKind,
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, MilliSecondsSinceUnixEpoch, OwnedSessionId, OwnedUserId, SessionId, UInt, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{services, utils, Error, Result};
+
+/// Length of the session ids handed out for email validation
+const SESSION_ID_LENGTH: usize = 32;
+
+/// Length of the tokens sent out in validation emails
+const VALIDATION_TOKEN_LENGTH: usize = 32;
+
+/// An email validation session, as started by one of the `requestToken`
+/// endpoints.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct EmailSession {
+	pub(crate) sid: String,
+	pub(crate) client_secret: String,
+	pub(crate) email: String,
+	pub(crate) token: String,
+	pub(crate) send_attempt: u64,
+	pub(crate) expires_at: u64,
+	pub(crate) validated_at: Option<u64>,
+}
+
+/// What a validation email is sent for, used to word the email.
+#[derive(Clone, Copy)]
+pub(crate) enum EmailPurpose {
+	Registration,
+	AddThreepid,
+	PasswordReset,
+}
+
+pub(crate) struct Service {
+	pub(crate) db: &'static dyn Data,
+}
+
+impl Service {
+	/// Lowercases and checks an email address so that lookups are
+	/// consistent.
+	pub(crate) fn normalize_email(&self, email: &str) -> Result<String> {
+		let email = email.trim().to_lowercase();
+
+		email
+			.parse::<lettre::Address>()
+			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid email address."))?;
+
+		Ok(email)
+	}
+
+	/// Starts (or continues) an email validation session and sends the
+	/// validation email. Repeated requests with the same client secret, email
+	/// and send attempt do not send another email.
+	pub(crate) async fn request_email_validation(
+		&self, client_secret: &ClientSecret, email: &str, send_attempt: UInt, purpose: EmailPurpose,
+	) -> Result<OwnedSessionId> {
+		let Some(mailer) = services().globals.mailer.as_ref() else {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidMediumNotSupported,
+				"Email is not configured on this server.",
+			));
+		};
+
+		let now = utils::millis_since_unix_epoch();
+		let send_attempt = u64::from(send_attempt);
+
+		let mut session = match self.db.email_session(client_secret.as_str(), email)? {
+			Some(session) if session.expires_at > now => {
+				if session.send_attempt >= send_attempt {
+					return session_id(&session.sid);
+				}
+				session
+			},
+			_ => EmailSession {
+				sid: utils::random_string(SESSION_ID_LENGTH),
+				client_secret: client_secret.to_string(),
+				email: email.to_owned(),
+				token: utils::random_string(VALIDATION_TOKEN_LENGTH),
+				send_attempt,
+				expires_at: now.saturating_add(
+					services()
+						.globals
+						.config
+						.email_validation_token_ttl
+						.saturating_mul(1000),
+				),
+				validated_at: None,
+			},
+		};
+		session.send_attempt = send_attempt;
+
+		let (subject, action) = match purpose {
+			EmailPurpose::Registration => ("Confirm your email address", "register an account"),
+			EmailPurpose::AddThreepid => ("Confirm your email address", "add this email address to your account"),
+			EmailPurpose::PasswordReset => ("Reset your password", "reset your password"),
+		};
+		let server_name = services().globals.server_name();
+		let body = format!(
+			"A request was made to {action} on {server_name}.\n\nTo confirm, open the following \
+			 link:\n\n{}?sid={}&client_secret={}&token={}\n\nIf you did not make this request, you can ignore this \
+			 email.\n",
+			submit_url(),
+			session.sid,
+			session.client_secret,
+			session.token,
+		);
+
+		mailer
+			.send(email, &format!("{subject} on {server_name}"), body)
+			.await?;
+
+		self.db.set_email_session(&session)?;
+		info!("Sent email validation token for session {}", session.sid);
+
+		session_id(&session.sid)
+	}
+
+	/// Marks the session as validated if the token matches.
+	pub(crate) fn submit_token(&self, sid: &str, client_secret: &str, token: &str) -> Result<()> {
+		let Some(mut session) = self.db.find_email_session(sid, client_secret)? else {
+			return Err(Error::BadRequest(ErrorKind::ThreepidAuthFailed, "Unknown validation session."));
+		};
+
+		if session.expires_at <= utils::millis_since_unix_epoch() {
+			return Err(Error::BadRequest(
+				ErrorKind::ThreepidAuthFailed,
+				"Validation session has expired.",
+			));
+		}
+
+		if session.token != token {
+			return Err(Error::BadRequest(ErrorKind::ThreepidAuthFailed, "Invalid validation token."));
+		}
+
+		if session.validated_at.is_none() {
+			session.validated_at = Some(utils::millis_since_unix_epoch());
+			self.db.set_email_session(&session)?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the email address and validation time of a session if it has
+	/// been validated and has not expired yet.
+	pub(crate) fn validated_email(&self, sid: &str, client_secret: &str) -> Result<Option<(String, u64)>> {
+		Ok(self
+			.db
+			.find_email_session(sid, client_secret)?
+			.filter(|session| session.expires_at > utils::millis_since_unix_epoch())
+			.and_then(|session| {
+				session
+					.validated_at
+					.map(|validated_at| (session.email, validated_at))
+			}))
+	}
+
+	/// Binds a validated email address to the user.
+	pub(crate) fn add_email(&self, user_id: &UserId, email: &str, validated_at: u64) -> Result<()> {
+		if let Some(owner) = self.db.find_user_by_threepid(&Medium::Email, email)? {
+			if owner != user_id {
+				return Err(Error::BadRequest(ErrorKind::ThreepidInUse, "Email address is already in use."));
+			}
+		}
+
+		let to_ts = |millis: u64| {
+			MilliSecondsSinceUnixEpoch(UInt::try_from(millis).expect("milliseconds since epoch fit into UInt"))
+		};
+
+		self.db.add_threepid(
+			user_id,
+			&ThirdPartyIdentifier {
+				address: email.to_owned(),
+				medium: Medium::Email,
+				validated_at: to_ts(validated_at),
+				added_at: to_ts(utils::millis_since_unix_epoch()),
+			},
+		)
+	}
+
+	pub(crate) fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> Result<bool> {
+		self.db.remove_threepid(user_id, medium, address)
+	}
+
+	pub(crate) fn threepids<'a>(&'a self, user_id: &UserId) -> impl Iterator<Item = Result<ThirdPartyIdentifier>> + 'a {
+		self.db.threepids(user_id)
+	}
+
+	pub(crate) fn find_user_by_threepid(&self, medium: &Medium, address: &str) -> Result<Option<OwnedUserId>> {
+		self.db.find_user_by_threepid(medium, address)
+	}
+
+	/// Unbinds all 3PIDs of the user, e.g. when the account is deactivated.
+	pub(crate) fn remove_all_threepids(&self, user_id: &UserId) -> Result<()> {
+		for threepid in self.threepids(user_id).collect::<Vec<_>>() {
+			let threepid = threepid?;
+			self.remove_threepid(user_id, &threepid.medium, &threepid.address)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Returns the URL of the endpoint validation tokens are submitted to. Clients
+/// may POST the token there, the link in the email GETs it.
+pub(crate) fn submit_url() -> String {
+	let base = services().globals.well_known_client().as_ref().map_or_else(
+		|| format!("https://{}", services().globals.server_name()),
+		|url| url.as_str().trim_end_matches('/').to_owned(),
+	);
+
+	format!("{base}/_conduwuit/email/submit_token")
+}
+
+fn session_id(sid: &str) -> Result<OwnedSessionId> {
+	SessionId::parse(sid).map_err(|_| Error::bad_database("Invalid session id in email validation session."))
+}
@@ -13,5 +13,11 @@ pub(crate) trait Data: Send + Sync {
 		&self, user_id: &UserId, device_id: &DeviceId, session: &str, uiaainfo: Option<&UiaaInfo>,
 	) -> Result<()>;
 
+	/// Remembers the email address and validation time of a completed
+	/// `m.login.email.identity` stage
+	fn set_uiaa_email(&self, user_id: &UserId, device_id: &DeviceId, session: &str, email: (String, u64));
+
+	fn take_uiaa_email(&self, user_id: &UserId, device_id: &DeviceId, session: &str) -> Option<(String, u64)>;
+
 	fn get_uiaa_session(&self, user_id: &UserId, device_id: &DeviceId, session: &str) -> Result<UiaaInfo>;
 }
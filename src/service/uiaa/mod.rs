@@ -5,7 +5,7 @@ pub(crate) use data::Data;
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{AuthData, AuthType, EmailIdentity, Password, UiaaInfo, UserIdentifier},
 	},
 	CanonicalJsonValue, DeviceId, UserId,
 };
@@ -40,6 +40,15 @@ impl Service {
 	pub(crate) fn try_auth(
 		&self, user_id: &UserId, device_id: &DeviceId, auth: &AuthData, uiaainfo: &UiaaInfo,
 	) -> Result<(bool, UiaaInfo)> {
+		self.try_auth_email(user_id, device_id, auth, uiaainfo)
+			.map(|(worked, uiaainfo, _)| (worked, uiaainfo))
+	}
+
+	/// Like `try_auth`, also returning the email address validated in the
+	/// session and when it was validated once the session completes
+	pub(crate) fn try_auth_email(
+		&self, user_id: &UserId, device_id: &DeviceId, auth: &AuthData, uiaainfo: &UiaaInfo,
+	) -> Result<(bool, UiaaInfo, Option<(String, u64)>)> {
 		let mut uiaainfo = auth.session().map_or_else(
 			|| Ok(uiaainfo.clone()),
 			|session| self.db.get_uiaa_session(user_id, device_id, session),
@@ -79,7 +88,7 @@ impl Service {
 							kind: ErrorKind::forbidden(),
 							message: "Invalid username or password.".to_owned(),
 						});
-						return Ok((false, uiaainfo));
+						return Ok((false, uiaainfo, None));
 					}
 				}
 
@@ -94,12 +103,36 @@ impl Service {
 						kind: ErrorKind::forbidden(),
 						message: "Invalid registration token.".to_owned(),
 					});
-					return Ok((false, uiaainfo));
+					return Ok((false, uiaainfo, None));
 				}
 			},
 			AuthData::Dummy(_) => {
 				uiaainfo.completed.push(AuthType::Dummy);
 			},
+			AuthData::EmailIdentity(EmailIdentity {
+				thirdparty_id_creds,
+				..
+			}) => {
+				if let Some(email) = services()
+					.threepid
+					.validated_email(thirdparty_id_creds.sid.as_str(), thirdparty_id_creds.client_secret.as_str())?
+				{
+					// Later requests of the session don't carry the credentials anymore
+					self.db.set_uiaa_email(
+						user_id,
+						device_id,
+						uiaainfo.session.as_ref().expect("session is always set"),
+						email,
+					);
+					uiaainfo.completed.push(AuthType::EmailIdentity);
+				} else {
+					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+						kind: ErrorKind::ThreepidAuthFailed,
+						message: "Email address has not been validated.".to_owned(),
+					});
+					return Ok((false, uiaainfo, None));
+				}
+			},
 			k => error!("type not supported: {:?}", k),
 		}
 
@@ -122,17 +155,16 @@ impl Service {
 				uiaainfo.session.as_ref().expect("session is always set"),
 				Some(&uiaainfo),
 			)?;
-			return Ok((false, uiaainfo));
+			return Ok((false, uiaainfo, None));
 		}
 
 		// UIAA was successful! Remove this session and return true
-		self.db.update_uiaa_session(
-			user_id,
-			device_id,
-			uiaainfo.session.as_ref().expect("session is always set"),
-			None,
-		)?;
-		Ok((true, uiaainfo))
+		let session = uiaainfo.session.as_ref().expect("session is always set");
+		self.db
+			.update_uiaa_session(user_id, device_id, session, None)?;
+		let email = self.db.take_uiaa_email(user_id, device_id, session);
+
+		Ok((true, uiaainfo, email))
 	}
 
 	pub(crate) fn get_uiaa_request(
@@ -0,0 +1,27 @@
+use ruma::{OwnedUserId, RoomId, UserId};
+
+use super::DirectoryEntry;
+use crate::Result;
+
+pub(crate) trait Data: Send + Sync {
+	/// Inserts or replaces the entry of the user and its search tokens
+	fn set_entry(&self, user_id: &UserId, entry: &DirectoryEntry) -> Result<()>;
+
+	fn get_entry(&self, user_id: &UserId) -> Result<Option<DirectoryEntry>>;
+
+	/// Removes the entry, its search tokens and the user's public rooms
+	fn remove_entry(&self, user_id: &UserId) -> Result<()>;
+
+	/// Users with a search token equal to `token`, or starting with it if
+	/// `prefix` is set. A user can be returned more than once.
+	fn users_with_token<'a>(&'a self, token: &str, prefix: bool) -> Box<dyn Iterator<Item = Result<OwnedUserId>> + 'a>;
+
+	fn set_in_public_room(&self, user_id: &UserId, room_id: &RoomId, public: bool) -> Result<()>;
+
+	/// Whether the user is joined to any room with a public join rule
+	fn in_public_room(&self, user_id: &UserId) -> Result<bool>;
+
+	fn is_built(&self) -> Result<bool>;
+
+	fn mark_built(&self) -> Result<()>;
+}
@@ -0,0 +1,284 @@
+mod data;
+
+use std::collections::HashSet;
+
+pub(crate) use data::Data;
+use ruma::{
+	api::client::user_directory::search_users,
+	events::{
+		room::{
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+		},
+		StateEventType,
+	},
+	OwnedMxcUri, RoomId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{services, utils::user_id::user_is_local, Result};
+
+/// What the directory knows about a user
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct DirectoryEntry {
+	pub(crate) displayname: Option<String>,
+	pub(crate) avatar_url: Option<OwnedMxcUri>,
+	/// Lowercased words of the user ID and the display name
+	pub(crate) tokens: Vec<String>,
+}
+
+pub(crate) struct Service {
+	pub(crate) db: &'static dyn Data,
+}
+
+impl Service {
+	/// Indexes the user with the given profile
+	pub(crate) fn update_user(
+		&self, user_id: &UserId, displayname: Option<String>, avatar_url: Option<OwnedMxcUri>,
+	) -> Result<()> {
+		let mut tokens = tokenize(user_id.as_str());
+		if let Some(displayname) = &displayname {
+			tokens.extend(tokenize(displayname));
+		}
+		tokens.sort_unstable();
+		tokens.dedup();
+
+		self.db.set_entry(
+			user_id,
+			&DirectoryEntry {
+				displayname,
+				avatar_url,
+				tokens,
+			},
+		)
+	}
+
+	/// Indexes a local user with their global profile
+	pub(crate) fn update_local_user(&self, user_id: &UserId) -> Result<()> {
+		self.update_user(
+			user_id,
+			services().users.displayname(user_id)?,
+			services().users.avatar_url(user_id)?,
+		)
+	}
+
+	pub(crate) fn remove_user(&self, user_id: &UserId) -> Result<()> { self.db.remove_entry(user_id) }
+
+	pub(crate) fn contains(&self, user_id: &UserId) -> Result<bool> { Ok(self.db.get_entry(user_id)?.is_some()) }
+
+	/// Keeps the directory in sync with a membership change which was already
+	/// applied to the state cache. Remote users are indexed with the profile
+	/// from their latest join event and dropped once we share no room with
+	/// them anymore.
+	pub(crate) fn update_membership(
+		&self, room_id: &RoomId, user_id: &UserId, membership: &MembershipState, displayname: Option<String>,
+		avatar_url: Option<OwnedMxcUri>,
+	) -> Result<()> {
+		match membership {
+			MembershipState::Join => {
+				if !user_is_local(user_id) {
+					self.update_user(user_id, displayname, avatar_url)?;
+				} else if !self.contains(user_id)? {
+					self.update_local_user(user_id)?;
+				}
+
+				self.db
+					.set_in_public_room(user_id, room_id, is_public_room(room_id)?)
+			},
+			MembershipState::Leave | MembershipState::Ban => {
+				self.db.set_in_public_room(user_id, room_id, false)?;
+
+				if !user_is_local(user_id)
+					&& services()
+						.rooms
+						.state_cache
+						.rooms_joined(user_id)
+						.next()
+						.is_none()
+				{
+					self.db.remove_entry(user_id)?;
+				}
+
+				Ok(())
+			},
+			_ => Ok(()),
+		}
+	}
+
+	/// Updates whether the members of the room count as being in a public room
+	/// after its join rules changed
+	pub(crate) fn update_room_visibility(&self, room_id: &RoomId, public: bool) -> Result<()> {
+		for user_id in services()
+			.rooms
+			.state_cache
+			.room_members(room_id)
+			.filter_map(Result::ok)
+		{
+			self.db.set_in_public_room(&user_id, room_id, public)?;
+		}
+
+		Ok(())
+	}
+
+	/// Whether `user_id` shows up in the search results of `sender_user`
+	pub(crate) fn is_visible(&self, sender_user: &UserId, user_id: &UserId) -> Result<bool> {
+		if sender_user == user_id
+			|| services().globals.config.user_directory_search_all_users
+			|| self.db.in_public_room(user_id)?
+		{
+			return Ok(true);
+		}
+
+		Ok(services()
+			.rooms
+			.user
+			.get_shared_rooms(vec![sender_user.to_owned(), user_id.to_owned()])?
+			.next()
+			.is_some())
+	}
+
+	/// Searches the directory for users the sender may see. Every word of the
+	/// search term has to match a word of the user ID or the display name,
+	/// either exactly or as a prefix depending on
+	/// `user_directory_prefix_search`. Returns the results and whether there
+	/// were more than `limit`.
+	pub(crate) fn search(
+		&self, sender_user: &UserId, search_term: &str, limit: usize,
+	) -> Result<(Vec<search_users::v3::User>, bool)> {
+		let prefix = services().globals.config.user_directory_prefix_search;
+		let terms = tokenize(search_term);
+
+		// The longest word usually has the fewest candidates
+		let Some(longest) = terms.iter().max_by_key(|term| term.len()) else {
+			return Ok((Vec::new(), false));
+		};
+
+		let mut seen = HashSet::new();
+		let mut users = self
+			.db
+			.users_with_token(longest, prefix)
+			.filter_map(Result::ok)
+			.filter(|user_id| seen.insert(user_id.clone()))
+			.filter_map(|user_id| {
+				let entry = self.db.get_entry(&user_id).ok()??;
+
+				let matches = terms.iter().all(|term| {
+					entry.tokens.iter().any(|token| {
+						if prefix {
+							token.starts_with(term.as_str())
+						} else {
+							token == term
+						}
+					})
+				});
+				if !matches || !self.is_visible(sender_user, &user_id).ok()? {
+					return None;
+				}
+
+				Some(search_users::v3::User {
+					user_id,
+					display_name: entry.displayname,
+					avatar_url: entry.avatar_url,
+				})
+			});
+
+		let results = users.by_ref().take(limit).collect();
+		let limited = users.next().is_some();
+
+		Ok((results, limited))
+	}
+
+	/// Builds the directory from scratch if it wasn't built yet, e.g. on
+	/// databases from before the directory existed
+	pub(crate) fn build_if_missing(&self) -> Result<()> {
+		if !self.db.is_built()? {
+			self.rebuild()?;
+		}
+
+		Ok(())
+	}
+
+	/// Reindexes all local users and all members of the rooms we are in
+	pub(crate) fn rebuild(&self) -> Result<()> {
+		info!("Building the user directory");
+
+		let mut count: usize = 0;
+		for user_id in services().users.iter().filter_map(Result::ok) {
+			// Appservice users have no password either, so they look deactivated,
+			// but unlike deactivated users they can still be in rooms
+			let active = !services().users.is_deactivated(&user_id)?
+				|| services()
+					.rooms
+					.state_cache
+					.rooms_joined(&user_id)
+					.next()
+					.is_some();
+
+			if user_is_local(&user_id) && active {
+				self.update_local_user(&user_id)?;
+				count = count.saturating_add(1);
+			}
+		}
+
+		for room_id in services().rooms.metadata.iter_ids().filter_map(Result::ok) {
+			let public = is_public_room(&room_id)?;
+			for user_id in services()
+				.rooms
+				.state_cache
+				.room_members(&room_id)
+				.filter_map(Result::ok)
+			{
+				if !user_is_local(&user_id) {
+					let content = services()
+						.rooms
+						.state_accessor
+						.room_state_get(&room_id, &StateEventType::RoomMember, user_id.as_str())?
+						.and_then(|pdu| serde_json::from_str::<RoomMemberEventContent>(pdu.content.get()).ok());
+
+					let (displayname, avatar_url) =
+						content.map_or((None, None), |content| (content.displayname, content.avatar_url));
+					self.update_user(&user_id, displayname, avatar_url)?;
+					count = count.saturating_add(1);
+				}
+
+				self.db.set_in_public_room(&user_id, &room_id, public)?;
+			}
+		}
+
+		self.db.mark_built()?;
+		debug!("Indexed {count} users in the user directory");
+
+		Ok(())
+	}
+}
+
+/// Whether the room's join rule is public
+fn is_public_room(room_id: &RoomId) -> Result<bool> {
+	Ok(services()
+		.rooms
+		.state_accessor
+		.room_state_get(room_id, &StateEventType::RoomJoinRules, "")?
+		.and_then(|event| serde_json::from_str::<RoomJoinRulesEventContent>(event.content.get()).ok())
+		.map_or(false, |content| content.join_rule == JoinRule::Public))
+}
+
+/// Splits the text into lowercased alphanumeric words
+fn tokenize(text: &str) -> Vec<String> {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(str::to_lowercase)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::tokenize;
+
+	#[test]
+	fn tokenize_user_id_and_displayname() {
+		assert_eq!(tokenize("@alice.smith:example.org"), ["alice", "smith", "example", "org"]);
+		assert_eq!(tokenize("  Ælfred  the Great!"), ["ælfred", "the", "great"]);
+		assert!(tokenize("--").is_empty());
+	}
+}
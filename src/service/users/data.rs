@@ -17,6 +17,24 @@ pub(crate) trait Data: Send + Sync {
 	/// Check if account is deactivated
 	fn is_deactivated(&self, user_id: &UserId) -> Result<bool>;
 
+	/// Check if the account was erased on deactivation
+	fn is_erased(&self, user_id: &UserId) -> Result<bool>;
+
+	/// Marks the account as erased, so its events are only served redacted
+	fn set_erased(&self, user_id: &UserId) -> Result<()>;
+
+	/// Check if the account is suspended (MSC3823)
+	fn is_suspended(&self, user_id: &UserId) -> Result<bool>;
+
+	/// Suspends or unsuspends the account
+	fn set_suspended(&self, user_id: &UserId, suspended: bool) -> Result<()>;
+
+	/// Check if the account is locked (MSC3939)
+	fn is_locked(&self, user_id: &UserId) -> Result<bool>;
+
+	/// Locks or unlocks the account
+	fn set_locked(&self, user_id: &UserId, locked: bool) -> Result<()>;
+
 	/// Returns the number of users registered on this server.
 	fn count(&self) -> Result<usize>;
 
@@ -68,9 +86,24 @@ pub(crate) trait Data: Send + Sync {
 	/// Returns an iterator over all device ids of this user.
 	fn all_device_ids<'a>(&'a self, user_id: &UserId) -> Box<dyn Iterator<Item = Result<OwnedDeviceId>> + 'a>;
 
-	/// Replaces the access token of one device.
+	/// Replaces the access token of one device. The new token does not expire
+	/// and any refresh token of the device is invalidated.
 	fn set_token(&self, user_id: &UserId, device_id: &DeviceId, token: &str) -> Result<()>;
 
+	/// Returns the timestamp (in milliseconds) at which the access token of
+	/// one device expires, or None if it never expires.
+	fn access_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Option<u64>>;
+
+	/// Sets the timestamp (in milliseconds) at which the current access token
+	/// of one device expires.
+	fn set_access_token_expires_at(&self, user_id: &UserId, device_id: &DeviceId, expires_at: u64) -> Result<()>;
+
+	/// Find out which user and device a refresh token belongs to.
+	fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, OwnedDeviceId)>>;
+
+	/// Replaces the refresh token of one device.
+	fn set_refresh_token(&self, user_id: &UserId, device_id: &DeviceId, refresh_token: &str) -> Result<()>;
+
 	fn add_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, one_time_key_key: &DeviceKeyId,
 		one_time_key_value: &Raw<OneTimeKey>,
@@ -3,6 +3,7 @@ use std::{
 	collections::{BTreeMap, BTreeSet},
 	mem,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 
 pub(crate) use data::Data;
@@ -23,7 +24,7 @@ use ruma::{
 	RoomAliasId, UInt, UserId,
 };
 
-use crate::{services, Error, Result};
+use crate::{service::sending::AppserviceEdu, services, utils, utils::user_id::user_is_local, Error, Result};
 
 pub(crate) struct SlidingSyncCache {
 	lists: BTreeMap<String, SyncRequestList>,
@@ -234,6 +235,30 @@ impl Service {
 	/// Check if account is deactivated
 	pub(crate) fn is_deactivated(&self, user_id: &UserId) -> Result<bool> { self.db.is_deactivated(user_id) }
 
+	/// Check if the account was erased on deactivation
+	pub(crate) fn is_erased(&self, user_id: &UserId) -> Result<bool> { self.db.is_erased(user_id) }
+
+	/// Marks the account as erased, so its events are only served redacted
+	pub(crate) fn set_erased(&self, user_id: &UserId) -> Result<()> { self.db.set_erased(user_id) }
+
+	/// Check if the account is suspended (MSC3823). Suspended users can still
+	/// read, but not send events, join rooms or upload media.
+	pub(crate) fn is_suspended(&self, user_id: &UserId) -> Result<bool> { self.db.is_suspended(user_id) }
+
+	/// Suspends or unsuspends the account
+	pub(crate) fn set_suspended(&self, user_id: &UserId, suspended: bool) -> Result<()> {
+		self.db.set_suspended(user_id, suspended)
+	}
+
+	/// Check if the account is locked (MSC3939). All requests of locked users
+	/// except logging out fail.
+	pub(crate) fn is_locked(&self, user_id: &UserId) -> Result<bool> { self.db.is_locked(user_id) }
+
+	/// Locks or unlocks the account
+	pub(crate) fn set_locked(&self, user_id: &UserId, locked: bool) -> Result<()> {
+		self.db.set_locked(user_id, locked)
+	}
+
 	/// Check if a user is an admin
 	pub(crate) fn is_admin(&self, user_id: &UserId) -> Result<bool> {
 		let admin_room_alias_id = RoomAliasId::parse(format!("#admins:{}", services().globals.server_name()))
@@ -253,6 +278,9 @@ impl Service {
 	/// Create a new user account on this homeserver.
 	pub(crate) fn create(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
 		self.db.set_password(user_id, password)?;
+		if user_is_local(user_id) {
+			services().user_directory.update_local_user(user_id)?;
+		}
 		Ok(())
 	}
 
@@ -287,7 +315,8 @@ impl Service {
 	/// Sets a new displayname or removes it if displayname is None. You still
 	/// need to nofify all rooms of this change.
 	pub(crate) async fn set_displayname(&self, user_id: &UserId, displayname: Option<String>) -> Result<()> {
-		self.db.set_displayname(user_id, displayname)
+		self.db.set_displayname(user_id, displayname)?;
+		self.update_directory(user_id)
 	}
 
 	/// Get the avatar_url of a user.
@@ -295,7 +324,8 @@ impl Service {
 
 	/// Sets a new avatar_url or removes it if avatar_url is None.
 	pub(crate) async fn set_avatar_url(&self, user_id: &UserId, avatar_url: Option<OwnedMxcUri>) -> Result<()> {
-		self.db.set_avatar_url(user_id, avatar_url)
+		self.db.set_avatar_url(user_id, avatar_url)?;
+		self.update_directory(user_id)
 	}
 
 	/// Get the blurhash of a user.
@@ -329,6 +359,38 @@ impl Service {
 		self.db.set_token(user_id, device_id, token)
 	}
 
+	/// Replaces the access token and refresh token of one device. The access
+	/// token expires after the configured `access_token_ttl`, which is
+	/// returned.
+	pub(crate) fn set_refreshable_token(
+		&self, user_id: &UserId, device_id: &DeviceId, token: &str, refresh_token: &str,
+	) -> Result<Duration> {
+		let ttl = services().globals.config.access_token_ttl;
+		let expires_at = utils::millis_since_unix_epoch().saturating_add(ttl.saturating_mul(1000));
+
+		self.db.set_token(user_id, device_id, token)?;
+		self.db
+			.set_access_token_expires_at(user_id, device_id, expires_at)?;
+		self.db
+			.set_refresh_token(user_id, device_id, refresh_token)?;
+
+		Ok(Duration::from_secs(ttl))
+	}
+
+	/// Checks if the current access token of one device has expired. Tokens
+	/// issued without a refresh token never expire.
+	pub(crate) fn is_access_token_expired(&self, user_id: &UserId, device_id: &DeviceId) -> Result<bool> {
+		Ok(self
+			.db
+			.access_token_expires_at(user_id, device_id)?
+			.is_some_and(|expires_at| expires_at <= utils::millis_since_unix_epoch()))
+	}
+
+	/// Find out which user and device a refresh token belongs to.
+	pub(crate) fn find_from_refresh_token(&self, refresh_token: &str) -> Result<Option<(OwnedUserId, OwnedDeviceId)>> {
+		self.db.find_from_refresh_token(refresh_token)
+	}
+
 	pub(crate) fn add_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, one_time_key_key: &DeviceKeyId,
 		one_time_key_value: &Raw<OneTimeKey>,
@@ -346,7 +408,21 @@ impl Service {
 	pub(crate) fn take_one_time_key(
 		&self, user_id: &UserId, device_id: &DeviceId, key_algorithm: &DeviceKeyAlgorithm,
 	) -> Result<Option<(OwnedDeviceKeyId, Raw<OneTimeKey>)>> {
-		self.db.take_one_time_key(user_id, device_id, key_algorithm)
+		let key = self
+			.db
+			.take_one_time_key(user_id, device_id, key_algorithm)?;
+
+		// appservices managing the device's encryption have to replenish its keys
+		if key.is_some() {
+			services()
+				.sending
+				.send_edu_appservices(&AppserviceEdu::OneTimeKeysCount {
+					user_id: user_id.to_owned(),
+					device_id: device_id.to_owned(),
+				})?;
+		}
+
+		Ok(key)
 	}
 
 	pub(crate) fn count_one_time_keys(
@@ -424,8 +500,24 @@ impl Service {
 		&self, sender: &UserId, target_user_id: &UserId, target_device_id: &DeviceId, event_type: &str,
 		content: serde_json::Value,
 	) -> Result<()> {
+		let event = serde_json::json!({
+			"type": event_type,
+			"sender": sender,
+			"content": content,
+			"to_user_id": target_user_id,
+			"to_device_id": target_device_id,
+		});
+
 		self.db
-			.add_to_device_event(sender, target_user_id, target_device_id, event_type, content)
+			.add_to_device_event(sender, target_user_id, target_device_id, event_type, content)?;
+
+		services()
+			.sending
+			.send_edu_appservices(&AppserviceEdu::ToDevice {
+				user_id: target_user_id.to_owned(),
+				device_id: target_device_id.to_owned(),
+				event,
+			})
 	}
 
 	pub(crate) fn get_to_device_events(
@@ -468,7 +560,22 @@ impl Service {
 		// account is deactivated.
 		self.db.set_password(user_id, None)?;
 
-		// TODO: Unhook 3PID
+		// Free up the user's email addresses for other accounts
+		services().threepid.remove_all_threepids(user_id)?;
+
+		services().user_directory.remove_user(user_id)?;
+
+		Ok(())
+	}
+
+	/// Reindexes a local user in the user directory after a profile change.
+	/// Remote users are indexed from their membership events instead, and
+	/// deactivated users were removed from the directory.
+	fn update_directory(&self, user_id: &UserId) -> Result<()> {
+		if user_is_local(user_id) && services().user_directory.contains(user_id)? {
+			services().user_directory.update_local_user(user_id)?;
+		}
+
 		Ok(())
 	}
 
@@ -12,7 +12,7 @@ use thiserror::Error;
 use tracing::error;
 use ErrorKind::{
 	Forbidden, GuestAccessForbidden, LimitExceeded, MissingToken, NotFound, ThreepidAuthFailed, ThreepidDenied,
-	TooLarge, Unauthorized, Unknown, UnknownToken, Unrecognized, UserDeactivated, WrongRoomKeysVersion,
+	TooLarge, Unauthorized, Unknown, UnknownToken, Unrecognized, UserDeactivated, UserLocked, WrongRoomKeysVersion,
 };
 
 use crate::RumaResponse;
@@ -50,6 +50,11 @@ pub(crate) enum Error {
 	},
 	#[error("{0}")]
 	Federation(OwnedServerName, RumaError),
+	#[error("Appservice returned unsuccessful HTTP response {0}")]
+	AppserviceResponse(StatusCode, String),
+	/// An error sent to the client as is, for error codes ruma doesn't know
+	#[error("{0}")]
+	Matrix(RumaError),
 	#[error("Could not do this io: {source}")]
 	Io {
 		#[from]
@@ -93,6 +98,18 @@ impl Error {
 		Self::BadConfig(message.to_owned())
 	}
 
+	/// The error of MSC3823 for requests suspended accounts can't make, whose
+	/// error code ruma doesn't know
+	pub(crate) fn user_suspended() -> Self {
+		Self::Matrix(RumaError {
+			status_code: StatusCode::FORBIDDEN,
+			body: ErrorBody::Json(serde_json::json!({
+				"errcode": "M_USER_SUSPENDED",
+				"error": "This account has been suspended.",
+			})),
+		})
+	}
+
 	pub(crate) fn to_response(&self) -> RumaResponse<UiaaResponse> {
 		if let Self::Uiaa(uiaainfo) = self {
 			return RumaResponse(UiaaResponse::AuthResponse(uiaainfo.clone()));
@@ -107,6 +124,10 @@ impl Error {
 			return RumaResponse(UiaaResponse::MatrixError(error));
 		}
 
+		if let Self::Matrix(error) = self {
+			return RumaResponse(UiaaResponse::MatrixError(error.clone()));
+		}
+
 		let message = format!("{self}");
 		let (kind, status_code) = match self {
 			Self::BadRequest(kind, _) => (
@@ -126,7 +147,8 @@ impl Error {
 					| UnknownToken {
 						..
 					}
-					| MissingToken => StatusCode::UNAUTHORIZED,
+					| MissingToken
+					| UserLocked => StatusCode::UNAUTHORIZED,
 					NotFound | Unrecognized => StatusCode::NOT_FOUND,
 					LimitExceeded {
 						..
@@ -150,7 +172,7 @@ impl Error {
 
 	/// Returns the Matrix error code / error kind
 	pub(crate) fn error_code(&self) -> ErrorKind {
-		if let Self::Federation(_, error) = self {
+		if let Self::Federation(_, error) | Self::Matrix(error) = self {
 			return error.error_kind().unwrap_or_else(|| &Unknown).clone();
 		}
 
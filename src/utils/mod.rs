@@ -117,6 +117,39 @@ pub(crate) fn common_elements(
 	}))
 }
 
+/// Matches `value` against a glob `pattern` where `*` matches any number of
+/// characters and `?` matches exactly one.
+pub(crate) fn glob_matches(pattern: &str, value: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let value: Vec<char> = value.chars().collect();
+
+	let (mut p, mut v) = (0, 0);
+	let mut backtrack = None;
+	while v < value.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				backtrack = Some((p, v));
+				p += 1;
+			},
+			Some(&c) if c == '?' || c == value[v] => {
+				p += 1;
+				v += 1;
+			},
+			_ => match backtrack {
+				// Let the last star consume one more character
+				Some((star, consumed)) => {
+					backtrack = Some((star, consumed + 1));
+					p = star + 1;
+					v = consumed + 1;
+				},
+				None => return false,
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Fallible conversion from any value that implements `Serialize` to a
 /// `CanonicalJsonObject`.
 ///
@@ -203,3 +236,20 @@ pub(crate) fn conduwuit_version() -> String {
 		},
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::glob_matches;
+
+	#[test]
+	fn glob() {
+		assert!(glob_matches("*", ""));
+		assert!(glob_matches("*.example.org", "evil.example.org"));
+		assert!(!glob_matches("*.example.org", "example.org"));
+		assert!(glob_matches("@spam*:example.org", "@spammer:example.org"));
+		assert!(glob_matches("@?:example.org", "@a:example.org"));
+		assert!(!glob_matches("@?:example.org", "@ab:example.org"));
+		assert!(glob_matches("a*b*c", "aXbYbZc"));
+		assert!(!glob_matches("a*b*c", "aXbYbZ"));
+	}
+}